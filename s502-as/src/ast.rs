@@ -71,7 +71,7 @@ pub struct Include {
 pub type Program = Vec<Action>;
 
 /// An instruction for the code generator perform.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Action {
     /// The index into the source where a line starts.
     /// This is used in combination with LineEnd to
@@ -92,7 +92,7 @@ pub enum Action {
 }
 
 /// A label that appears at the beginning of a line.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Label {
     Top(TopLabel),
     /// The first String is the optional explicit parent,
@@ -101,10 +101,12 @@ pub enum Label {
 }
 
 /// Top level label of the line.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct TopLabel {
     pub name: String,
     pub visibility: Visibility,
+    /// The address given with `=`, otherwise the label is where the code is.
+    pub address: Option<Spanned<u16>>,
 }
 
 /// A toplevel label can be exposed to other objects or kept
@@ -116,7 +118,7 @@ pub enum Visibility {
 }
 
 /// A directive or CPU instruction.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Instruction {
     /// What the assembler or CPU should do.
     pub mnemonic: Spanned<Mnemonic>,
@@ -183,13 +185,34 @@ pub enum Mnemonic {
     Txa,
     Txs,
     Tya,
+    // Only on the 65C02.
+    Bra,
+    Phx,
+    Phy,
+    Plx,
+    Ply,
+    Stz,
     Dfb,
     Dfw,
     Equ,
     Hlt,
+    Inb,
     Inl,
     Org,
     Sct,
+    Sctdef,
+    Ds,
+    Opcode,
+    Align,
+    Rept,
+    Endr,
+    Dfs,
+    If,
+    Else,
+    Endif,
+    Datestamp,
+    Fillbyte,
+    Entry,
 }
 
 impl Mnemonic {
@@ -220,6 +243,34 @@ impl Mnemonic {
             || self == &Mnemonic::Txa
             || self == &Mnemonic::Txs
             || self == &Mnemonic::Tya
+            || self == &Mnemonic::Phx
+            || self == &Mnemonic::Phy
+            || self == &Mnemonic::Plx
+            || self == &Mnemonic::Ply
+    }
+
+    /// Whether the instruction only exists on the 65C02.
+    pub fn is_65c02(&self) -> bool {
+        self == &Mnemonic::Bra
+            || self == &Mnemonic::Phx
+            || self == &Mnemonic::Phy
+            || self == &Mnemonic::Plx
+            || self == &Mnemonic::Ply
+            || self == &Mnemonic::Stz
+    }
+
+    /// Whether the instruction writes to the address in its operand.
+    pub fn is_write(&self) -> bool {
+        self == &Mnemonic::Sta
+            || self == &Mnemonic::Stx
+            || self == &Mnemonic::Sty
+            || self == &Mnemonic::Stz
+            || self == &Mnemonic::Asl
+            || self == &Mnemonic::Lsr
+            || self == &Mnemonic::Rol
+            || self == &Mnemonic::Ror
+            || self == &Mnemonic::Inc
+            || self == &Mnemonic::Dec
     }
 
     pub fn is_branch(&self) -> bool {
@@ -231,6 +282,7 @@ impl Mnemonic {
             || self == &Mnemonic::Bpl
             || self == &Mnemonic::Bvc
             || self == &Mnemonic::Bvs
+            || self == &Mnemonic::Bra
     }
 }
 
@@ -241,7 +293,7 @@ impl fmt::Display for Mnemonic {
 }
 
 /// The parsed instruction operand.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Operand {
     pub mode: OperandMode,
     pub modifier: Option<Spanned<Modifier>>,
@@ -268,6 +320,7 @@ impl Operand {
             (OperandMode::YIndexed, Value::Byte(_)) => AddressMode::ZeropageY,
             (OperandMode::YIndexed, _) => AddressMode::AbsoluteY,
             (OperandMode::Immediate, _) => AddressMode::Immediate,
+            (OperandMode::Indirect, Value::Byte(_)) => AddressMode::IndirectZeropage,
             (OperandMode::Indirect, _) => AddressMode::Indirect,
             (OperandMode::XIndirect, _) => AddressMode::XIndirect,
             (OperandMode::IndirectY, _) => AddressMode::IndirectY,
@@ -279,7 +332,7 @@ impl Operand {
 /// The exact mode cannot be parsed because references
 /// may be to bytes or words which makes the operand
 /// ambiguous.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum OperandMode {
     /// The A register is being used.
     Accumulator,
@@ -300,7 +353,10 @@ pub enum OperandMode {
     YIndexed,
     /// The operand is an immediate number.
     Immediate,
-    /// The operand dereferences an absolute address.
+    /// The operand dereferences an address. This covers
+    /// `(abs)` and `(zpg)`, but which one it is is not
+    /// known until generation when macros and labels
+    /// are resolved.
     Indirect,
     /// The operand adds X to a zeropage address
     /// without carry and dereferences the word
@@ -320,8 +376,46 @@ pub enum Modifier {
     LowByte,
 }
 
+/// An arithmetic or bitwise operator in an expression.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+/// The size that a value is explicitly coerced to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Width {
+    Byte,
+    Word,
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Width::Byte => write!(f, "byte"),
+            Width::Word => write!(f, "word"),
+        }
+    }
+}
+
+/// The size, optional fill byte, and optional name of the padding inserted by
+/// `align` or `dfs`.
+pub type Padding = (
+    Box<Spanned<Value>>,
+    Option<Box<Spanned<Value>>>,
+    Option<Spanned<String>>,
+);
+
 /// The value to be modified and used by the operand.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     /// The accumulator is the value to be used.
     /// This is only used with the Accumulator address mode.
@@ -334,6 +428,40 @@ pub enum Value {
     String(String),
     /// The value is a reference to a macro or label.
     Reference(String),
+    /// The address of an entry in a table, written `label[index]`, which is the
+    /// referenced macro or label plus the index.
+    Indexed((String, u16)),
+    /// Bytes read from a data file by the `inb` directive.
+    Data(Vec<u8>),
+    /// The size of the section with the given name, written `sizeof("name")`.
+    SectionSize(String),
+    /// The opcode of an instruction in an address mode, from the `opcode` directive.
+    Opcode((Mnemonic, AddressMode)),
+    /// The boundary, optional fill byte, and optional name for the padding of the
+    /// `align` directive, like `align 256, $ea, padding`.
+    Alignment(Padding),
+    /// The number of bytes, optional fill byte, and optional name for the padding
+    /// of the `dfs` directive, like `dfs 4, $ea, padding`.
+    Fill(Padding),
+    /// Two values with an operator between them, like `base+3`. Expressions are
+    /// evaluated once macros are known.
+    Expr((Box<Spanned<Value>>, Operator, Box<Spanned<Value>>)),
+    /// The bitwise complement of a value in an expression, like `~mask`.
+    Not(Box<Spanned<Value>>),
+    /// The values of a `dfb` or `dfw` directive that defines more than one, like
+    /// `dfb $01, "hi", <label`.
+    List(Vec<Spanned<Operand>>),
+    /// The number of copies of a `rept` block and the lines in it up to `endr`.
+    Repeat((Box<Spanned<Value>>, Program)),
+    /// The condition of an `if` block, the lines after it up to `else` or `endif`,
+    /// and the lines from `else` up to `endif`.
+    Conditional((Box<Spanned<Value>>, Program, Program)),
+    /// Which copy of a `rept` block is being assembled, starting at 0, written `\@`.
+    RepeatCounter,
+    /// The address of the current instruction, written `$`.
+    ProgramCounter,
+    /// A literal or macro forced to a byte or word, like `byte(FOO)`.
+    Coerced((Width, Box<Spanned<Value>>)),
     /// An included program.
     Include((String, Program)),
 }
@@ -361,6 +489,8 @@ pub enum AddressMode {
     XIndirect,
     /// Indirect, Y-indexed.
     IndirectY,
+    /// Zeropage indirect.
+    IndirectZeropage,
     /// Zeropage.
     Zeropage,
     /// Zeropage, X-indexed.
@@ -370,6 +500,25 @@ pub enum AddressMode {
 }
 
 impl AddressMode {
+    /// The names of address modes as written after the `opcode` directive.
+    pub const NAMES: [(&'static str, AddressMode); 14] = [
+        ("accumulator", AddressMode::Accumulator),
+        ("absolute", AddressMode::Absolute),
+        ("absolutex", AddressMode::AbsoluteX),
+        ("absolutey", AddressMode::AbsoluteY),
+        ("immediate", AddressMode::Immediate),
+        ("implied", AddressMode::Implied),
+        ("indirect", AddressMode::Indirect),
+        ("xindirect", AddressMode::XIndirect),
+        ("indirecty", AddressMode::IndirectY),
+        ("indirectzeropage", AddressMode::IndirectZeropage),
+        ("zeropage", AddressMode::Zeropage),
+        ("zeropagex", AddressMode::ZeropageX),
+        ("zeropagey", AddressMode::ZeropageY),
+        // Branches use the zeropage entry for their offset.
+        ("relative", AddressMode::Zeropage),
+    ];
+
     /// Get the string representation of AddressMode. This is used instead of Display
     /// because zeropage and relative have identicla syntax, and which it is depends
     /// on the mnemonic.
@@ -388,6 +537,7 @@ impl AddressMode {
                     AddressMode::Indirect => "Indirect",
                     AddressMode::XIndirect => "X-indexed, indirect",
                     AddressMode::IndirectY => "Indirect, Y-indexed",
+                    AddressMode::IndirectZeropage => "Zeropage indirect",
                     AddressMode::Zeropage => "Zeropage",
                     AddressMode::ZeropageX => "Zeropage, X-indexed",
                     AddressMode::ZeropageY => "Zeropage, Y-indexed",
@@ -397,198 +547,319 @@ impl AddressMode {
     }
 }
 
+/// The processor to assemble for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cpu {
+    /// The original NMOS 6502.
+    Nmos6502,
+    /// The CMOS 65C02, which adds instructions and the `(zp)` address mode.
+    Cmos65c02,
+}
+
+impl Cpu {
+    /// The opcode table for the processor's instructions.
+    pub fn opcodes(self) -> &'static EnumMap<Mnemonic, EnumMap<AddressMode, Option<u8>>> {
+        match self {
+            Cpu::Nmos6502 => &OPCODES,
+            Cpu::Cmos65c02 => &CMOS_OPCODES,
+        }
+    }
+
+    /// The name of the processor as it's usually written.
+    pub fn name(self) -> &'static str {
+        match self {
+            Cpu::Nmos6502 => "6502",
+            Cpu::Cmos65c02 => "65C02",
+        }
+    }
+
+    /// The processor given by name to `--cpu` or a `cpu` pragma.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "6502" => Some(Cpu::Nmos6502),
+            "65c02" => Some(Cpu::Cmos65c02),
+            _ => None,
+        }
+    }
+}
+
 lazy_static! {
 /// Lookup opcode based on mnemonic and address mode. None Indicates an invalid combination.
 pub static ref OPCODES: EnumMap<Mnemonic, EnumMap<AddressMode, Option<u8>>> = enum_map! {
     Adc => enum_map! {Accumulator => None,       Absolute => Some(0x6d), AbsoluteX => Some(0x7d), AbsoluteY => Some(0x79),
                       Immediate   => Some(0x69), Implied  => None,       Indirect  => None,       XIndirect => Some(0x61),
-                      IndirectY   => Some(0x71), Zeropage => Some(0x65), ZeropageX => Some(0x75), ZeropageY => None},
+                      IndirectY   => Some(0x71), Zeropage => Some(0x65), ZeropageX => Some(0x75), ZeropageY => None, IndirectZeropage => None},
     And => enum_map! {Accumulator => None,       Absolute => Some(0x2d), AbsoluteX => Some(0x3d), AbsoluteY => Some(0x39),
                       Immediate   => Some(0x29), Implied  => None,       Indirect  => None,       XIndirect => Some(0x21),
-                      IndirectY   => Some(0x31), Zeropage => Some(0x25), ZeropageX => Some(0x35), ZeropageY => None},
+                      IndirectY   => Some(0x31), Zeropage => Some(0x25), ZeropageX => Some(0x35), ZeropageY => None, IndirectZeropage => None},
     Asl => enum_map! {Accumulator => Some(0x0a), Absolute => Some(0x0e), AbsoluteX => Some(0x1e), AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x06), ZeropageX => Some(0x16), ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x06), ZeropageX => Some(0x16), ZeropageY => None, IndirectZeropage => None},
     Bcc => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x90), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x90), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Bcs => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0xb0), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0xb0), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Beq => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0xf0), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0xf0), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Bit => enum_map! {Accumulator => None,       Absolute => Some(0x2c), AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x24), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x24), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Bmi => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x30), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x30), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Bne => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0xd0), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0xd0), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Bpl => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x10), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x10), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Brk => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x00), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Bvc => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x50), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x50), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Bvs => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x70), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x70), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Clc => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x18), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Cld => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xd8), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Cli => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x58), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Clv => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xb8), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Cmp => enum_map! {Accumulator => None,       Absolute => Some(0xcd), AbsoluteX => Some(0xdd), AbsoluteY => Some(0xd9),
                       Immediate   => Some(0xc9), Implied  => None,       Indirect  => None,       XIndirect => Some(0xc1),
-                      IndirectY   => Some(0xd1), Zeropage => Some(0xc5), ZeropageX => Some(0xd5), ZeropageY => None},
+                      IndirectY   => Some(0xd1), Zeropage => Some(0xc5), ZeropageX => Some(0xd5), ZeropageY => None, IndirectZeropage => None},
     Cpx => enum_map! {Accumulator => None,       Absolute => Some(0xec), AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => Some(0xe0), Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0xe4), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0xe4), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Cpy => enum_map! {Accumulator => None,       Absolute => Some(0xcc), AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => Some(0xc0), Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0xc4), ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0xc4), ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Dec => enum_map! {Accumulator => None,       Absolute => Some(0xce), AbsoluteX => Some(0xde), AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0xc6), ZeropageX => Some(0xd6), ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0xc6), ZeropageX => Some(0xd6), ZeropageY => None, IndirectZeropage => None},
     Dex => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xca), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Dey => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x88), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Eor => enum_map! {Accumulator => None,       Absolute => Some(0x4d), AbsoluteX => Some(0x5d), AbsoluteY => Some(0x59),
                       Immediate   => Some(0x49), Implied  => None,       Indirect  => None,       XIndirect => Some(0x41),
-                      IndirectY   => Some(0x51), Zeropage => Some(0x45), ZeropageX => Some(0x55), ZeropageY => None},
+                      IndirectY   => Some(0x51), Zeropage => Some(0x45), ZeropageX => Some(0x55), ZeropageY => None, IndirectZeropage => None},
     Inc => enum_map! {Accumulator => None,       Absolute => Some(0xee), AbsoluteX => Some(0xfe), AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0xe6), ZeropageX => Some(0xf6), ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0xe6), ZeropageX => Some(0xf6), ZeropageY => None, IndirectZeropage => None},
     Inx => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xe8), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Iny => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xc8), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Jmp => enum_map! {Accumulator => None,       Absolute => Some(0x4c), AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => Some(0x6c), XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Jsr => enum_map! {Accumulator => None,       Absolute => Some(0x20), AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Lda => enum_map! {Accumulator => None,       Absolute => Some(0xad), AbsoluteX => Some(0xbd), AbsoluteY => Some(0xb9),
                       Immediate   => Some(0xa9), Implied  => None,       Indirect  => None,       XIndirect => Some(0xa1),
-                      IndirectY   => Some(0xb1), Zeropage => Some(0xa5), ZeropageX => Some(0xb5), ZeropageY => None},
+                      IndirectY   => Some(0xb1), Zeropage => Some(0xa5), ZeropageX => Some(0xb5), ZeropageY => None, IndirectZeropage => None},
     Ldx => enum_map! {Accumulator => None,       Absolute => Some(0xae), AbsoluteX => None,       AbsoluteY => Some(0xbe),
                       Immediate   => Some(0xa2), Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0xa6), ZeropageX => None,       ZeropageY => Some(0xb6)},
+                      IndirectY   => None,       Zeropage => Some(0xa6), ZeropageX => None,       ZeropageY => Some(0xb6), IndirectZeropage => None},
     Ldy => enum_map! {Accumulator => None,       Absolute => Some(0xac), AbsoluteX => Some(0xbc), AbsoluteY => None,
                       Immediate   => Some(0xa0), Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0xa4), ZeropageX => Some(0xb4), ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0xa4), ZeropageX => Some(0xb4), ZeropageY => None, IndirectZeropage => None},
     Lsr => enum_map! {Accumulator => Some(0x4a), Absolute => Some(0x4e), AbsoluteX => Some(0x5e), AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x46), ZeropageX => Some(0x56), ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x46), ZeropageX => Some(0x56), ZeropageY => None, IndirectZeropage => None},
     Nop => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xea), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Ora => enum_map! {Accumulator => None,       Absolute => Some(0x0d), AbsoluteX => Some(0x1d), AbsoluteY => Some(0x19),
                       Immediate   => Some(0x09), Implied  => None,       Indirect  => None,       XIndirect => Some(0x01),
-                      IndirectY   => Some(0x11), Zeropage => Some(0x05), ZeropageX => Some(0x15), ZeropageY => None},
+                      IndirectY   => Some(0x11), Zeropage => Some(0x05), ZeropageX => Some(0x15), ZeropageY => None, IndirectZeropage => None},
     Pha => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x48), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Php => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x08), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Pla => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x68), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Plp => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x28), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Rol => enum_map! {Accumulator => Some(0x2a), Absolute => Some(0x2e), AbsoluteX => Some(0x3e), AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x26), ZeropageX => Some(0x36), ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x26), ZeropageX => Some(0x36), ZeropageY => None, IndirectZeropage => None},
     Ror => enum_map! {Accumulator => Some(0x6a), Absolute => Some(0x6e), AbsoluteX => Some(0x7e), AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x66), ZeropageX => Some(0x76), ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x66), ZeropageX => Some(0x76), ZeropageY => None, IndirectZeropage => None},
     Rti => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x40), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Rts => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x60), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Sbc => enum_map! {Accumulator => None,       Absolute => Some(0xed), AbsoluteX => Some(0xfd), AbsoluteY => Some(0xf9),
                       Immediate   => Some(0xe9), Implied  => None,       Indirect  => None,       XIndirect => Some(0xe1),
-                      IndirectY   => Some(0xf1), Zeropage => Some(0xe5), ZeropageX => Some(0xf5), ZeropageY => None},
+                      IndirectY   => Some(0xf1), Zeropage => Some(0xe5), ZeropageX => Some(0xf5), ZeropageY => None, IndirectZeropage => None},
     Sec => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x38), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Sed => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xf8), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Sei => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x78), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Sta => enum_map! {Accumulator => None,       Absolute => Some(0x8d), AbsoluteX => Some(0x9d), AbsoluteY => Some(0x99),
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => Some(0x81),
-                      IndirectY   => Some(0x91), Zeropage => Some(0x85), ZeropageX => Some(0x95), ZeropageY => None},
+                      IndirectY   => Some(0x91), Zeropage => Some(0x85), ZeropageX => Some(0x95), ZeropageY => None, IndirectZeropage => None},
     Stx => enum_map! {Accumulator => None,       Absolute => Some(0x8e), AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x86), ZeropageX => None,       ZeropageY => Some(0x96)},
+                      IndirectY   => None,       Zeropage => Some(0x86), ZeropageX => None,       ZeropageY => Some(0x96), IndirectZeropage => None},
     Sty => enum_map! {Accumulator => None,       Absolute => Some(0x8c), AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => Some(0x84), ZeropageX => Some(0x94), ZeropageY => None},
+                      IndirectY   => None,       Zeropage => Some(0x84), ZeropageX => Some(0x94), ZeropageY => None, IndirectZeropage => None},
     Tax => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xaa), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Tay => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xa8), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Tsx => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0xba), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Txa => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x8a), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Txs => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x9a), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Tya => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x98), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Bra => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                      Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Phx => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                      Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Phy => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                      Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Plx => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                      Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Ply => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                      Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Stz => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                      Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Dfb => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Dfw => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Equ => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Hlt => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => Some(0x02), Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Inb => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                      Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Inl => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Org => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
     Sct => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
-                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None},
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Sctdef => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                         Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                         IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Ds => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                     Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                     IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Opcode => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                         Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                         IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Align => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                        Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                        IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Rept => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                       IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Endr => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                       IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Dfs => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                      Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                      IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    If => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                     Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                     IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Else => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                       Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                       IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Endif => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                        Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                        IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Datestamp => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                            Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                            IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Fillbyte => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                           Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                           IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+    Entry => enum_map! {Accumulator => None,       Absolute => None,       AbsoluteX => None,       AbsoluteY => None,
+                        Immediate   => None,       Implied  => None,       Indirect  => None,       XIndirect => None,
+                        IndirectY   => None,       Zeropage => None,       ZeropageX => None,       ZeropageY => None, IndirectZeropage => None},
+};
+
+/// The opcodes of the 65C02, which has every 6502 opcode and some more.
+pub static ref CMOS_OPCODES: EnumMap<Mnemonic, EnumMap<AddressMode, Option<u8>>> = {
+    use AddressMode::*;
+    use Mnemonic::*;
+
+    let mut opcodes = *OPCODES;
+    for (mnemonic, opcode) in [
+        (Adc, 0x72), (And, 0x32), (Cmp, 0xd2), (Eor, 0x52),
+        (Lda, 0xb2), (Ora, 0x12), (Sbc, 0xf2), (Sta, 0x92),
+    ] {
+        opcodes[mnemonic][IndirectZeropage] = Some(opcode);
+    }
+    opcodes[Bit][Immediate] = Some(0x89);
+    opcodes[Bra][Zeropage] = Some(0x80);
+    opcodes[Phx][Implied] = Some(0xda);
+    opcodes[Phy][Implied] = Some(0x5a);
+    opcodes[Plx][Implied] = Some(0xfa);
+    opcodes[Ply][Implied] = Some(0x7a);
+    opcodes[Stz][Zeropage] = Some(0x64);
+    opcodes[Stz][ZeropageX] = Some(0x74);
+    opcodes[Stz][Absolute] = Some(0x9c);
+    opcodes[Stz][AbsoluteX] = Some(0x9e);
+    opcodes
 };
 }
 
@@ -679,13 +950,13 @@ impl From<char> for AppleCharacter {
 }
 
 pub fn apple_string(
-    string: &String,
+    string: &str,
     string_location: Location,
 ) -> Result<Vec<AppleCharacter>, AssemblerError> {
     let mut mode = AppleCharacterMode::Normal;
     let mut bytes = Vec::with_capacity(string.len());
 
-    let mut characters = string.as_str().chars().enumerate().peekable();
+    let mut characters = string.chars().enumerate().peekable();
     while characters.peek().is_some() {
         let (character_index, mut character) = characters.next().unwrap();
         if character == '\\' {
@@ -0,0 +1,111 @@
+//! The equates file that names fixed addresses, like hardware registers.
+//!
+//! Each line of an equates file names one address and has the form
+//!
+//! ```text
+//! name address
+//! ```
+//!
+//! Numbers are written the same way as in assembly source and `*` starts a
+//! comment. Unlike `equ` macros, the names become labels so they are listed in
+//! symbol tables along with the program's own labels.
+
+use crate::{
+    error::AssemblerError,
+    lines::{parse_lines, LineFormat},
+    parser::lexer::{Literal, Token},
+};
+
+/// A name given to an address by an equates file.
+#[derive(Debug, PartialEq)]
+pub struct Equate {
+    /// The name that the address is referred to by.
+    pub name: String,
+    /// The address that the name stands for.
+    pub address: u16,
+}
+
+const FORMAT: LineFormat = LineFormat {
+    name: "equates file",
+    help: "Each line of an equates file is expected to be `name address`",
+    duplicate: |name| format!("`{}` is given an address more than once", name),
+    first: "First given here",
+};
+
+/// Parse an equates file, returning the equates in the order they are written.
+pub fn parse_equates(file_name: &str, source: &str) -> Result<Vec<Equate>, Vec<AssemblerError>> {
+    parse_lines(file_name, source, &FORMAT, |line| {
+        let name = match line.next() {
+            Some((Token::Ident(name), _)) => name,
+            Some((token, span)) => return Err(line.unexpected(token, span, "a name")),
+            None => unreachable!("Empty lines are skipped"),
+        };
+
+        let address = match line.next() {
+            Some((Token::Literal(Literal::Byte(byte)), _)) => byte as u16,
+            Some((Token::Literal(Literal::Word(word)), _)) => word,
+            Some((token, span)) => return Err(line.unexpected(token, span, "an address")),
+            None => {
+                return Err(AssemblerError {
+                    message: format!("Missing address for `{}`", name),
+                    labels: vec![(line.location.clone(), None)],
+                    help: None,
+                })
+            }
+        };
+
+        Ok((name.clone(), Equate { name, address }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_equates() {
+        let equates = parse_equates(
+            "apple2.equ",
+            "* Soft switches.\nKBD $c000\n\nKBDSTRB $c010 * Clears the key.\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            equates,
+            vec![
+                Equate {
+                    name: "KBD".to_string(),
+                    address: 0xc000,
+                },
+                Equate {
+                    name: "KBDSTRB".to_string(),
+                    address: 0xc010,
+                },
+            ]
+        );
+    }
+
+    /// Each malformed line is reported at the token that is wrong.
+    #[test]
+    fn test_parse_equates_errors() {
+        let errors = parse_equates(
+            "apple2.equ",
+            "$c000 KBD\nKBD\nKBD $c000 $1\nKBD $c000\nKBD $c010\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[0].message, "Expected a name in equates file");
+        assert_eq!(errors[0].labels[0].0.span, 0..5);
+        assert_eq!(errors[1].message, "Missing address for `KBD`");
+        assert_eq!(
+            errors[2].message,
+            "Expected the end of the line in equates file"
+        );
+        assert_eq!(
+            errors[3].message,
+            "`KBD` is given an address more than once"
+        );
+        assert_eq!(errors[3].labels[1].0.span, 27..36);
+    }
+}
@@ -1,11 +1,16 @@
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use codespan_reporting::{
-    diagnostic::{Diagnostic, Label},
+    diagnostic::{Diagnostic, Label, Severity},
     files::SimpleFiles,
     term::{
         emit,
-        termcolor::{ColorChoice, StandardStream},
+        termcolor::{Buffer, BufferWriter, ColorChoice, StandardStream},
         Config,
     },
 };
@@ -24,14 +29,317 @@ pub struct AssemblerError {
     pub help: Option<String>,
 }
 
+/// A problem that doesn't stop assembly.
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    /// The kind of warning, used to turn it off or make it an error.
+    pub category: WarningCategory,
+    /// What the problem is and where it is.
+    pub error: AssemblerError,
+}
+
+/// The kinds of warnings that can be controlled with `-W`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WarningCategory {
+    /// A section is declared but nothing is put in it.
+    EmptySection,
+    /// An instruction undoes or repeats the one before it.
+    Redundant,
+    /// `jmp ($xxff)` reads its target across a page.
+    JmpIndirectBug,
+    /// A line is indented with the character `--indent` doesn't allow.
+    Indent,
+    /// A macro is defined but never referenced.
+    UnusedMacro,
+    /// Code is assembled over bytes that were already assembled.
+    Overwrite,
+    /// An instruction writes to an address in a range declared as ROM.
+    RomWrite,
+    /// A label's address is used as an immediate value, like `lda #screen`.
+    ImmediateAddress,
+    /// Two labels have names that only differ in case, like `Loop` and `loop`.
+    LabelCase,
+    /// An immediate expression doesn't fit in a byte, so only its low byte is used.
+    Overflow,
+}
+
+impl WarningCategory {
+    pub const ALL: [WarningCategory; 10] = [
+        WarningCategory::EmptySection,
+        WarningCategory::Redundant,
+        WarningCategory::JmpIndirectBug,
+        WarningCategory::Indent,
+        WarningCategory::UnusedMacro,
+        WarningCategory::Overwrite,
+        WarningCategory::RomWrite,
+        WarningCategory::ImmediateAddress,
+        WarningCategory::LabelCase,
+        WarningCategory::Overflow,
+    ];
+
+    /// The name used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningCategory::EmptySection => "empty-section",
+            WarningCategory::Redundant => "redundant",
+            WarningCategory::JmpIndirectBug => "jmp-indirect-bug",
+            WarningCategory::Indent => "indent",
+            WarningCategory::UnusedMacro => "unused-macro",
+            WarningCategory::Overwrite => "overwrite",
+            WarningCategory::RomWrite => "rom-write",
+            WarningCategory::ImmediateAddress => "immediate-address",
+            WarningCategory::LabelCase => "label-case",
+            WarningCategory::Overflow => "overflow",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.name() == name)
+    }
+}
+
+/// How each category of warning is reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WarningLevel {
+    Off,
+    Warn,
+    Error,
+}
+
+/// The level of every warning category, set by the `-W` options.
+#[derive(Debug)]
+pub struct WarningSettings {
+    levels: HashMap<WarningCategory, WarningLevel>,
+}
+
+impl Default for WarningSettings {
+    fn default() -> Self {
+        Self {
+            levels: WarningCategory::ALL
+                .into_iter()
+                .map(|category| {
+                    // Lints about style are opt in.
+                    let level = if matches!(
+                        category,
+                        WarningCategory::Redundant | WarningCategory::UnusedMacro
+                    ) {
+                        WarningLevel::Off
+                    } else {
+                        WarningLevel::Warn
+                    };
+                    (category, level)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl WarningSettings {
+    /// Apply the value of a `-W` option: `<category>` enables a category,
+    /// `no-<category>` disables it, `error=<category>` makes it an error, and
+    /// `error` makes every enabled category an error.
+    pub fn apply(&mut self, option: &str) -> Result<(), String> {
+        if option == "error" {
+            for level in self.levels.values_mut() {
+                if *level == WarningLevel::Warn {
+                    *level = WarningLevel::Error;
+                }
+            }
+            return Ok(());
+        }
+
+        let (name, level) = if let Some(name) = option.strip_prefix("no-") {
+            (name, WarningLevel::Off)
+        } else if let Some(name) = option.strip_prefix("error=") {
+            (name, WarningLevel::Error)
+        } else {
+            (option, WarningLevel::Warn)
+        };
+
+        let category = WarningCategory::from_name(name).ok_or_else(|| {
+            format!(
+                "Unknown warning category `{}`, expected one of {}",
+                name,
+                WarningCategory::ALL
+                    .iter()
+                    .map(|category| format!("`{}`", category.name()))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+        })?;
+        self.levels.insert(category, level);
+
+        Ok(())
+    }
+
+    /// Enable every category that is off, for `--pedantic`.
+    pub fn enable_all(&mut self) {
+        for level in self.levels.values_mut() {
+            if *level == WarningLevel::Off {
+                *level = WarningLevel::Warn;
+            }
+        }
+    }
+
+    pub fn level(&self, category: WarningCategory) -> WarningLevel {
+        self.levels[&category]
+    }
+}
+
+/// How errors and warnings are written to stderr.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageFormat {
+    /// Excerpts of the source pointing at the problem, for people to read.
+    Human,
+    /// One JSON object per line, for editors and other tools to read.
+    Json,
+}
+
+/// Whether diagnostics are written as JSON, set once from `--message-format`
+/// before any source is assembled.
+static JSON_MESSAGES: AtomicBool = AtomicBool::new(false);
+
+pub fn set_message_format(format: MessageFormat) {
+    JSON_MESSAGES.store(format == MessageFormat::Json, Ordering::Relaxed);
+}
+
+thread_local! {
+    /// Diagnostics held back on this thread by `buffered` until its source is done.
+    static HELD_DIAGNOSTICS: RefCell<Option<Buffer>> = const { RefCell::new(None) };
+}
+
+/// Hold the diagnostics reported on this thread while `assemble` runs, then write
+/// them all at once. Sources are assembled on separate threads, so this keeps one
+/// source's errors and warnings from being interleaved with another's.
+pub fn buffered<T>(assemble: impl FnOnce() -> T) -> T {
+    let writer = BufferWriter::stderr(ColorChoice::Always);
+    HELD_DIAGNOSTICS.with(|held| *held.borrow_mut() = Some(writer.buffer()));
+    let result = assemble();
+    if let Some(buffer) = HELD_DIAGNOSTICS.with(|held| held.borrow_mut().take()) {
+        let _ = writer.print(&buffer);
+    }
+    result
+}
+
 pub fn report_errors(
     errors: Vec<AssemblerError>,
     id_table: &HashMap<String, usize>,
     files: &SimpleFiles<String, String>,
 ) {
+    report(Severity::Error, errors, id_table, files);
+}
+
+/// Report an error that isn't in any source, like an invalid option or a file that
+/// couldn't be read, in the same format as the others.
+pub fn report_error(message: String, help: Option<String>) {
+    let error = AssemblerError {
+        message,
+        labels: vec![],
+        help,
+    };
+    report(
+        Severity::Error,
+        vec![error],
+        &HashMap::new(),
+        &SimpleFiles::new(),
+    );
+}
+
+/// Report how many sources failed to assemble after all of them were tried. It's
+/// left out of JSON output, which only has diagnostics.
+pub fn report_summary(failed_count: usize, succeeded_count: usize) {
+    if JSON_MESSAGES.load(Ordering::Relaxed) {
+        return;
+    }
+
     let stderr_writer = StandardStream::stderr(ColorChoice::Always);
+    let _ = writeln!(
+        stderr_writer.lock(),
+        "{} {} failed, {} succeeded",
+        failed_count,
+        if failed_count == 1 { "file" } else { "files" },
+        succeeded_count
+    );
+}
+
+/// Report problems that don't stop assembly at the level their category is set to.
+/// Returns whether any of them were made errors.
+pub fn report_warnings(
+    warnings: Vec<Warning>,
+    settings: &WarningSettings,
+    id_table: &HashMap<String, usize>,
+    files: &SimpleFiles<String, String>,
+) -> bool {
+    let mut reported_warnings = vec![];
+    let mut reported_errors = vec![];
+
+    for mut warning in warnings {
+        let level = settings.level(warning.category);
+        // Show the option that controls it, like other assemblers and compilers do.
+        warning.error.message = match level {
+            WarningLevel::Off => continue,
+            WarningLevel::Warn => {
+                format!("{} [-W{}]", warning.error.message, warning.category.name())
+            }
+            WarningLevel::Error => format!(
+                "{} [-Werror={}]",
+                warning.error.message,
+                warning.category.name()
+            ),
+        };
+
+        if level == WarningLevel::Error {
+            reported_errors.push(warning.error);
+        } else {
+            reported_warnings.push(warning.error);
+        }
+    }
+
+    let promoted = !reported_errors.is_empty();
+    report(Severity::Warning, reported_warnings, id_table, files);
+    report(Severity::Error, reported_errors, id_table, files);
+    promoted
+}
+
+fn report(
+    severity: Severity,
+    errors: Vec<AssemblerError>,
+    id_table: &HashMap<String, usize>,
+    files: &SimpleFiles<String, String>,
+) {
+    let writer = BufferWriter::stderr(ColorChoice::Always);
+    let mut terminal = writer.buffer();
+    write_diagnostics(&mut terminal, severity, errors, id_table, files);
+
+    // Write them all at once, or after the rest of the source's if they're held.
+    HELD_DIAGNOSTICS.with(|held| match held.borrow_mut().as_mut() {
+        Some(held) => {
+            let _ = held.write_all(terminal.as_slice());
+        }
+        None => {
+            let _ = writer.print(&terminal);
+        }
+    });
+}
+
+fn write_diagnostics(
+    terminal: &mut Buffer,
+    severity: Severity,
+    errors: Vec<AssemblerError>,
+    id_table: &HashMap<String, usize>,
+    files: &SimpleFiles<String, String>,
+) {
     let codespan_config = Config::default();
 
+    if JSON_MESSAGES.load(Ordering::Relaxed) {
+        for error in errors {
+            let _ = writeln!(terminal, "{}", json_diagnostic(severity, &error));
+        }
+        return;
+    }
+
     for error in errors {
         // Create labels from notes.
         let mut labels = vec![];
@@ -56,27 +364,76 @@ pub fn report_errors(
         });
 
         // Then create diagnostic message from it.
-        let diagnostic = Diagnostic::error()
+        let diagnostic = Diagnostic::new(severity)
             .with_message(error.message)
             .with_labels(labels);
 
-        let _ = emit(
-            &mut stderr_writer.lock(),
-            &codespan_config,
-            files,
-            &diagnostic,
-        );
+        let _ = emit(terminal, &codespan_config, files, &diagnostic);
 
         // And create a second help diagnostic if one was given.
         if let Some(note) = error.help {
             let diagnostic = Diagnostic::help().with_message(note);
 
-            let _ = emit(
-                &mut stderr_writer.lock(),
-                &codespan_config,
-                files,
-                &diagnostic,
-            );
+            let _ = emit(terminal, &codespan_config, files, &diagnostic);
+        }
+    }
+}
+
+/// An error as a single line of JSON, like
+/// `{"severity":"error","message":"...","help":null,"labels":[{"file":"a.65a","start":0,"end":3,"label":null}]}`,
+/// where the spans are byte offsets into the file and the first label is the primary one.
+fn json_diagnostic(severity: Severity, error: &AssemblerError) -> String {
+    let severity = match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    };
+    let labels = error
+        .labels
+        .iter()
+        .map(|(location, label)| {
+            format!(
+                "{{\"file\":{},\"start\":{},\"end\":{},\"label\":{}}}",
+                json_string(&location.file_name),
+                location.span.start,
+                location.span.end,
+                label.as_deref().map_or(String::from("null"), json_string)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+
+    format!(
+        "{{\"severity\":\"{}\",\"message\":{},\"help\":{},\"labels\":[{}]}}",
+        severity,
+        json_string(&error.message),
+        error
+            .help
+            .as_deref()
+            .map_or(String::from("null"), json_string),
+        labels
+    )
+}
+
+/// A string as a quoted JSON string, escaping the characters JSON doesn't allow.
+pub fn json_string(string: &str) -> String {
+    let mut json = String::with_capacity(string.len() + 2);
+    json.push('"');
+    for character in string.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            character if (character as u32) < 0x20 => {
+                json.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => json.push(character),
         }
     }
+    json.push('"');
+    json
 }
@@ -1,40 +1,153 @@
 //! The file output for raw binary files.
 
-use std::fs;
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use super::*;
 
-/// Resolve each reference to a label.
-fn resolve_references(section: &mut Section) -> Result<(), Vec<AssemblerError>> {
-    let labels: HashMap<String, usize> = section
-        .labels
+/// The error for an output file that couldn't be written, explaining the common
+/// causes instead of only giving the system's message.
+pub(super) fn write_error(description: &str, file_name: &str, error: io::Error) -> AssemblerError {
+    match error.kind() {
+        ErrorKind::PermissionDenied => AssemblerError {
+            message: format!("Permission denied writing {} `{}`", description, file_name),
+            labels: vec![],
+            help: Some(String::from(
+                "Check that the file and its directory can be written to",
+            )),
+        },
+        ErrorKind::IsADirectory => AssemblerError {
+            message: format!("Output path `{}` is a directory", file_name),
+            labels: vec![],
+            help: Some(format!("Give a file name for the {}", description)),
+        },
+        _ => AssemblerError {
+            message: format!("Error writing {} `{}`: {}", description, file_name, error),
+            labels: vec![],
+            help: None,
+        },
+    }
+}
+
+/// Resolve each reference to a label. Sections in binary mode are placed at
+/// their actual addresses so references may be to labels in any section.
+/// The cross references give the macros to suggest for a misspelled name.
+fn resolve_references(
+    object: &mut Object,
+    cross_references: &[CrossReference],
+) -> Result<(), Vec<AssemblerError>> {
+    let labels: HashMap<String, usize> = object
         .iter()
+        .flat_map(|section| section.labels.iter())
         .map(|label| (label.name.clone(), label.offset))
         .collect();
+    let macros: Vec<&str> = cross_references
+        .iter()
+        .filter(|cross_reference| {
+            cross_reference.definition.is_some() && !labels.contains_key(&cross_reference.name)
+        })
+        .map(|cross_reference| cross_reference.name.as_str())
+        .collect();
+    let sizes: HashMap<String, usize> = object
+        .iter()
+        .map(|section| {
+            (
+                section.name.clone(),
+                section.highest_origin - section.lowest_origin.unwrap_or(0),
+            )
+        })
+        .collect();
 
     let mut errors = Vec::new();
 
+    for section in object.iter_mut() {
+        resolve_section_references(section, &labels, &macros, &sizes, &mut errors);
+    }
+
+    if !errors.is_empty() {
+        Err(errors)
+    } else {
+        Ok(())
+    }
+}
+
+fn resolve_section_references(
+    section: &mut Section,
+    labels: &HashMap<String, usize>,
+    macros: &[&str],
+    sizes: &HashMap<String, usize>,
+    errors: &mut Vec<AssemblerError>,
+) {
     for reference in &section.references {
-        let label_search = labels.get(&reference.name);
-        let label = if label_search.is_none() {
+        let label = if reference.section_size {
+            sizes.get(&reference.name)
+        } else {
+            labels.get(&reference.name)
+        };
+        let subtrahend = match &reference.subtrahend {
+            None => Some(0),
+            Some(subtrahend) => labels.get(subtrahend).copied(),
+        };
+        let label = if let (Some(label), Some(subtrahend)) = (label, subtrahend) {
+            // A negative addend or distance wraps around like the address does.
+            (*label as u16)
+                .wrapping_sub(subtrahend as u16)
+                .wrapping_add(reference.addend) as usize
+        } else {
+            let name = match (label, &reference.subtrahend) {
+                (Some(_), Some(subtrahend)) => subtrahend,
+                _ => &reference.name,
+            };
+            let section_missing = reference.section_size && label.is_none();
+            let help = if section_missing {
+                suggest_symbol(name, sizes.keys().map(String::as_str))
+                    .map(|section| format!("Did you mean `{}`?", section))
+            } else if macros.contains(&name.as_str()) {
+                // Macros are substituted as they're read, so one can't be used above its definition.
+                Some(format!("Define the macro `{}` before it's used", name))
+            } else {
+                suggest_symbol(
+                    name,
+                    labels
+                        .keys()
+                        .map(String::as_str)
+                        .chain(macros.iter().copied()),
+                )
+                .map(|symbol| format!("Did you mean `{}`?", symbol))
+            };
             errors.push(AssemblerError {
-                message: format!("Label `{}` being referenced does not exist", reference.name),
+                message: if section_missing {
+                    format!("Section `{}` being referenced does not exist", name)
+                } else {
+                    format!("Label `{}` being referenced does not exist", name)
+                },
                 labels: vec![(reference.location.clone(), None)],
-                help: None,
+                help,
             });
             continue;
-        } else {
-            *label_search.unwrap()
         };
 
         if reference.branch {
             // The target address is relative to the pc after reading the operand and incrementing.
             let offset = label as isize - (reference.offset as isize + 1);
-            if offset < -128 || offset > 127 {
+            if !(-128..=127).contains(&offset) {
                 errors.push(AssemblerError {
-                    message: format!("The relative target is out of range, at `{}` bytes", offset),
-                    labels: vec![(reference.location.clone(), None)],
-                    help: None,
+                    message: format!(
+                        "Branch target `{}` is out of range, at `{}` bytes",
+                        reference.name, offset
+                    ),
+                    labels: vec![(
+                        reference.location.clone(),
+                        Some(String::from("Branches reach from -128 to 127 bytes")),
+                    )],
+                    help: Some(format!(
+                        "Branch on the opposite condition over a `jmp {}` instead",
+                        reference.name
+                    )),
                 });
                 continue;
             }
@@ -57,6 +170,154 @@ fn resolve_references(section: &mut Section) -> Result<(), Vec<AssemblerError>>
             }) => section.data[reference.offset] = (label) as u8,
         }
     }
+}
+
+/// How the binary is written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// The raw bytes.
+    Binary,
+    /// A run-length encoding of the used addresses, see `encode_rle`.
+    Rle,
+    /// Intel HEX records of the bytes, see `encode_intel_hex`.
+    IntelHex,
+    /// Motorola S-records of the used addresses, see `encode_srec`.
+    Srec,
+    /// A BASIC program that pokes the used addresses, see `encode_basic_poke`.
+    BasicPoke,
+    /// All 64K of memory with the bytes at their addresses, see `create_flat_image`.
+    Flat64k,
+}
+
+impl OutputFormat {
+    /// The extension of files in the format when several formats are written at once.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Binary => "bin",
+            OutputFormat::Rle => "rle",
+            OutputFormat::IntelHex => "hex",
+            OutputFormat::Srec => "s19",
+            OutputFormat::BasicPoke => "bas",
+            OutputFormat::Flat64k => "img",
+        }
+    }
+
+    /// The name given to `--format` or a `format` pragma for the format.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Binary => "bin",
+            OutputFormat::Rle => "rle",
+            OutputFormat::IntelHex => "ihex",
+            OutputFormat::Srec => "srec",
+            OutputFormat::BasicPoke => "basic-poke",
+            OutputFormat::Flat64k => "flat64k",
+        }
+    }
+
+    /// The format given by name to `--format` or a `format` pragma.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bin" => Some(OutputFormat::Binary),
+            "rle" => Some(OutputFormat::Rle),
+            "ihex" => Some(OutputFormat::IntelHex),
+            "srec" => Some(OutputFormat::Srec),
+            "basic-poke" => Some(OutputFormat::BasicPoke),
+            "flat64k" => Some(OutputFormat::Flat64k),
+            _ => None,
+        }
+    }
+}
+
+/// Write the binary covering every section to each of the files in its format,
+/// returning the contents of each file in the same order. Sections without a
+/// layout script placement are padded with 0, and memory outside of the code in
+/// a flat image is filled with the fill byte.
+pub fn emit_binary(
+    object: &mut Object,
+    cross_references: &[CrossReference],
+    script: &[SectionPlacement],
+    outputs: &[(OutputFormat, String)],
+    fill: u8,
+) -> Result<Vec<Vec<u8>>, Vec<AssemblerError>> {
+    resolve_binary(object, cross_references)?;
+
+    let mut contents = Vec::with_capacity(outputs.len());
+    for (format, output_filename) in outputs {
+        let output = match format {
+            OutputFormat::Binary => create_image(object, script),
+            OutputFormat::Rle => create_rle(object, script)?,
+            OutputFormat::IntelHex => {
+                encode_intel_hex(image_start(object) as u16, &create_image(object, script))
+            }
+            OutputFormat::Srec => create_srec(object, output_filename),
+            OutputFormat::BasicPoke => encode_basic_poke(&used_chunks(object)),
+            OutputFormat::Flat64k => create_flat_image(object, fill),
+        };
+
+        fs::write(output_filename, &output)
+            .map_err(|error| vec![write_error("binary file", output_filename, error)])?;
+        contents.push(output);
+    }
+
+    Ok(contents)
+}
+
+/// Check that the sections don't overlap and resolve the references to labels now
+/// that every address is known.
+pub fn resolve_binary(
+    object: &mut Object,
+    cross_references: &[CrossReference],
+) -> Result<(), Vec<AssemblerError>> {
+    check_section_overlaps(object)?;
+    resolve_references(object, cross_references)
+}
+
+/// The bytes of every section of a resolved binary, without writing them to a file.
+pub fn image_bytes(object: &Object) -> Vec<u8> {
+    create_image(object, &[])
+}
+
+/// Make sure that no two sections are placed over each other, since only one of
+/// them could end up in the binary.
+fn check_section_overlaps(object: &Object) -> Result<(), Vec<AssemblerError>> {
+    let mut sections: Vec<&Section> = object
+        .iter()
+        .filter(|section| section.highest_origin > section.lowest_origin.unwrap_or(0))
+        .collect();
+    sections.sort_by_key(|section| section.lowest_origin.unwrap_or(0));
+
+    let mut errors = Vec::new();
+
+    for (index, first) in sections.iter().enumerate() {
+        for second in &sections[index + 1..] {
+            let start = second.lowest_origin.unwrap_or(0);
+            let end = first.highest_origin.min(second.highest_origin);
+            if start >= end {
+                continue;
+            }
+
+            errors.push(AssemblerError {
+                message: format!(
+                    "Sections `{}` and `{}` overlap at ${:04x}-${:04x}",
+                    first.name,
+                    second.name,
+                    start,
+                    end - 1
+                ),
+                labels: [first, second]
+                    .iter()
+                    .filter_map(|section| {
+                        section.location.clone().map(|location| {
+                            (location, Some(format!("`{}` declared here", section.name)))
+                        })
+                    })
+                    .collect(),
+                help: Some(String::from(
+                    "Place the sections at addresses that don't overlap",
+                )),
+            });
+        }
+    }
 
     if !errors.is_empty() {
         Err(errors)
@@ -65,135 +326,724 @@ fn resolve_references(section: &mut Section) -> Result<(), Vec<AssemblerError>>
     }
 }
 
-pub fn emit_binary(
-    section: &mut Section,
-    output_filename: &String,
-) -> Result<(), Vec<AssemblerError>> {
-    let _ = resolve_references(section)?;
+/// Compress the used addresses, which have to be contiguous so the stub that
+/// decompresses them only needs a single destination.
+fn create_rle(
+    object: &Object,
+    script: &[SectionPlacement],
+) -> Result<Vec<u8>, Vec<AssemblerError>> {
+    let ranges = merge_ranges(
+        object
+            .iter()
+            .flat_map(|section| section.used_ranges.iter().cloned())
+            .collect(),
+    );
 
-    fs::write(
-        output_filename,
-        &section.data[section.lowest_origin.unwrap_or(0)..section.highest_origin],
-    )
-    .map_err(|error| {
-        vec![AssemblerError {
-            message: format!("Error writing binary file `{}`: {}", output_filename, error),
-            labels: vec![],
-            help: None,
-        }]
-    })?;
+    let range = match ranges.as_slice() {
+        [] => 0..0,
+        [range] => range.clone(),
+        [first, second, ..] => {
+            return Err(vec![AssemblerError {
+                message: format!(
+                    "RLE output needs the used addresses to be contiguous, but there is a gap at ${:04x}-${:04x}",
+                    first.end,
+                    second.start - 1
+                ),
+                labels: vec![],
+                help: Some(String::from(
+                    "Fill the gap with data or assemble each part separately",
+                )),
+            }]);
+        }
+    };
 
-    Ok(())
+    let image_start = image_start(object);
+    let image = create_image(object, script);
+    Ok(encode_rle(
+        range.start as u16,
+        &image[range.start - image_start..range.end - image_start],
+    ))
+}
+
+/// Run-length encode bytes that are loaded at an address.
+///
+/// The output starts with the load address as a little endian word, followed by
+/// packets that each start with a control byte `c`:
+///
+/// * `c` = 0 ends the data.
+/// * `c` < $80 is followed by `c` bytes that are copied as they are.
+/// * `c` >= $80 is followed by one byte that is repeated `c - $7e` times, from 2
+///   up to 129 times.
+fn encode_rle(address: u16, bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![address as u8, (address >> 8) as u8];
+    let mut literals: Vec<u8> = Vec::with_capacity(0x7f);
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let run = bytes[index..]
+            .iter()
+            .take(129)
+            .take_while(|byte| **byte == bytes[index])
+            .count();
+
+        if run >= 2 {
+            if !literals.is_empty() {
+                encoded.push(literals.len() as u8);
+                encoded.append(&mut literals);
+            }
+            encoded.push((run + 0x7e) as u8);
+            encoded.push(bytes[index]);
+            index += run;
+        } else {
+            literals.push(bytes[index]);
+            if literals.len() == 0x7f {
+                encoded.push(literals.len() as u8);
+                encoded.append(&mut literals);
+            }
+            index += 1;
+        }
+    }
+
+    if !literals.is_empty() {
+        encoded.push(literals.len() as u8);
+        encoded.append(&mut literals);
+    }
+    encoded.push(0);
+
+    encoded
 }
 
+/// Write bytes that are loaded at an address as Intel HEX data records of up to
+/// 16 bytes each, followed by the end of file record.
+///
+/// Each record is a line of `:`, the byte count, the address as a big endian
+/// word, the record type, the data, and a checksum that makes the sum of the
+/// record's bytes 0, all in hex.
+fn encode_intel_hex(address: u16, bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = String::with_capacity(bytes.len() * 3);
+
+    let mut write_record = |address: u16, record_type: u8, data: &[u8]| {
+        let mut record = vec![
+            data.len() as u8,
+            (address >> 8) as u8,
+            address as u8,
+            record_type,
+        ];
+        record.extend_from_slice(data);
+        let checksum = record
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+            .wrapping_neg();
+        record.push(checksum);
+
+        encoded.push(':');
+        for byte in record {
+            encoded.push_str(&format!("{:02X}", byte));
+        }
+        encoded.push('\n');
+    };
+
+    for (index, chunk) in bytes.chunks(16).enumerate() {
+        write_record(address.wrapping_add(index as u16 * 16), 0x00, chunk);
+    }
+    write_record(0, 0x01, &[]);
+
+    encoded.into_bytes()
+}
+
+/// The S-records of the used addresses of every section, with the output file's
+/// name in the header. Addresses that nothing was written to are left out.
+fn create_srec(object: &Object, output_filename: &str) -> Vec<u8> {
+    let name = Path::new(output_filename)
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    encode_srec(&name, &used_chunks(object))
+}
+
+/// All 64K of memory with the used addresses of every section holding their bytes
+/// and every other address holding the fill byte.
+fn create_flat_image(object: &Object, fill: u8) -> Vec<u8> {
+    let mut image = vec![fill; 0x10000];
+    for (address, bytes) in used_chunks(object) {
+        image[address..address + bytes.len()].copy_from_slice(bytes);
+    }
+    image
+}
+
+/// The contiguous runs of used addresses of every section in address order,
+/// each with the address it starts at.
+fn used_chunks(object: &Object) -> Vec<(usize, &[u8])> {
+    let mut chunks: Vec<(usize, &[u8])> = object
+        .iter()
+        .flat_map(|section| {
+            merge_ranges(section.used_ranges.clone())
+                .into_iter()
+                .filter(|range| !range.is_empty())
+                .map(|range| (range.start, &section.data[range]))
+        })
+        .collect();
+    chunks.sort_by_key(|(address, _)| *address);
+    chunks
+}
+
+/// Write chunks of bytes and the addresses they are loaded at as Motorola
+/// S-records: an S0 header with the name, S1 data records of up to 16 bytes
+/// each, and an S9 record with the first address to start at.
+///
+/// Each record is a line of `S`, the record type, the number of bytes that
+/// follow, the address as a big endian word, the data, and a checksum that is
+/// the complement of the low byte of the sum of the others, all in hex. S2 and
+/// S8 records are used instead of S1 and S9 for addresses that need 3 bytes.
+fn encode_srec(name: &str, chunks: &[(usize, &[u8])]) -> Vec<u8> {
+    let mut encoded = String::new();
+
+    let mut write_record = |record_type: u8, address: usize, data: &[u8]| {
+        let address_width = if address > 0xffff { 3 } else { 2 };
+        let record_type = match (record_type, address_width) {
+            (1, 3) => 2,
+            (9, 3) => 8,
+            _ => record_type,
+        };
+
+        let mut record = vec![(address_width + data.len() + 1) as u8];
+        record.extend_from_slice(&(address as u32).to_be_bytes()[4 - address_width..]);
+        record.extend_from_slice(data);
+        let checksum = !record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        record.push(checksum);
+
+        encoded.push_str(&format!("S{}", record_type));
+        for byte in record {
+            encoded.push_str(&format!("{:02X}", byte));
+        }
+        encoded.push('\n');
+    };
+
+    write_record(0, 0, name.as_bytes());
+    for (address, bytes) in chunks {
+        for (index, chunk) in bytes.chunks(16).enumerate() {
+            write_record(1, address + index * 16, chunk);
+        }
+    }
+    write_record(9, chunks.first().map_or(0, |(address, _)| *address), &[]);
+
+    encoded.into_bytes()
+}
+
+/// Write chunks of bytes and the addresses they are loaded at as a BASIC program
+/// that pokes them into memory, for typing in or loading on the target machine.
+///
+/// Lines are numbered from 10 in steps of 10. Each chunk gets a line with a loop
+/// that reads its bytes and pokes them, like
+///
+/// ```text
+/// 10 FOR A=768 TO 771:READ B:POKE A,B:NEXT
+/// ```
+///
+/// then an `END` line follows, and the bytes of every chunk are listed in order
+/// after it in `DATA` lines of up to 16 bytes each, all in decimal.
+fn encode_basic_poke(chunks: &[(usize, &[u8])]) -> Vec<u8> {
+    let mut lines = Vec::new();
+
+    for (address, bytes) in chunks {
+        lines.push(format!(
+            "FOR A={} TO {}:READ B:POKE A,B:NEXT",
+            address,
+            address + bytes.len() - 1
+        ));
+    }
+    lines.push(String::from("END"));
+    for (_, bytes) in chunks {
+        for chunk in bytes.chunks(16) {
+            lines.push(format!(
+                "DATA {}",
+                chunk
+                    .iter()
+                    .map(|byte| byte.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ));
+        }
+    }
+
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| format!("{} {}\n", (index + 1) * 10, line))
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Sort ranges and combine the ones that are adjacent or overlap.
+pub(super) fn merge_ranges(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+/// The address of the first byte in the binary.
+fn image_start(object: &Object) -> usize {
+    object
+        .iter()
+        .filter(|section| section.highest_origin > section.lowest_origin.unwrap_or(0))
+        .map(|section| section.lowest_origin.unwrap_or(0))
+        .min()
+        .unwrap_or(0)
+}
+
+/// Lay the used part of each section out in address order.
+fn create_image(object: &Object, script: &[SectionPlacement]) -> Vec<u8> {
+    let mut sections: Vec<&Section> = object
+        .iter()
+        .filter(|section| section.highest_origin > section.lowest_origin.unwrap_or(0))
+        .collect();
+    sections.sort_by_key(|section| section.lowest_origin.unwrap_or(0));
+
+    let mut image = Vec::new();
+    let mut image_start = None;
+    let mut fill = 0;
+
+    for section in sections {
+        let start = section.lowest_origin.unwrap_or(0);
+        let image_start = *image_start.get_or_insert(start);
+
+        let (offset, end) = (start - image_start, section.highest_origin - image_start);
+
+        // Pad the gap after the previous section.
+        if image.len() < offset {
+            image.resize(offset, fill);
+        }
+        if image.len() < end {
+            image.resize(end, 0);
+        }
+        // Reserved space at the end of the section was never written and stays 0.
+        let written = section
+            .data
+            .get(start..section.highest_origin.min(section.data.len()))
+            .unwrap_or_default();
+        image[offset..offset + written.len()].copy_from_slice(written);
+
+        fill = script
+            .iter()
+            .find(|placement| placement.name == section.name)
+            .map_or(0, |placement| placement.fill);
+    }
+
+    image
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn create_listing(
-    section: &Section,
-    listings: Vec<Listing>,
+    object: &Object,
+    listings: &[Listing],
+    source_name: &str,
+    cpu: Cpu,
+    deterministic: bool,
+    code_only: bool,
+    canonical_numbers: bool,
     file_name: String,
 ) -> Result<(), AssemblerError> {
-    let mut listing = listings
-        .into_iter()
-        .map(|listing| {
-            let (_, start, end) = if listing.location.is_none() {
-                return listing.code;
-            } else {
-                listing.location.unwrap()
-            };
+    let header = listing_header(source_name, cpu, deterministic);
+    let listing = listings
+        .iter()
+        // Lines that didn't put anything in a section don't have code to show.
+        .filter(|listing| !code_only || listing.location.is_none_or(|(_, start, end)| end > start))
+        .map(|listing| listing_line(object, listing, canonical_numbers))
+        .collect::<Vec<String>>();
+    let mut listing = [header, listing].concat();
+    listing.push(listing_footer(object));
+    fs::write(&file_name, listing.join("\n"))
+        .map_err(|error| write_error("listing file", &file_name, error))?;
+
+    Ok(())
+}
+
+/// The comment lines at the top of a listing, describing the build so a printed
+/// listing documents itself.
+pub(super) fn listing_header(source_name: &str, cpu: Cpu, deterministic: bool) -> Vec<String> {
+    let mut header = vec![
+        format!("              * Source: {}", source_name),
+        format!(
+            "              * Assembler: s502-as {}",
+            env!("CARGO_PKG_VERSION")
+        ),
+        format!("              * CPU: {}", cpu.name()),
+    ];
+    if !deterministic {
+        header.push(format!("              * Date: {}", current_date()));
+    }
+    header.push(String::new());
+    header
+}
 
-            let mut listing_line = format!("{:04x?} ", start);
-            match end - start {
-                0 => listing_line.push_str("         "),
-                1 => listing_line.push_str(&format!("{:02x?}       ", section.data[start])),
+/// A line of a listing with the offset and bytes it put in its section, if any,
+/// followed by its code and notes.
+pub(super) fn listing_line(object: &Object, listing: &Listing, canonical_numbers: bool) -> String {
+    let (section_index, start, end) = if let Some(location) = listing.location {
+        location
+    } else {
+        return listing.code(canonical_numbers);
+    };
+    let code = listing.code(canonical_numbers);
+
+    let section = &object[section_index];
+    let mut listing_line = format!("{:04x?} ", start);
+    match end - start {
+        0 => listing_line.push_str("         "),
+        1 => listing_line.push_str(&format!("{:02x?}       ", section.data[start])),
+        2 => listing_line.push_str(&format!(
+            "{:02x?} {:02x?}    ",
+            section.data[start],
+            section.data[start + 1],
+        )),
+        3 => listing_line.push_str(&format!(
+            "{:02x?} {:02x?} {:02x?} ",
+            section.data[start],
+            section.data[start + 1],
+            section.data[start + 2],
+        )),
+        mut count_bytes => {
+            listing_line.push_str(&format!(
+                "{:02x?} {:02x?} {:02x?} {}\n{:04x?} ",
+                section.data[start],
+                section.data[start + 1],
+                section.data[start + 2],
+                code,
+                start + 3,
+            ));
+            count_bytes -= 3;
+
+            let mut start_line = start + 3;
+            while count_bytes > 3 {
+                listing_line.push_str(&format!(
+                    "{:02x?} {:02x?} {:02x?} \n{:04x?} ",
+                    section.data[start_line],
+                    section.data[start_line + 1],
+                    section.data[start_line + 2],
+                    start_line + 3
+                ));
+                count_bytes -= 3;
+                start_line += 3;
+            }
+
+            match count_bytes {
+                0 => {}
+                1 => listing_line.push_str(&format!("{:02x?}       ", section.data[start_line])),
                 2 => listing_line.push_str(&format!(
                     "{:02x?} {:02x?}    ",
-                    section.data[start],
-                    section.data[start + 1],
+                    section.data[start_line],
+                    section.data[start_line + 1],
                 )),
-                3 => listing_line.push_str(&format!(
+                _ => listing_line.push_str(&format!(
                     "{:02x?} {:02x?} {:02x?} ",
-                    section.data[start],
-                    section.data[start + 1],
-                    section.data[start + 2],
+                    section.data[start_line],
+                    section.data[start_line + 1],
+                    section.data[start_line + 2],
                 )),
-                mut count_bytes => {
-                    listing_line.push_str(&format!(
-                        "{:02x?} {:02x?} {:02x?} {}\n{:04x?} ",
-                        section.data[start],
-                        section.data[start + 1],
-                        section.data[start + 2],
-                        listing.code,
-                        start + 3,
-                    ));
-                    count_bytes -= 3;
-
-                    let mut start_line = start + 3;
-                    while count_bytes > 3 {
-                        listing_line.push_str(&format!(
-                            "{:02x?} {:02x?} {:02x?} \n{:04x?} ",
-                            section.data[start_line],
-                            section.data[start_line + 1],
-                            section.data[start_line + 2],
-                            start_line + 3
-                        ));
-                        count_bytes -= 3;
-                        start_line += 3;
-                    }
-
-                    match count_bytes {
-                        0 => {}
-                        1 => listing_line
-                            .push_str(&format!("{:02x?}       ", section.data[start_line])),
-                        2 => listing_line.push_str(&format!(
-                            "{:02x?} {:02x?}    ",
-                            section.data[start_line],
-                            section.data[start_line + 1],
-                        )),
-                        _ => listing_line.push_str(&format!(
-                            "{:02x?} {:02x?} {:02x?} ",
-                            section.data[start_line],
-                            section.data[start_line + 1],
-                            section.data[start_line + 2],
-                        )),
-                    }
-                }
             }
+        }
+    }
 
-            if end - start <= 3 {
-                listing_line.push_str(&listing.code);
-            }
+    if end - start <= 3 {
+        listing_line.push_str(&code);
+    }
 
-            listing_line
-        })
-        .collect::<Vec<String>>();
-    listing.push(format!(
-        "              * Assembled {} bytes",
-        section.highest_origin - section.lowest_origin.unwrap_or(0)
-    ));
-    fs::write(&file_name, listing.join("\n")).map_err(|error| AssemblerError {
-        message: format!("Error writing listing file `{}`: {}", file_name, error),
-        labels: vec![],
-        help: None,
-    })?;
+    for note in &listing.notes {
+        listing_line.push_str(&format!("\n              * Note: {}", note));
+    }
 
-    Ok(())
+    listing_line
 }
 
-pub fn create_symbol_table(section: &Section, file_name: String) -> Result<(), AssemblerError> {
-    fs::write(
-        &file_name,
-        section
-            .labels
+/// The comment line at the end of a listing with how many bytes were assembled.
+pub(super) fn listing_footer(object: &Object) -> String {
+    format!(
+        "              * Assembled {} bytes",
+        object
             .iter()
+            .map(|section| section.highest_origin - section.lowest_origin.unwrap_or(0))
+            .sum::<usize>()
+    )
+}
+
+/// Today's date in UTC as `yyyy-mm-dd`.
+fn current_date() -> String {
+    let (year, month, day) = civil_date();
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Today's date in UTC as the year, month, and day.
+pub(crate) fn civil_date() -> (u16, u8, u8) {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+
+    // Convert days since the epoch to a civil date, shifting the year to start in
+    // March so the leap day is at the end.
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year as u16, month as u8, day as u8)
+}
+
+/// The layout of a symbol table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolFormat {
+    /// Macros of the exported labels that can be included in other programs.
+    S502,
+    /// A Mesen label file with every label as an offset into the binary.
+    Mesen,
+}
+
+pub fn create_symbol_table(
+    object: &Object,
+    format: SymbolFormat,
+    file_name: String,
+) -> Result<(), AssemblerError> {
+    let labels = object.iter().flat_map(|section| section.labels.iter());
+    let symbols = match format {
+        SymbolFormat::S502 => labels
             .filter(|label| label.visibility == Visibility::Global)
             .map(|label| format!("{} equ ${:04x}", label.name, label.offset))
             .collect::<Vec<String>>()
             .join("\n"),
-    )
-    .map_err(|error| AssemblerError {
-        message: format!("Error writing symbol table `{}`: {}", file_name, error),
+        SymbolFormat::Mesen => {
+            let image_start = image_start(object);
+            labels
+                // Labels given an address before the binary have no offset into it.
+                .filter(|label| label.offset >= image_start)
+                // Mesen doesn't allow `.` in label names.
+                .map(|label| {
+                    format!(
+                        "P:{:04X}:{}",
+                        label.offset - image_start,
+                        label.name.replace('.', "_")
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        }
+    };
+
+    fs::write(&file_name, symbols)
+        .map_err(|error| write_error("symbol table", &file_name, error))?;
+
+    Ok(())
+}
+
+/// Write the merged ranges of used addresses in each section.
+pub fn create_coverage(object: &Object, file_name: String) -> Result<(), AssemblerError> {
+    let mut coverage = String::new();
+
+    for section in object {
+        for range in merge_ranges(section.used_ranges.clone()) {
+            coverage.push_str(&format!(
+                "{} ${:04x}-${:04x}\n",
+                section.name,
+                range.start,
+                range.end - 1
+            ));
+        }
+    }
+
+    fs::write(&file_name, coverage)
+        .map_err(|error| write_error("coverage file", &file_name, error))?;
+
+    Ok(())
+}
+
+/// Make sure the binary has the same hash as the one written to the manifest of
+/// a known good build. This is done after the references are resolved so it
+/// covers the final bytes.
+pub fn check_hash(
+    object: &Object,
+    script: &[SectionPlacement],
+    expected: u64,
+) -> Result<(), AssemblerError> {
+    let actual = fnv1a_64(&create_image(object, script));
+    if actual == expected {
+        return Ok(());
+    }
+
+    Err(AssemblerError {
+        message: String::from("The hash of the binary doesn't match the expected hash"),
         labels: vec![],
-        help: None,
-    })?;
+        help: Some(format!(
+            "expected fnv1a64:{:016x}\n           got fnv1a64:{:016x}",
+            expected, actual
+        )),
+    })
+}
+
+/// Write each label and macro with the lines that define and reference it.
+pub fn create_cross_reference(
+    cross_references: &[CrossReference],
+    file_name: String,
+) -> Result<(), AssemblerError> {
+    let mut table = String::new();
+
+    for cross_reference in cross_references {
+        table.push_str(&format!("{}\n", cross_reference.name));
+        match &cross_reference.definition {
+            Some((file, line)) => table.push_str(&format!("    defined  {}:{}\n", file, line)),
+            None => table.push_str("    defined  elsewhere\n"),
+        }
+        for (file, line) in &cross_reference.uses {
+            table.push_str(&format!("    used     {}:{}\n", file, line));
+        }
+    }
+
+    fs::write(&file_name, table)
+        .map_err(|error| write_error("cross-reference file", &file_name, error))?;
+
+    Ok(())
+}
+
+/// Write a JSON summary of the binary for use by other tools, with an entry for
+/// each file that was written and the contents it was written with.
+pub fn create_manifest(
+    object: &Object,
+    outputs: &[(OutputFormat, String)],
+    contents: &[Vec<u8>],
+    file_name: String,
+) -> Result<(), AssemblerError> {
+    let outputs = outputs
+        .iter()
+        .zip(contents)
+        .map(|((format, output_filename), output)| {
+            format!(
+                "    {{\"output\": {}, \"format\": \"{}\", \"size\": {}, \"hash\": \"fnv1a64:{:016x}\"}}",
+                json_string(output_filename),
+                format.name(),
+                output.len(),
+                fnv1a_64(output)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",\n");
+
+    let manifest = formatdoc! {
+        "
+        {{
+          \"outputs\": [
+        {}
+          ],
+          \"symbols\": {},
+          \"sections\": [
+        {}
+          ]
+        }}
+        ",
+        outputs,
+        object.iter().map(|section| section.labels.len()).sum::<usize>(),
+        json_sections(object),
+    };
+
+    fs::write(&file_name, manifest).map_err(|error| write_error("manifest", &file_name, error))?;
 
     Ok(())
 }
+
+/// Write the symbols, the line each address was assembled from, and the sections
+/// in one JSON file for debuggers and emulators to load.
+pub fn create_debug_bundle(
+    object: &Object,
+    listings: &[Listing],
+    file_name: String,
+) -> Result<(), AssemblerError> {
+    let symbols = object
+        .iter()
+        .flat_map(|section| section.labels.iter())
+        .map(|label| {
+            format!(
+                "    {{\"name\": {}, \"address\": {}, \"global\": {}}}",
+                json_string(&label.name),
+                label.offset,
+                label.visibility == Visibility::Global
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",\n");
+
+    let lines = listings
+        .iter()
+        .filter_map(|listing| match (&listing.location, &listing.source_line) {
+            (Some((_, start, end)), Some((file, line))) if end > start => Some(format!(
+                "    {{\"address\": {}, \"size\": {}, \"file\": {}, \"line\": {}}}",
+                start,
+                end - start,
+                json_string(file),
+                line
+            )),
+            _ => None,
+        })
+        .collect::<Vec<String>>()
+        .join(",\n");
+
+    let bundle = formatdoc! {
+        "
+        {{
+          \"symbols\": [
+        {}
+          ],
+          \"lines\": [
+        {}
+          ],
+          \"sections\": [
+        {}
+          ]
+        }}
+        ",
+        symbols,
+        lines,
+        json_sections(object),
+    };
+
+    fs::write(&file_name, bundle)
+        .map_err(|error| write_error("debug bundle", &file_name, error))?;
+
+    Ok(())
+}
+
+/// The base address and size of each section as JSON objects.
+fn json_sections(object: &Object) -> String {
+    object
+        .iter()
+        .map(|section| {
+            let base = section.lowest_origin.unwrap_or(0);
+            format!(
+                "    {{\"name\": {}, \"base\": {}, \"size\": {}}}",
+                json_string(&section.name),
+                base,
+                section.highest_origin - base
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",\n")
+}
+
+/// The 64 bit FNV-1a hash, which is simple and stable across builds.
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -1,34 +1,81 @@
 pub mod binary;
+pub mod object;
 
-use std::{collections::HashMap, iter::Peekable, ops::Range, vec};
+use std::{
+    collections::HashMap,
+    iter::Peekable,
+    ops::{Range, RangeInclusive},
+    vec,
+};
 
-use codespan_reporting::files::SimpleFiles;
+use codespan_reporting::files::{Files, SimpleFiles};
 
-use crate::{ast::*, error::*};
+use crate::{ast::*, equates::Equate, error::*, parser::lexer::Literal, script::SectionPlacement};
 
 pub type Object = Vec<Section>;
 
+/// What assembling a program gives: its sections, the listing of each line, the
+/// cross-reference of its symbols, and the label given to `entry` if there is one.
+pub type Generated = (Object, Vec<Listing>, Vec<CrossReference>, Option<String>);
+
+/// The number of addresses the 6502 can reach.
+pub const MEMORY_SIZE: usize = 0x10000;
+
+/// The most labels a section may have unless another limit is given.
+pub const DEFAULT_MAX_LABELS: usize = 65536;
+/// The most macros a program may define unless another limit is given.
+pub const DEFAULT_MAX_MACROS: usize = 65536;
+
 pub struct Section {
     pub name: String,
-    pub data: [u8; 65536],
+    /// The bytes of the section up to the highest one put in it. The rest of memory
+    /// is 0, so a section only takes as much space as its code.
+    pub data: Vec<u8>,
     // The offset into the current section where to put the next byte of code.
     pub origin: usize,
     pub lowest_origin: Option<usize>,
     pub highest_origin: usize,
     /// The ranges of memory that have had code put into them. This is used to warn
-    /// the programmer when they overwrite code, and it is only used in binary mode.
+    /// the programmer when they overwrite code or leave a section empty.
     pub used_ranges: Vec<Range<usize>>,
+    /// The ranges skipped with `ds`, which are left as 0 without being code.
+    pub reserved_ranges: Vec<Range<usize>>,
+    /// The largest boundary passed to `align`, which the linker has to place the
+    /// section on for the alignment to hold.
+    pub alignment: usize,
     pub labels: Vec<SectionLabel>,
     pub references: Vec<Reference>,
+    /// Where the section was first declared, or None for the implicit default section.
+    pub location: Option<Location>,
+}
+
+impl Section {
+    /// An empty section that grows as code is put in it.
+    fn new(name: String, location: Option<Location>) -> Self {
+        Self {
+            name,
+            data: Vec::new(),
+            origin: 0,
+            lowest_origin: None,
+            highest_origin: 0,
+            used_ranges: vec![],
+            reserved_ranges: vec![],
+            alignment: 1,
+            labels: vec![],
+            references: vec![],
+            location,
+        }
+    }
 }
 
+#[derive(Debug, PartialEq)]
 pub struct SectionLabel {
     pub name: String,
     pub visibility: Visibility,
     pub offset: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Reference {
     /// The symbol being referenced.
     pub name: String,
@@ -38,10 +85,33 @@ pub struct Reference {
     pub modifier: Option<Spanned<Modifier>>,
     // Whether the instruction making hte reference is a branch.
     pub branch: bool,
+    /// Whether the name is a section whose size is referenced instead of a label.
+    pub section_size: bool,
+    /// Whether the name is a section whose start address is referenced instead of
+    /// a label, from `$` in an object.
+    pub section_start: bool,
+    /// What is added to the label's address, from an index like `table[3]` or an
+    /// expression like `label+3`.
+    pub addend: u16,
+    /// A label whose address is subtracted, from an expression like `end-start`.
+    pub subtrahend: Option<String>,
     /// Where in the source code the reference is.
     pub location: Location,
 }
 
+/// The result of evaluating an expression.
+enum Evaluated {
+    /// A number and whether any part of it was a word.
+    Number(i32, bool),
+    /// A label's address plus a number, which isn't known until labels are resolved.
+    Label(String, i32),
+    /// The distance from the second label to the first plus a number, when it
+    /// isn't known until labels are resolved.
+    Distance(String, String, i32),
+    /// The start of the current section plus a number, from `$` in an object.
+    SectionStart(i32),
+}
+
 #[derive(Debug)]
 enum ActualValue {
     Accumulator,
@@ -52,6 +122,28 @@ enum ActualValue {
     Reference(Reference),
 }
 
+/// The character that lines are expected to be indented with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Indentation {
+    Tabs,
+    Spaces,
+}
+
+/// What an instruction operates on, used to compare consecutive instructions.
+#[derive(Debug, PartialEq)]
+enum Target {
+    Address(u16),
+    Label(String, Option<Modifier>),
+}
+
+/// The most recently assembled instruction, used to find redundant sequences.
+struct RecentInstruction {
+    mnemonic: Mnemonic,
+    /// The address operated on, None if the instruction doesn't access memory.
+    target: Option<Target>,
+    location: Location,
+}
+
 #[derive(Debug)]
 pub enum Macro {
     Byte(u8, Location),
@@ -59,9 +151,97 @@ pub enum Macro {
     String(String, Location),
 }
 
+impl Macro {
+    /// Where the macro was defined.
+    fn location(&self) -> &Location {
+        match self {
+            Macro::Byte(_, location) | Macro::Word(_, location) | Macro::String(_, location) => {
+                location
+            }
+        }
+    }
+}
+
+/// Where macros defined on the command line with `--define` are said to be defined.
+fn command_line_location() -> Location {
+    Location {
+        span: 0..0,
+        file_name: String::from("<command line>"),
+    }
+}
+
 pub struct Listing {
     location: Option<(usize, usize, usize)>,
+    /// The file and line number that the code came from.
+    source_line: Option<(String, usize)>,
     code: String,
+    /// The spans of number literals in the code and how they're written in hex.
+    numbers: Vec<(Range<usize>, String)>,
+    /// Warnings about the line, shown beneath it with `--listing-notes`.
+    notes: Vec<String>,
+}
+
+impl Listing {
+    /// The code of the line, with each number literal written as hex if requested.
+    fn code(&self, canonical_numbers: bool) -> String {
+        let mut code = self.code.clone();
+        if canonical_numbers {
+            // Replace from the end so the earlier spans stay correct.
+            for (span, number) in self.numbers.iter().rev() {
+                code.replace_range(span.clone(), number);
+            }
+        }
+        code
+    }
+}
+
+/// Attach each warning that isn't turned off to the listing line its first label
+/// points to, so the listing can show it beneath the code.
+pub fn annotate_listings(
+    listings: &mut [Listing],
+    warnings: &[Warning],
+    settings: &WarningSettings,
+    id_table: &HashMap<String, usize>,
+    files: &SimpleFiles<String, String>,
+) {
+    for warning in warnings {
+        if settings.level(warning.category) == WarningLevel::Off {
+            continue;
+        }
+        let location = match warning.error.labels.first() {
+            Some((location, _)) => location,
+            // There's no line to put it under.
+            None => continue,
+        };
+        let line_number = files
+            .line_index(id_table[&location.file_name], location.span.start)
+            .unwrap_or(0)
+            + 1;
+
+        if let Some(listing) = listings.iter_mut().find(|listing| {
+            listing
+                .source_line
+                .as_ref()
+                .is_some_and(|(file_name, line)| {
+                    file_name == &location.file_name && *line == line_number
+                })
+        }) {
+            listing.notes.push(format!(
+                "{} [-W{}]",
+                warning.error.message,
+                warning.category.name()
+            ));
+        }
+    }
+}
+
+/// A label or macro and the source lines it appears on.
+pub struct CrossReference {
+    name: String,
+    /// The file and line number of the definition, None if it's defined in another object.
+    definition: Option<(String, usize)>,
+    /// The file and line number of each reference.
+    uses: Vec<(String, usize)>,
 }
 
 pub struct GeneratorContext<'context> {
@@ -69,6 +249,43 @@ pub struct GeneratorContext<'context> {
     program: Peekable<vec::IntoIter<Action>>,
     /// Whether or not to assemble in binary mode.
     binary: bool,
+    /// Where to place sections when assembling multiple sections in binary mode.
+    script: Option<&'context [SectionPlacement]>,
+    /// The origin of the default section before any `org` directive.
+    initial_origin: Option<usize>,
+    /// Names of fixed addresses from an equates file, which become labels.
+    equates: &'context [Equate],
+    /// Macros defined on the command line with `--define`.
+    definitions: &'context [(String, Literal)],
+    /// Whether to warn about redundant instruction sequences.
+    lint: bool,
+    /// The addresses of ROM, which instructions shouldn't write to.
+    rom_ranges: &'context [RangeInclusive<u16>],
+    /// The indentation to warn about lines not using, if any.
+    indentation: Option<Indentation>,
+    /// The processor whose instructions are allowed.
+    cpu: Cpu,
+    /// Whether shifts and rotates without an operand act on the accumulator.
+    implicit_accumulator: bool,
+    /// Whether the code has to run from any address, so it can't contain label addresses.
+    pic: bool,
+    /// Whether sections have to be declared with `sctdef` before `sct` uses them.
+    strict_sections: bool,
+    /// Whether the boundary given to `align` has to be a power of two.
+    strict_alignment: bool,
+    /// Whether `datestamp` writes zeros instead of the date, so builds can be compared.
+    deterministic: bool,
+    /// Whether label names are compared without case, by making them lowercase.
+    case_insensitive_labels: bool,
+    /// The most labels that a section may have, so generated or hostile input
+    /// errors instead of exhausting memory.
+    max_labels: usize,
+    /// The most macros that may be defined, for the same reason.
+    max_macros: usize,
+    /// Sections declared with `sctdef` and where they were declared.
+    declared_sections: HashMap<String, Location>,
+    /// The previous instruction if nothing, such as a label, came between it and the current one.
+    last_instruction: Option<RecentInstruction>,
     /// The map from file names to ID numbers.
     id_table: &'context HashMap<String, usize>,
     /// The source files. This is used for getting excerpts for listings.
@@ -87,12 +304,37 @@ pub struct GeneratorContext<'context> {
     include_stack: Vec<(String, usize)>,
     /// The most recent parent label, used for filling in implied parent of sublabels.
     last_parent_label: Option<(Spanned<String>, String)>,
+    /// The parent label of each including file, restored when its include ends since
+    /// the parser resolves sublabels in each file under that file's own parent.
+    including_parent_labels: Vec<Option<(Spanned<String>, String)>>,
+    /// The section and offset of the last byte written over already assembled code,
+    /// so a run of them is only warned about once.
+    last_overwrite: Option<(usize, usize)>,
+    /// Where the current line started writing over already assembled code.
+    line_overwrite: Option<usize>,
+    /// The number literals in the current line's operands and their hex forms.
+    current_line_numbers: Vec<(Range<usize>, String)>,
     /// Macros defined during generation.
     macros: HashMap<String, Macro>,
+    /// Where each label and macro has been referenced in an operand.
+    symbol_uses: HashMap<String, Vec<Location>>,
+    /// Where each label was defined.
+    label_definitions: HashMap<String, Location>,
+    /// The first spelling of each label name in lowercase, to warn about labels
+    /// that only differ in case.
+    label_spellings: HashMap<String, String>,
     /// A label appeared on this line so a macro may be created.
     macro_valid: bool,
+    /// The label given to `entry` and where it was given.
+    entry: Option<(String, Location)>,
+    /// Whether the current instruction tried to put a byte past the end of memory.
+    past_memory_end: bool,
+    /// The byte that `align` and `dfs` fill with when they aren't given one, set by `fillbyte`.
+    fill: u8,
     /// Errors found during code generation.
     errors: Vec<AssemblerError>,
+    /// Warnings found during code generation. These are reported even if there are errors.
+    warnings: &'context mut Vec<Warning>,
     // old_listing: Vec<String>,
     listing: Vec<Listing>,
 }
@@ -105,10 +347,29 @@ impl<'context> GeneratorContext<'context> {
         top_file_name: String,
         id_table: &'context HashMap<String, usize>,
         files: &'context SimpleFiles<String, String>,
+        warnings: &'context mut Vec<Warning>,
     ) -> Self {
         Self {
             program: program.into_iter().peekable(),
             binary,
+            script: None,
+            initial_origin: None,
+            equates: &[],
+            definitions: &[],
+            lint: false,
+            rom_ranges: &[],
+            indentation: None,
+            cpu: Cpu::Nmos6502,
+            implicit_accumulator: false,
+            pic: false,
+            strict_sections: false,
+            strict_alignment: false,
+            deterministic: false,
+            case_insensitive_labels: false,
+            max_labels: DEFAULT_MAX_LABELS,
+            max_macros: DEFAULT_MAX_MACROS,
+            declared_sections: HashMap::new(),
+            last_instruction: None,
             id_table,
             files,
             object: Object::with_capacity(2),
@@ -120,63 +381,219 @@ impl<'context> GeneratorContext<'context> {
             // Start with the top level file.
             include_stack: vec![(top_file_name, 0)],
             last_parent_label: None,
+            including_parent_labels: vec![],
+            last_overwrite: None,
+            line_overwrite: None,
+            current_line_numbers: Vec::new(),
             macros: HashMap::with_capacity(32),
+            symbol_uses: HashMap::with_capacity(64),
+            label_definitions: HashMap::with_capacity(64),
+            label_spellings: HashMap::with_capacity(64),
             macro_valid: false,
+            entry: None,
+            past_memory_end: false,
+            fill: 0,
             errors: Vec::with_capacity(4),
+            warnings,
             listing: Vec::with_capacity(512),
         }
     }
 
-    pub fn generate_code(mut self) -> Result<(Object, Vec<Listing>), Vec<AssemblerError>> {
+    /// Place sections according to a layout script, allowing the `sct` directive in binary mode.
+    pub fn with_script(mut self, script: &'context [SectionPlacement]) -> Self {
+        self.script = Some(script);
+        self
+    }
+
+    /// Start the default section at an address, as if the program began with `org`.
+    pub fn with_origin(mut self, origin: u16) -> Self {
+        self.initial_origin = Some(origin as usize);
+        self
+    }
+
+    /// Define labels at the addresses named in an equates file.
+    pub fn with_equates(mut self, equates: &'context [Equate]) -> Self {
+        self.equates = equates;
+        self
+    }
+
+    /// Define macros before the program, like `--define DEBUG=1` on the command line.
+    pub fn with_definitions(mut self, definitions: &'context [(String, Literal)]) -> Self {
+        self.definitions = definitions;
+        self
+    }
+
+    /// Warn about instructions that write to these ranges of addresses.
+    pub fn with_rom_ranges(mut self, rom_ranges: &'context [RangeInclusive<u16>]) -> Self {
+        self.rom_ranges = rom_ranges;
+        self
+    }
+
+    /// Warn about instruction sequences that have no effect, like `tax` followed by `txa`.
+    pub fn with_lint(mut self) -> Self {
+        self.lint = true;
+        self
+    }
+
+    /// Warn about lines that are indented with the other character.
+    pub fn with_indentation(mut self, indentation: Indentation) -> Self {
+        self.indentation = Some(indentation);
+        self
+    }
+
+    /// Assemble for a processor other than the NMOS 6502.
+    pub fn with_cpu(mut self, cpu: Cpu) -> Self {
+        self.cpu = cpu;
+        self
+    }
+
+    /// Let `asl`, `lsr`, `rol`, and `ror` leave out the `a` operand.
+    pub fn with_implicit_accumulator(mut self) -> Self {
+        self.implicit_accumulator = true;
+        self
+    }
+
+    /// Assemble position independent code, which only refers to labels with branches.
+    pub fn with_pic(mut self) -> Self {
+        self.pic = true;
+        self
+    }
+
+    /// Require sections to be declared with `sctdef` so a misspelled name in `sct`
+    /// is an error instead of a new section.
+    pub fn with_strict_sections(mut self) -> Self {
+        self.strict_sections = true;
+        self
+    }
+
+    /// Only allow powers of two as the boundary of the `align` directive.
+    pub fn with_strict_alignment(mut self) -> Self {
+        self.strict_alignment = true;
+        self
+    }
+
+    /// Write zeros for `datestamp` instead of the date it's assembled on.
+    pub fn with_deterministic(mut self) -> Self {
+        self.deterministic = true;
+        self
+    }
+
+    /// Compare the names of labels and macros without case, so `Loop` and `loop` are
+    /// the same label. The names are written in lowercase in symbol tables.
+    pub fn with_case_insensitive_labels(mut self) -> Self {
+        self.case_insensitive_labels = true;
+        self
+    }
+
+    /// Limit how many labels each section may have and how many macros may be defined.
+    pub fn with_table_limits(mut self, max_labels: usize, max_macros: usize) -> Self {
+        self.max_labels = max_labels;
+        self.max_macros = max_macros;
+        self
+    }
+
+    pub fn generate_code(mut self) -> Result<Generated, Vec<AssemblerError>> {
         // We need at least one section for hte firstl ine start action, so just give it the default name.
-        self.object.push(Section {
-            name: String::from("default"),
-            data: [0; 65536],
-            origin: 0,
-            lowest_origin: None,
-            highest_origin: 0,
-            used_ranges: Vec::with_capacity(8),
-            labels: Vec::with_capacity(64),
-            references: Vec::with_capacity(128),
-        });
+        self.object
+            .push(Section::new(String::from("default"), None));
+
+        if self.case_insensitive_labels {
+            let mut program = self.program.collect::<Program>();
+            lowercase_symbols(&mut program);
+            self.program = program.into_iter().peekable();
+        }
+
+        // Equates are labels at their addresses, like `name = address` but defined
+        // before the program.
+        for equate in self.equates {
+            let name = self.symbol_name(&equate.name);
+            self.object[0].labels.push(SectionLabel {
+                name,
+                visibility: Visibility::Global,
+                offset: equate.address as usize,
+            });
+        }
+
+        // Definitions from the command line are macros that every line can use.
+        for (name, value) in self.definitions {
+            let location = command_line_location();
+            let definition = match value {
+                Literal::Byte(byte) => Macro::Byte(*byte, location),
+                Literal::Word(word) => Macro::Word(*word, location),
+                Literal::String(string) => Macro::String(string.clone(), location),
+                Literal::Character(_) => unreachable!(),
+            };
+            self.macros.insert(self.symbol_name(name), definition);
+        }
+
+        // The default section can also be placed by the script.
+        if let Some(address) = self.script_address("default") {
+            self.set_origin(address);
+        }
+        if let Some(address) = self.initial_origin {
+            self.set_origin(address);
+        }
 
         while self.program.peek().is_some() {
             let action = self.program.next().unwrap();
             match action {
                 Action::LineStart(start_index) => {
+                    self.check_indentation(start_index);
                     self.current_line_source_start = start_index;
+                    self.current_line_numbers.clear();
                     self.current_line_section_start = self.object[self.current_section].origin;
+                    // Lines without an instruction don't put anything in the section.
+                    self.current_line_section_end = self.current_line_section_start;
                 }
                 Action::LineEnd(line_end) => {
                     self.current_line_source_end = line_end;
+                    self.warn_overwrite(line_end);
                     self.create_listing_line(line_end);
                     self.macro_valid = false;
                 }
                 Action::PushInclude(included_name) => {
                     self.listing.push(Listing {
                         location: None,
+                        source_line: None,
                         code: format!("**** START INCLUDED FILE `{}`", included_name),
+                        numbers: vec![],
+                        notes: vec![],
                     });
                     self.include_stack
                         .push((included_name, self.current_line_source_end));
+                    self.including_parent_labels
+                        .push(self.last_parent_label.take());
                 }
                 Action::PopInclude => {
                     let (file_name, continue_source_index) = self.include_stack.pop().unwrap();
+                    self.last_parent_label = self.including_parent_labels.pop().unwrap();
                     self.listing.push(Listing {
                         location: None,
+                        source_line: None,
                         code: format!("**** END   INCLUDED FILE `{}`", file_name),
+                        numbers: vec![],
+                        notes: vec![],
                     });
                     self.current_line_source_start = continue_source_index;
                     self.current_line_section_start = self.object[self.current_section].origin;
                 }
                 Action::Label(label) => {
+                    // A label may be jumped to, so the instructions around it are not a sequence.
+                    self.last_instruction = None;
                     if self.handle_label(label).is_err() {
                         self.skip_to_eol();
                         continue;
                     }
                 }
                 Action::Instruction(instruction) => {
-                    match self.handle_instruction(instruction) {
+                    let instruction_span = instruction.span.clone();
+                    let result = self
+                        .handle_instruction(instruction)
+                        .and_then(|bytes_inserted| {
+                            self.check_memory_end(instruction_span)?;
+                            Ok(bytes_inserted)
+                        });
+                    match result {
                         Err(error) => {
                             self.errors.push(error);
                             self.current_line_section_end =
@@ -193,10 +610,221 @@ impl<'context> GeneratorContext<'context> {
             }
         }
 
+        self.warn_empty_sections();
+        self.warn_unused_macros();
+        if !self.binary {
+            self.check_distances();
+        }
+        self.check_entry();
+
         if !self.errors.is_empty() {
             Err(self.errors)
         } else {
-            Ok((self.object, self.listing))
+            let cross_references = self.cross_references();
+            let entry = self.entry.map(|(name, _)| name);
+            Ok((self.object, self.listing, cross_references, entry))
+        }
+    }
+
+    /// The entry point has to be a label defined in the program, so the linker
+    /// doesn't have to look for it in other objects.
+    fn check_entry(&mut self) {
+        if let Some((name, location)) = &self.entry {
+            if !self.label_definitions.contains_key(name) {
+                self.errors.push(AssemblerError {
+                    message: format!("The entry point `{}` isn't a label in this program", name),
+                    labels: vec![(location.clone(), None)],
+                    help: None,
+                });
+            }
+        }
+    }
+
+    /// The linker places each section on its own, so in an object the distance
+    /// between labels is only known when both are in the same section.
+    fn check_distances(&mut self) {
+        let section_of = |name: &str| {
+            self.object
+                .iter()
+                .position(|section| section.labels.iter().any(|label| label.name == name))
+        };
+
+        for reference in self.object.iter().flat_map(|section| &section.references) {
+            let start = match &reference.subtrahend {
+                Some(start) => start,
+                None => continue,
+            };
+            let end_section = section_of(&reference.name);
+            if end_section.is_some() && end_section == section_of(start) {
+                continue;
+            }
+
+            self.errors.push(AssemblerError {
+                message: format!(
+                    "`{}` and `{}` aren't in the same section",
+                    reference.name, start
+                ),
+                labels: vec![(reference.location.clone(), None)],
+                help: Some(String::from(
+                    "Sections are placed separately when linking, so the distance between \
+                    labels can only be taken within one section",
+                )),
+            });
+        }
+    }
+
+    /// Warn about declared sections that never had anything put in them, which is
+    /// likely a typo in the name given to `sct`.
+    fn warn_empty_sections(&mut self) {
+        for section in &self.object {
+            if let Some(location) = &section.location {
+                if section.used_ranges.is_empty()
+                    && section.reserved_ranges.is_empty()
+                    && section.labels.is_empty()
+                {
+                    self.warnings.push(Warning {
+                        category: WarningCategory::EmptySection,
+                        error: AssemblerError {
+                            message: format!(
+                                "Section `{}` is declared but never used",
+                                section.name
+                            ),
+                            labels: vec![(location.clone(), None)],
+                            help: Some(String::from("Is the section name misspelled?")),
+                        },
+                    });
+                }
+            }
+        }
+    }
+
+    /// Warn about the current line writing over code that was already assembled,
+    /// pointing to the line that first wrote there.
+    fn warn_overwrite(&mut self, line_end: usize) {
+        let offset = match self.line_overwrite.take() {
+            Some(offset) => offset,
+            None => return,
+        };
+
+        let file_name = self.include_stack.last().unwrap().0.clone();
+        let mut labels = vec![(
+            Location {
+                span: self.trimmed_line(&file_name, self.current_line_source_start..line_end),
+                file_name,
+            },
+            None,
+        )];
+        let original = self.listing.iter().find_map(|listing| {
+            match (&listing.location, &listing.source_line) {
+                (Some((section, start, end)), Some((file_name, line_number)))
+                    if *section == self.current_section && (*start..*end).contains(&offset) =>
+                {
+                    Some((file_name, *line_number))
+                }
+                _ => None,
+            }
+        });
+        if let Some((file_name, line_number)) = original {
+            if let Ok(span) = self
+                .files
+                .line_range(self.id_table[file_name], line_number - 1)
+            {
+                labels.push((
+                    Location {
+                        span: self.trimmed_line(file_name, span),
+                        file_name: file_name.clone(),
+                    },
+                    Some(String::from("First written here")),
+                ));
+            }
+        }
+
+        self.warnings.push(Warning {
+            category: WarningCategory::Overwrite,
+            error: AssemblerError {
+                message: format!("Code overwrites bytes already assembled at ${:04x}", offset),
+                labels,
+                help: Some(String::from(
+                    "Check for `org` directives that place code on top of other code",
+                )),
+            },
+        });
+    }
+
+    /// The span of a line without the line break and whitespace at its end.
+    fn trimmed_line(&self, file_name: &str, span: Range<usize>) -> Range<usize> {
+        let source = self.files.get(self.id_table[file_name]).unwrap().source();
+        span.start..span.start + source[span].trim_end().len()
+    }
+
+    /// Warn about macros that were defined but never referenced in an operand.
+    fn warn_unused_macros(&mut self) {
+        let mut unused = self
+            .macros
+            .iter()
+            .filter(|(name, _)| !self.symbol_uses.contains_key(*name))
+            .map(|(name, mac)| (name, mac.location()))
+            // A definition may only be used by some builds of the program.
+            .filter(|(_, location)| **location != command_line_location())
+            .collect::<Vec<_>>();
+        // Report them in source order rather than hash order.
+        unused.sort_by_key(|(_, location)| (&location.file_name, location.span.start));
+
+        for (name, location) in unused {
+            self.warnings.push(Warning {
+                category: WarningCategory::UnusedMacro,
+                error: AssemblerError {
+                    message: format!("Macro `{}` is defined but never used", name),
+                    labels: vec![(location.clone(), None)],
+                    help: Some(String::from(
+                        "Is the macro name misspelled where it's used?",
+                    )),
+                },
+            });
+        }
+    }
+
+    /// Warn if the whitespace before the first token of a line uses the character
+    /// that isn't allowed. The lexer skips whitespace so this looks at the source.
+    fn check_indentation(&mut self, line_start: usize) {
+        let disallowed = match self.indentation {
+            Some(Indentation::Tabs) => ' ',
+            Some(Indentation::Spaces) => '\t',
+            None => return,
+        };
+
+        let file_name = &self.include_stack.last().unwrap().0;
+        let line = &self.files.get(self.id_table[file_name]).unwrap().source()[line_start..];
+        let indent_length = line
+            .find(|character| character != ' ' && character != '\t')
+            .unwrap_or(line.len());
+
+        // Blank lines don't have anything to indent.
+        if line[indent_length..].starts_with(['\n', '\r']) || indent_length == line.len() {
+            return;
+        }
+
+        if line[..indent_length].contains(disallowed) {
+            self.warnings.push(Warning {
+                category: WarningCategory::Indent,
+                error: AssemblerError {
+                    message: format!(
+                        "Line is indented with {}",
+                        if disallowed == '\t' { "tabs" } else { "spaces" }
+                    ),
+                    labels: vec![(
+                        Location {
+                            span: line_start..line_start + indent_length,
+                            file_name: file_name.clone(),
+                        },
+                        None,
+                    )],
+                    help: Some(format!(
+                        "Indent with {} as set by --indent",
+                        if disallowed == '\t' { "spaces" } else { "tabs" }
+                    )),
+                },
+            });
         }
     }
 
@@ -213,16 +841,180 @@ impl<'context> GeneratorContext<'context> {
         instruction: Spanned<Instruction>,
     ) -> Result<usize, AssemblerError> {
         let mnemonic = instruction.mnemonic.clone();
+        let instruction_location = Location {
+            span: instruction.span.clone(),
+            file_name: self.include_stack.last().unwrap().0.clone(),
+        };
+        // Anything other than an instruction in between breaks up a sequence.
+        let previous_instruction = self.last_instruction.take();
+
+        if mnemonic.val.is_65c02() && self.cpu != Cpu::Cmos65c02 {
+            return Err(AssemblerError {
+                message: format!(
+                    "Instruction `{}` is not available for the {}",
+                    mnemonic.val,
+                    self.cpu.name()
+                ),
+                labels: vec![(
+                    Location {
+                        span: mnemonic.span.clone(),
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    None,
+                )],
+                help: Some(String::from(
+                    "It was added in the 65C02, chosen with `--cpu 65c02`",
+                )),
+            });
+        }
+
+        // Data read by `inb` is already bytes, so insert it without resolving.
+        if let Some(Spanned {
+            val:
+                Operand {
+                    value:
+                        Spanned {
+                            val: Value::Data(bytes),
+                            span: _,
+                        },
+                    ..
+                },
+            span: _,
+        }) = &instruction.val.operand
+        {
+            for byte in bytes {
+                self.insert_byte(*byte);
+            }
+            return Ok(bytes.len());
+        }
+
+        // The boundary and fill byte of `align` are resolved separately.
+        if let Some(Spanned {
+            val:
+                Operand {
+                    value:
+                        Spanned {
+                            val: Value::Alignment((boundary, fill, name)),
+                            span: _,
+                        },
+                    ..
+                },
+            span: _,
+        }) = instruction.val.operand
+        {
+            return self.align(*boundary, fill.map(|fill| *fill), name, instruction.span);
+        }
+
+        // So are the count and fill byte of `dfs`.
+        if let Some(Spanned {
+            val:
+                Operand {
+                    value:
+                        Spanned {
+                            val: Value::Fill((count, fill, name)),
+                            span: _,
+                        },
+                    ..
+                },
+            span: _,
+        }) = instruction.val.operand
+        {
+            return self.fill(*count, fill.map(|fill| *fill), name, instruction.span);
+        }
+
+        // Each value of a `dfb` or `dfw` list is resolved right before it's defined,
+        // so references are made at its offset.
+        if let Some(Spanned {
+            val:
+                Operand {
+                    value:
+                        Spanned {
+                            val: Value::List(operands),
+                            span: _,
+                        },
+                    ..
+                },
+            span: _,
+        }) = instruction.val.operand
+        {
+            let mut bytes_inserted = 0;
+            for operand in operands {
+                let (operand, _) = self.resolve_operand(operand, false)?;
+                self.check_position_independence(mnemonic.val, &operand.val)?;
+                bytes_inserted += if mnemonic.val == Mnemonic::Dfw {
+                    self.handle_dfw(operand)?
+                } else {
+                    self.handle_dfb(operand)?
+                };
+            }
+            return Ok(bytes_inserted);
+        }
+
+        if let Some(Spanned {
+            val:
+                Operand {
+                    value:
+                        Spanned {
+                            val: Value::Repeat((count, body)),
+                            span: _,
+                        },
+                    ..
+                },
+            span: _,
+        }) = instruction.val.operand
+        {
+            return self.repeat(*count, body);
+        }
+
+        // Only one block of an `if` is assembled.
+        if let Some(Spanned {
+            val:
+                Operand {
+                    value:
+                        Spanned {
+                            val: Value::Conditional((condition, then_body, else_body)),
+                            span: _,
+                        },
+                    ..
+                },
+            span: _,
+        }) = instruction.val.operand
+        {
+            return self.conditional(*condition, then_body, else_body);
+        }
+
+        // The label of `entry` is only recorded, not referenced where the directive is.
+        if mnemonic.val == Mnemonic::Entry {
+            return self.set_entry(instruction.val.operand, instruction.span);
+        }
+
+        // The operand of `datestamp` is optional, so it's handled before implied instructions.
+        if mnemonic.val == Mnemonic::Datestamp {
+            return self.datestamp(instruction.val.operand, instruction.span);
+        }
+
         // If the instruction is implied then handle it, otherwise take out
         // the operand to use later.
-        let (spanned_operand, address_mode) = match instruction.val.operand {
+        let (spanned_operand, mut address_mode) = match instruction.val.operand {
             None => {
                 // No operand so it's expected to be implied.
-                return self.handle_implied_instruction(mnemonic.val, instruction.span.clone());
+                let bytes_inserted =
+                    self.handle_implied_instruction(mnemonic.val, instruction.span.clone())?;
+                self.check_redundancy(
+                    previous_instruction,
+                    RecentInstruction {
+                        mnemonic: mnemonic.val,
+                        target: None,
+                        location: instruction_location,
+                    },
+                );
+                return Ok(bytes_inserted);
             }
-            Some(operand) => self.resolve_operand(operand, mnemonic.is_branch()),
+            Some(operand) => self.resolve_operand(operand, mnemonic.is_branch())?,
         };
-        let (operand, operand_span) = (spanned_operand.val, spanned_operand.span);
+        let (mut operand, operand_span) = (spanned_operand.val, spanned_operand.span);
+
+        self.check_position_independence(mnemonic.val, &operand)?;
 
         // Handle directives first.
         match mnemonic.val {
@@ -232,13 +1024,124 @@ impl<'context> GeneratorContext<'context> {
             // Skip hlt directive because it's essentially an implied instruction so
             // it's treated as one. Also skip inl because it's handled in the parser.
             Mnemonic::Org => return self.change_origin(operand, instruction.span.clone()),
+            Mnemonic::Ds => return self.reserve_bytes(operand, instruction.span.clone()),
+            // The operand was already looked up as a byte.
+            Mnemonic::Opcode => return self.handle_dfb(Spanned::new((operand, operand_span))),
             Mnemonic::Sct => return self.change_section(operand, instruction.span),
+            Mnemonic::Sctdef => return self.declare_section(operand, instruction.span),
+            Mnemonic::Fillbyte => return self.change_fill(operand, instruction.span),
             _ => {}
         }
 
+        // `jmp` only has an absolute pointer, so a zeropage one is widened. The other
+        // indirect instructions only have a zeropage pointer, so an absolute one has
+        // to fit in a byte.
+        if let (Mnemonic::Jmp, AddressMode::IndirectZeropage, ActualValue::Byte(byte)) =
+            (mnemonic.val, address_mode, &operand)
+        {
+            address_mode = AddressMode::Indirect;
+            operand = ActualValue::Word(*byte as u16);
+        } else if address_mode == AddressMode::Indirect
+            && self.cpu.opcodes()[mnemonic.val][AddressMode::IndirectZeropage].is_some()
+        {
+            address_mode = AddressMode::IndirectZeropage;
+            operand = match operand {
+                ActualValue::Word(word) if word <= 0xff => ActualValue::Byte(word as u8),
+                ActualValue::Word(_)
+                | ActualValue::Reference(Reference {
+                    modifier: None,
+                    branch: false,
+                    ..
+                }) => {
+                    return Err(AssemblerError {
+                        message: format!(
+                            "The pointer for indirect `{}` has to be in the zeropage",
+                            mnemonic.val
+                        ),
+                        labels: vec![(
+                            Location {
+                                span: operand_span,
+                                file_name: self.include_stack.last().unwrap().0.clone(),
+                            },
+                            None,
+                        )],
+                        help: Some(String::from(
+                            "Use `<` or `>` to take one byte of a label's address",
+                        )),
+                    });
+                }
+                operand => operand,
+            };
+        }
+
+        // `lda #screen` loads part of the address as a value, which is usually meant
+        // to be `lda screen`. Only one byte fits, so it's the low byte.
+        if let (
+            AddressMode::Immediate,
+            ActualValue::Reference(
+                reference @ Reference {
+                    modifier: None,
+                    section_size: false,
+                    section_start: false,
+                    subtrahend: None,
+                    ..
+                },
+            ),
+        ) = (address_mode, &mut operand)
+        {
+            self.warnings.push(Warning {
+                category: WarningCategory::ImmediateAddress,
+                error: AssemblerError {
+                    message: format!(
+                        "The address of `{}` is loaded as an immediate byte",
+                        reference.name
+                    ),
+                    labels: vec![(
+                        Location {
+                            span: operand_span.clone(),
+                            file_name: self.include_stack.last().unwrap().0.clone(),
+                        },
+                        Some(String::from("Only the low byte of the address is used")),
+                    )],
+                    help: Some(format!(
+                        "Use `#>{0}` or `#<{0}` to load the low or high byte of the address, \
+                        or `{0}` without `#` to load from the address",
+                        reference.name
+                    )),
+                },
+            });
+            reference.modifier = Some(Spanned::new((Modifier::LowByte, operand_span.clone())));
+        }
+        // The distance between labels isn't an address, and `$` has no name to load
+        // instead, so they're only narrowed the same way.
+        if let (
+            AddressMode::Immediate,
+            ActualValue::Reference(reference @ Reference { modifier: None, .. }),
+        ) = (address_mode, &mut operand)
+        {
+            if reference.subtrahend.is_some() || reference.section_start {
+                reference.modifier = Some(Spanned::new((Modifier::LowByte, operand_span.clone())));
+            }
+        }
+
         // Insert opcode.
-        if let Some(byte) = OPCODES[mnemonic.val][address_mode] {
+        if let Some(byte) = self.cpu.opcodes()[mnemonic.val][address_mode] {
             self.insert_byte(byte);
+        } else if mnemonic.val.is_implied() {
+            return Err(AssemblerError {
+                message: format!("`{}` takes no operand", mnemonic.val),
+                labels: vec![(
+                    Location {
+                        span: operand_span.clone(),
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    Some(String::from("Unexpected operand")),
+                )],
+                help: Some(
+                    mnemonic_suggestion(self.cpu, mnemonic.val, address_mode)
+                        .unwrap_or_else(|| String::from("Remove the operand")),
+                ),
+            });
         } else {
             return Err(AssemblerError {
                 message: format!(
@@ -253,31 +1156,112 @@ impl<'context> GeneratorContext<'context> {
                     },
                     None,
                 )],
-                help: Some(format!(
-                    "Valid address modes are:\n    {}",
-                    OPCODES[mnemonic.val]
-                        .iter()
-                        .filter(|(_, opcode)| opcode.is_some())
-                        .map(|(mode, _)| mode.string_rep(mnemonic.val))
-                        .collect::<Vec<String>>()
-                        .join("\n    ")
-                )),
+                help: Some(invalid_mode_help(self.cpu, mnemonic.val, address_mode)),
             });
         }
 
-        // Insert operand bytes.
-        Ok(match operand {
-            ActualValue::Byte(byte) => {
-                self.insert_byte(byte);
-                2
+        // The NMOS 6502 doesn't carry into the high byte of the pointer when reading
+        // it, so `jmp ($xxff)` takes the high byte of the target from `$xx00`. The
+        // 65C02 fixed this.
+        if let (Mnemonic::Jmp, AddressMode::Indirect, ActualValue::Word(pointer)) =
+            (mnemonic.val, address_mode, &operand)
+        {
+            if pointer & 0xff == 0xff && self.cpu == Cpu::Nmos6502 {
+                self.warnings.push(Warning {
+                    category: WarningCategory::JmpIndirectBug,
+                    error: AssemblerError {
+                        message: format!(
+                            "`jmp (${:04x})` reads the high byte of its target from `${:04x}`",
+                            pointer,
+                            pointer & 0xff00
+                        ),
+                        labels: vec![(
+                            Location {
+                                span: operand_span.clone(),
+                                file_name: self.include_stack.last().unwrap().0.clone(),
+                            },
+                            None,
+                        )],
+                        help: Some(String::from(
+                            "The 6502 doesn't cross a page when reading an indirect address, \
+                        so move the pointer so it doesn't end in $ff",
+                        )),
+                    },
+                });
             }
-            ActualValue::Word(word) => {
-                self.insert_word(word);
+        }
+
+        // Writing to ROM does nothing, so the address is likely wrong. Only literal
+        // addresses and macros are checked since labels are resolved later.
+        if let (true, ActualValue::Byte(_) | ActualValue::Word(_)) =
+            (mnemonic.val.is_write(), &operand)
+        {
+            let address = match operand {
+                ActualValue::Byte(byte) => byte as u16,
+                ActualValue::Word(word) => word,
+                _ => unreachable!(),
+            };
+            if let Some(rom_range) = self
+                .rom_ranges
+                .iter()
+                .find(|rom_range| rom_range.contains(&address))
+            {
+                self.warnings.push(Warning {
+                    category: WarningCategory::RomWrite,
+                    error: AssemblerError {
+                        message: format!("`{}` writes to ROM at `${:04x}`", mnemonic.val, address),
+                        labels: vec![(
+                            Location {
+                                span: operand_span.clone(),
+                                file_name: self.include_stack.last().unwrap().0.clone(),
+                            },
+                            None,
+                        )],
+                        help: Some(format!(
+                            "${:04x}-${:04x} was declared ROM with --rom-range",
+                            rom_range.start(),
+                            rom_range.end()
+                        )),
+                    },
+                });
+            }
+        }
+
+        let target = match &operand {
+            _ if address_mode == AddressMode::Immediate => None,
+            ActualValue::Byte(byte) => Some(Target::Address(*byte as u16)),
+            ActualValue::Word(word) => Some(Target::Address(*word)),
+            ActualValue::Reference(reference) => Some(Target::Label(
+                reference.name.clone(),
+                reference
+                    .modifier
+                    .as_ref()
+                    .map(|modifier| modifier.val.clone()),
+            )),
+            _ => None,
+        };
+        self.check_redundancy(
+            previous_instruction,
+            RecentInstruction {
+                mnemonic: mnemonic.val,
+                target,
+                location: instruction_location,
+            },
+        );
+
+        // Insert operand bytes.
+        Ok(match operand {
+            ActualValue::Byte(byte) => {
+                self.insert_byte(byte);
+                2
+            }
+            ActualValue::Word(word) => {
+                self.insert_word(word);
                 3
             }
             ActualValue::String(_) => {
                 return Err(AssemblerError {
-                    message: format!(
+                    message: String::from(
                         "String operands are only allowed for the `dfb` and `inl` directives",
                     ),
                     labels: vec![(
@@ -306,12 +1290,72 @@ impl<'context> GeneratorContext<'context> {
         })
     }
 
+    /// Warn if an instruction undoes or repeats the effect of the one right before it,
+    /// then remember it for the next instruction.
+    fn check_redundancy(
+        &mut self,
+        previous: Option<RecentInstruction>,
+        current: RecentInstruction,
+    ) {
+        if !self.lint {
+            return;
+        }
+
+        if let Some(previous) = previous {
+            let message = match (previous.mnemonic, current.mnemonic) {
+                (Mnemonic::Tax, Mnemonic::Txa)
+                | (Mnemonic::Txa, Mnemonic::Tax)
+                | (Mnemonic::Tay, Mnemonic::Tya)
+                | (Mnemonic::Tya, Mnemonic::Tay)
+                | (Mnemonic::Tsx, Mnemonic::Txs)
+                | (Mnemonic::Txs, Mnemonic::Tsx) => Some(format!(
+                    "`{}` undoes the `{}` before it",
+                    current.mnemonic, previous.mnemonic
+                )),
+                (Mnemonic::Lda, Mnemonic::Sta)
+                    if previous.target.is_some() && previous.target == current.target =>
+                {
+                    Some(String::from(
+                        "`sta` stores the value that was just loaded from the same address",
+                    ))
+                }
+                _ => None,
+            };
+
+            if let Some(message) = message {
+                self.warnings.push(Warning {
+                    category: WarningCategory::Redundant,
+                    error: AssemblerError {
+                        message,
+                        labels: vec![
+                            (current.location.clone(), None),
+                            (
+                                previous.location,
+                                Some(String::from("Previous instruction")),
+                            ),
+                        ],
+                        help: Some(String::from("One or both instructions may be unnecessary")),
+                    },
+                });
+            }
+        }
+
+        self.last_instruction = Some(current);
+    }
+
     fn handle_implied_instruction(
         &mut self,
         mnemonic: Mnemonic,
         instruction_span: Range<usize>,
     ) -> Result<usize, AssemblerError> {
-        if let Some(byte) = OPCODES[mnemonic][AddressMode::Implied] {
+        if let Some(byte) = self.cpu.opcodes()[mnemonic][AddressMode::Implied] {
+            self.insert_byte(byte);
+            Ok(1)
+        } else if let (true, Some(byte)) = (
+            self.implicit_accumulator,
+            self.cpu.opcodes()[mnemonic][AddressMode::Accumulator],
+        ) {
+            // Shifts and rotates without an operand act on the accumulator.
             self.insert_byte(byte);
             Ok(1)
         } else {
@@ -328,19 +1372,46 @@ impl<'context> GeneratorContext<'context> {
                     },
                     None,
                 )],
-                help: Some(format!(
-                    "Valid address modes are:\n    {}",
-                    OPCODES[mnemonic]
-                        .iter()
-                        .filter(|(_, opcode)| opcode.is_some())
-                        .map(|(mode, _)| mode.string_rep(mnemonic))
-                        .collect::<Vec<String>>()
-                        .join("\n    ")
-                )),
+                help: Some(invalid_mode_help(self.cpu, mnemonic, AddressMode::Implied)),
             })
         }
     }
 
+    /// A label's address depends on where position independent code is loaded,
+    /// so only the offsets in branches can be used.
+    fn check_position_independence(
+        &self,
+        mnemonic: Mnemonic,
+        operand: &ActualValue,
+    ) -> Result<(), AssemblerError> {
+        if let (true, ActualValue::Reference(reference)) = (self.pic, operand) {
+            if !reference.branch && !reference.section_size {
+                return Err(AssemblerError {
+                    message: if mnemonic == Mnemonic::Dfw {
+                        format!(
+                            "Address table entry `{}` is absolute in position independent code",
+                            reference.name
+                        )
+                    } else {
+                        format!(
+                            "Absolute reference to `{}` in position independent code",
+                            reference.name
+                        )
+                    },
+                    labels: vec![(reference.location.clone(), None)],
+                    help: Some(String::from(if mnemonic == Mnemonic::Dfw {
+                        "A table of addresses is only correct at one load address, so \
+                        dispatch with a chain of comparisons and branches instead"
+                    } else {
+                        "Use a branch to reach the label, since branches are relative"
+                    })),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     fn handle_dfb(&mut self, operand: Spanned<ActualValue>) -> Result<usize, AssemblerError> {
         let operand_location = Location {
             span: operand.span.clone(),
@@ -450,21 +1521,35 @@ impl<'context> GeneratorContext<'context> {
         // Take the label from this line as the macro name.
         let macro_name = self.object[self.current_section].labels.pop().unwrap().name;
 
-        if self.macros.contains_key(&macro_name) {
+        if let Some(defined) = self.macros.get(&macro_name) {
+            let location = defined.location().clone();
+            // A definition from the command line has no source to point to.
+            let (labels, help) = if location == command_line_location() {
+                (
+                    vec![(directive_location, None)],
+                    Some(format!("`{}` was defined with --define", macro_name)),
+                )
+            } else {
+                (
+                    vec![
+                        (directive_location, None),
+                        (location, Some(String::from("Already defined here"))),
+                    ],
+                    None,
+                )
+            };
             return Err(AssemblerError {
                 message: format!("The macro `{}` has already been defined", macro_name),
-                labels: vec![
-                    (directive_location, None),
-                    (
-                        match &self.macros[&macro_name] {
-                            Macro::Byte(_, location) => location.clone(),
-                            Macro::Word(_, location) => location.clone(),
-                            Macro::String(_, location) => location.clone(),
-                        },
-                        Some(String::from("Already defined here")),
-                    ),
-                ],
-                help: None,
+                labels,
+                help,
+            });
+        }
+
+        if self.macros.len() >= self.max_macros {
+            return Err(AssemblerError {
+                message: format!("Can't define more than {} macros", self.max_macros),
+                labels: vec![(directive_location, None)],
+                help: Some(String::from("The limit can be raised with --max-macros")),
             });
         }
 
@@ -506,19 +1591,7 @@ impl<'context> GeneratorContext<'context> {
         }
 
         match operand {
-            ActualValue::Word(word) => {
-                if word as usize > self.object[self.current_section].origin {
-                    self.object[self.current_section].highest_origin = word as usize;
-                }
-                self.object[self.current_section].origin = word as usize;
-                self.current_line_section_start = self.object[self.current_section].origin;
-
-                if self.object[self.current_section].lowest_origin.is_none()
-                    || (word as usize) < self.object[self.current_section].lowest_origin.unwrap()
-                {
-                    self.object[self.current_section].lowest_origin = Some(word as usize);
-                }
-            }
+            ActualValue::Word(word) => self.set_origin(word as usize),
             _ => {
                 return Err(AssemblerError {
                     message: String::from("The `org` directive expects a word operand"),
@@ -531,286 +1604,2206 @@ impl<'context> GeneratorContext<'context> {
         Ok(0)
     }
 
-    fn change_section(
+    /// Move the origin of the current section to an address.
+    fn set_origin(&mut self, address: usize) {
+        if address > self.object[self.current_section].origin {
+            self.object[self.current_section].highest_origin = address;
+        }
+        self.object[self.current_section].origin = address;
+        self.current_line_section_start = self.object[self.current_section].origin;
+
+        if self.object[self.current_section].lowest_origin.is_none()
+            || address < self.object[self.current_section].lowest_origin.unwrap()
+        {
+            self.object[self.current_section].lowest_origin = Some(address);
+        }
+    }
+
+    /// Skip the given number of bytes without putting anything in them.
+    fn reserve_bytes(
         &mut self,
         operand: ActualValue,
         instruction_span: Range<usize>,
     ) -> Result<usize, AssemblerError> {
         let directive_location = Location {
-            span: instruction_span.clone(),
+            span: instruction_span,
             file_name: self.include_stack.last().unwrap().0.clone(),
         };
 
-        if self.binary {
-            return Err(AssemblerError {
-                message: String::from("The `sct` directive is not allowed in binary mode"),
-                labels: vec![(directive_location, None)],
-                help: None,
-            });
-        }
-
-        match operand {
-            ActualValue::String(name) => {
-                let section_index = self.object.iter().position(|section| section.name == *name);
-                if let Some(index) = section_index {
-                    self.current_section = index;
-                } else {
-                    if self.object.len() == 1 {
-                        if self.object[self.current_section].origin == 0 {
-                            self.object[0].name = name.clone();
-                        } else {
-                            return Err(AssemblerError {
-                                message: String::from(
-                                    "The `sct` directive must appear before any code",
-                                ),
-                                labels: vec![(directive_location, None)],
-                                help: None,
-                            });
-                        }
-                    } else {
-                        self.current_section = self.object.len();
-                        self.object.push(Section {
-                            name: name.clone(),
-                            data: [0; 65536],
-                            origin: 0,
-                            lowest_origin: None,
-                            highest_origin: 0,
-                            used_ranges: Vec::with_capacity(8),
-                            labels: Vec::with_capacity(64),
-                            references: Vec::with_capacity(128),
-                        });
-                    }
-                }
-            }
+        let count = match operand {
+            ActualValue::Byte(byte) => byte as usize,
+            ActualValue::Word(word) => word as usize,
             _ => {
                 return Err(AssemblerError {
-                    message: String::from("The `sct` directive expects a string operand"),
+                    message: String::from("The `ds` directive expects a number of bytes"),
                     labels: vec![(directive_location, None)],
                     help: None,
                 });
             }
+        };
+
+        let section = &mut self.object[self.current_section];
+        let start = section.origin;
+        if start + count > MEMORY_SIZE {
+            return Err(AssemblerError {
+                message: format!(
+                    "Reserving {} bytes at ${:04x} goes past the end of memory",
+                    count, start
+                ),
+                labels: vec![(directive_location, None)],
+                help: None,
+            });
+        }
+
+        if section.lowest_origin.is_none() {
+            section.lowest_origin = Some(start);
+        }
+        section.origin += count;
+        section.highest_origin = section.highest_origin.max(section.origin);
+        if count > 0 {
+            section.reserved_ranges.push(start..section.origin);
         }
 
+        // Nothing was put in the section for the listing to show.
         Ok(0)
     }
 
-    fn create_listing_line(&mut self, line_end: usize) {
-        self.listing.push(Listing {
-            location: Some((
-                self.current_section,
-                self.current_line_section_start,
-                self.current_line_section_end,
+    /// Fill bytes until the origin is a multiple of the boundary.
+    fn align(
+        &mut self,
+        boundary: Spanned<Value>,
+        fill: Option<Spanned<Value>>,
+        name: Option<Spanned<String>>,
+        instruction_span: Range<usize>,
+    ) -> Result<usize, AssemblerError> {
+        let boundary_span = boundary.span.clone();
+        let boundary = match self.resolve_operand(
+            Spanned::new((
+                Operand {
+                    mode: OperandMode::Address,
+                    modifier: None,
+                    value: boundary,
+                },
+                boundary_span.clone(),
             )),
-            code: String::from(
-                self.files
-                    .get(self.id_table[&self.include_stack.last().unwrap().0])
-                    .unwrap()
-                    .source()[self.current_line_source_start..line_end]
-                    .trim_end(),
-            ),
-        });
-    }
-
-    fn handle_label(&mut self, spanned_label: Spanned<Label>) -> Result<(), ()> {
-        match spanned_label.val {
-            Label::Top(top_label) => {
-                self.macro_valid = true;
-
-                // Expect subsequent sublabels to go under this parent label.
-                self.last_parent_label = Some((
-                    Spanned::new((top_label.name.clone(), spanned_label.span)),
-                    self.include_stack.last().unwrap().0.clone(),
-                ));
-
-                // And store it.
-                let label_offset = self.object[self.current_section].origin;
-                self.object[self.current_section].labels.push(SectionLabel {
-                    name: top_label.name,
-                    visibility: top_label.visibility,
-                    offset: label_offset,
+            false,
+        )? {
+            (
+                Spanned {
+                    val: ActualValue::Byte(byte),
+                    ..
+                },
+                _,
+            ) => byte as usize,
+            (
+                Spanned {
+                    val: ActualValue::Word(word),
+                    ..
+                },
+                _,
+            ) => word as usize,
+            _ => {
+                return Err(AssemblerError {
+                    message: String::from("The `align` directive expects a number of bytes"),
+                    labels: vec![(
+                        Location {
+                            span: boundary_span,
+                            file_name: self.include_stack.last().unwrap().0.clone(),
+                        },
+                        None,
+                    )],
+                    help: None,
                 });
             }
-            Label::Sub((parent_label, sublabel)) => {
-                // Expect a parent label to exist first.
-                let current_parent = if let Some(ref parent_label) = self.last_parent_label {
-                    parent_label
+        };
+
+        if boundary == 0 || (self.strict_alignment && !boundary.is_power_of_two()) {
+            return Err(AssemblerError {
+                message: format!("Can't align to a boundary of {} bytes", boundary),
+                labels: vec![(
+                    Location {
+                        span: boundary_span,
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    None,
+                )],
+                help: Some(String::from(if boundary == 0 {
+                    "The boundary has to be at least 1"
                 } else {
-                    self.errors.push(AssemblerError {
-                        message: String::from("No parent label to put sublabel under"),
-                        labels: vec![(
-                            Location {
-                                span: sublabel.span,
-                                file_name: self.include_stack.last().unwrap().0.clone(),
-                            },
-                            None,
-                        )],
-                        help: Some(format!(
-                            "Did you mean to create a parent label `{}`?",
-                            sublabel.val,
-                        )),
-                    });
+                    "The boundary has to be a power of two because of --strict-align"
+                })),
+            });
+        }
 
-                    return Err(());
-                };
+        let (fill, name) = self.padding_name(fill, name);
+        let fill = self.fill_byte(fill, "align")?;
 
-                if let Some(parent_label) = &parent_label {
-                    // Make sure explicit parent label matches acual parent.
-                    if &parent_label.val != &current_parent.0.val {
-                        self.errors.push(AssemblerError {
-                            message: String::from(
-                                "Explicit parent label does not match most recent parent label",
-                            ),
-                            labels: vec![
-                                (
-                                    Location {
-                                        span: parent_label.span.clone(),
-                                        file_name: self.include_stack.last().unwrap().0.clone(),
-                                    },
-                                    Some(format!("Found `{}`", parent_label.val)),
-                                ),
-                                (
-                                    Location {
-                                        span: current_parent.0.span.clone(),
-                                        file_name: current_parent.1.clone(),
-                                    },
-                                    Some(format!("Expected `{}`", current_parent.0.val)),
-                                ),
-                            ],
-                            help: Some(format!(
-                                "Change `{}` to `{}`",
-                                parent_label.val, current_parent.0.val
-                            )),
-                        });
+        let section = &mut self.object[self.current_section];
+        let start = section.origin;
+        let count = (boundary - start % boundary) % boundary;
+        if start + count > MEMORY_SIZE {
+            return Err(AssemblerError {
+                message: format!(
+                    "Aligning ${:04x} to {} bytes goes past the end of memory",
+                    start, boundary
+                ),
+                labels: vec![(
+                    Location {
+                        span: instruction_span,
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            });
+        }
+        // An object's offsets only stay aligned if the linker places it on the boundary.
+        section.alignment = section.alignment.max(boundary);
 
-                        return Err(());
-                    } else {
-                        let offset = self.object[self.current_section].origin;
-                        self.object[self.current_section].labels.push(SectionLabel {
-                            name: format!("{}.{}", parent_label.val, sublabel.val),
-                            visibility: Visibility::Object,
-                            offset,
-                        });
-                    }
-                } else {
-                    let offset = self.object[self.current_section].origin;
-                    self.object[self.current_section].labels.push(SectionLabel {
-                        name: format!("{}.{}", current_parent.0.val, sublabel.val),
-                        visibility: Visibility::Object,
-                        offset,
-                    });
-                }
-            }
+        if let Some(name) = name {
+            self.define_padding_label(name, start)?;
+        }
+        for _ in 0..count {
+            self.insert_byte(fill);
         }
 
-        Ok(())
+        Ok(count)
     }
 
-    /// Get the bytes of an operand, resolving a reference to a macro if possible,
+    /// Resolve the optional fill byte of `align` or `dfs`, which defaults to the one
+    /// set by `fillbyte`.
+    fn fill_byte(
+        &mut self,
+        fill: Option<Spanned<Value>>,
+        directive: &str,
+    ) -> Result<u8, AssemblerError> {
+        let fill = match fill {
+            None => return Ok(self.fill),
+            Some(fill) => fill,
+        };
+        let fill_span = fill.span.clone();
+        match self.resolve_operand(
+            Spanned::new((
+                Operand {
+                    mode: OperandMode::Address,
+                    modifier: None,
+                    value: fill,
+                },
+                fill_span.clone(),
+            )),
+            false,
+        )? {
+            (
+                Spanned {
+                    val: ActualValue::Byte(byte),
+                    ..
+                },
+                _,
+            ) => Ok(byte),
+            _ => Err(AssemblerError {
+                message: format!("The `{}` directive expects a fill byte", directive),
+                labels: vec![(
+                    Location {
+                        span: fill_span,
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            }),
+        }
+    }
+
+    /// Set the byte that later `align` and `dfs` directives fill with when they aren't
+    /// given one. `ds` only reserves space, so it isn't affected.
+    fn change_fill(
+        &mut self,
+        operand: ActualValue,
+        instruction_span: Range<usize>,
+    ) -> Result<usize, AssemblerError> {
+        match operand {
+            ActualValue::Byte(byte) => self.fill = byte,
+            _ => {
+                return Err(AssemblerError {
+                    message: String::from("The `fillbyte` directive expects a byte"),
+                    labels: vec![(
+                        Location {
+                            span: instruction_span,
+                            file_name: self.include_stack.last().unwrap().0.clone(),
+                        },
+                        None,
+                    )],
+                    help: None,
+                })
+            }
+        }
+
+        // Nothing was put in the section for the listing to show.
+        Ok(0)
+    }
+
+    /// Record the label that the program starts at, which is written in the object for
+    /// the linker. Only one may be given.
+    fn set_entry(
+        &mut self,
+        operand: Option<Spanned<Operand>>,
+        instruction_span: Range<usize>,
+    ) -> Result<usize, AssemblerError> {
+        let directive_location = Location {
+            span: instruction_span,
+            file_name: self.include_stack.last().unwrap().0.clone(),
+        };
+
+        let name = match operand {
+            Some(Spanned {
+                val:
+                    Operand {
+                        mode: OperandMode::Address,
+                        modifier: None,
+                        value:
+                            Spanned {
+                                val: Value::Reference(name),
+                                span,
+                            },
+                    },
+                span: _,
+            }) => {
+                self.record_use(&name, span);
+                name
+            }
+            _ => {
+                return Err(AssemblerError {
+                    message: String::from("The `entry` directive expects a label"),
+                    labels: vec![(directive_location, None)],
+                    help: None,
+                })
+            }
+        };
+
+        if let Some((_, first_location)) = &self.entry {
+            return Err(AssemblerError {
+                message: String::from("The entry point is given more than once"),
+                labels: vec![
+                    (directive_location, None),
+                    (
+                        first_location.clone(),
+                        Some(String::from("First given here")),
+                    ),
+                ],
+                help: None,
+            });
+        }
+
+        self.entry = Some((name, directive_location));
+        Ok(0)
+    }
+
+    /// Fill the given number of bytes with the fill byte, which is the one set by
+    /// `fillbyte` if not given.
+    fn fill(
+        &mut self,
+        count: Spanned<Value>,
+        fill: Option<Spanned<Value>>,
+        name: Option<Spanned<String>>,
+        instruction_span: Range<usize>,
+    ) -> Result<usize, AssemblerError> {
+        let count_span = count.span.clone();
+        let count = match self.resolve_operand(
+            Spanned::new((
+                Operand {
+                    mode: OperandMode::Address,
+                    modifier: None,
+                    value: count,
+                },
+                count_span.clone(),
+            )),
+            false,
+        )? {
+            (
+                Spanned {
+                    val: ActualValue::Byte(byte),
+                    ..
+                },
+                _,
+            ) => byte as usize,
+            (
+                Spanned {
+                    val: ActualValue::Word(word),
+                    ..
+                },
+                _,
+            ) => word as usize,
+            _ => {
+                return Err(AssemblerError {
+                    message: String::from("The `dfs` directive expects a number of bytes"),
+                    labels: vec![(
+                        Location {
+                            span: count_span,
+                            file_name: self.include_stack.last().unwrap().0.clone(),
+                        },
+                        None,
+                    )],
+                    help: None,
+                });
+            }
+        };
+
+        let (fill, name) = self.padding_name(fill, name);
+        let fill = self.fill_byte(fill, "dfs")?;
+
+        let section = &self.object[self.current_section];
+        let start = section.origin;
+        if start + count > MEMORY_SIZE {
+            let remaining = MEMORY_SIZE - start;
+            return Err(AssemblerError {
+                message: format!(
+                    "Filling {} bytes at ${:04x} goes past the end of memory",
+                    count, start
+                ),
+                labels: vec![(
+                    Location {
+                        span: instruction_span,
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    None,
+                )],
+                help: Some(format!(
+                    "Only {} byte{} can be filled before the end of memory",
+                    remaining,
+                    if remaining == 1 { "" } else { "s" }
+                )),
+            });
+        }
+
+        if let Some(name) = name {
+            self.define_padding_label(name, start)?;
+        }
+        for _ in 0..count {
+            self.insert_byte(fill);
+        }
+
+        Ok(count)
+    }
+
+    /// Insert the date of the build as `yyyy-mm-dd` in ASCII, or as packed BCD bytes
+    /// of the century, year, month, and day with `datestamp "bcd"`. The bytes are
+    /// zeros in deterministic builds.
+    fn datestamp(
+        &mut self,
+        operand: Option<Spanned<Operand>>,
+        instruction_span: Range<usize>,
+    ) -> Result<usize, AssemblerError> {
+        let bcd = match operand {
+            None => false,
+            Some(Spanned {
+                val:
+                    Operand {
+                        mode: OperandMode::Address,
+                        modifier: None,
+                        value:
+                            Spanned {
+                                val: Value::String(format),
+                                span: _,
+                            },
+                    },
+                span: _,
+            }) if format.eq_ignore_ascii_case("ascii") || format.eq_ignore_ascii_case("bcd") => {
+                format.eq_ignore_ascii_case("bcd")
+            }
+            Some(operand) => {
+                return Err(AssemblerError {
+                    message: String::from("The `datestamp` directive expects a date format"),
+                    labels: vec![(
+                        Location {
+                            span: operand.span,
+                            file_name: self.include_stack.last().unwrap().0.clone(),
+                        },
+                        None,
+                    )],
+                    help: Some(String::from(
+                        "Use `\"ascii\"` for `yyyy-mm-dd` or `\"bcd\"` for packed BCD bytes",
+                    )),
+                });
+            }
+        };
+
+        let (year, month, day) = binary::civil_date();
+        let bytes: Vec<u8> = if bcd {
+            [(year / 100) as u8, (year % 100) as u8, month, day]
+                .iter()
+                .map(|part| ((part / 10) << 4) | (part % 10))
+                .collect()
+        } else {
+            format!("{:04}-{:02}-{:02}", year, month, day).into_bytes()
+        };
+
+        let section = &self.object[self.current_section];
+        if section.origin + bytes.len() > MEMORY_SIZE {
+            return Err(AssemblerError {
+                message: format!(
+                    "The date at ${:04x} goes past the end of memory",
+                    section.origin
+                ),
+                labels: vec![(
+                    Location {
+                        span: instruction_span,
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            });
+        }
+
+        for byte in &bytes {
+            self.insert_byte(if self.deterministic { 0 } else { *byte });
+        }
+
+        Ok(bytes.len())
+    }
+
+    /// Split the name for the padding of `align` or `dfs` from its fill byte. With
+    /// only one argument after the size, a symbol that isn't a macro names the
+    /// padding, like `align 256, padding`, instead of being a label to fill with.
+    fn padding_name(
+        &self,
+        fill: Option<Spanned<Value>>,
+        name: Option<Spanned<String>>,
+    ) -> (Option<Spanned<Value>>, Option<Spanned<String>>) {
+        match (fill, name) {
+            (
+                Some(Spanned {
+                    val: Value::Reference(symbol),
+                    span,
+                }),
+                None,
+            ) if !self.macros.contains_key(&symbol) => (None, Some(Spanned::new((symbol, span)))),
+            (fill, name) => (fill, name),
+        }
+    }
+
+    /// Define a label at the start of the padding inserted by `align` or `dfs` so
+    /// symbol tables show where the space went.
+    fn define_padding_label(
+        &mut self,
+        name: Spanned<String>,
+        offset: usize,
+    ) -> Result<(), AssemblerError> {
+        let section = &self.object[self.current_section];
+        if section.labels.len() >= self.max_labels {
+            return Err(AssemblerError {
+                message: format!(
+                    "Can't define more than {} labels in section `{}`",
+                    self.max_labels, section.name
+                ),
+                labels: vec![(
+                    Location {
+                        span: name.span,
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    None,
+                )],
+                help: Some(String::from("The limit can be raised with --max-labels")),
+            });
+        }
+
+        self.check_duplicate_label(&name.val, name.span.clone())?;
+        self.record_definition(&name.val, name.span);
+        self.object[self.current_section].labels.push(SectionLabel {
+            name: name.val,
+            visibility: Visibility::Object,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// Assemble the body of a `rept` block the given number of times, right after
+    /// the line with `rept`.
+    fn repeat(&mut self, count: Spanned<Value>, body: Program) -> Result<usize, AssemblerError> {
+        let count_span = count.span.clone();
+        let count = match self.resolve_operand(
+            Spanned::new((
+                Operand {
+                    mode: OperandMode::Address,
+                    modifier: None,
+                    value: count,
+                },
+                count_span.clone(),
+            )),
+            false,
+        )? {
+            (
+                Spanned {
+                    val: ActualValue::Byte(byte),
+                    ..
+                },
+                _,
+            ) => byte as u16,
+            (
+                Spanned {
+                    val: ActualValue::Word(word),
+                    ..
+                },
+                _,
+            ) => word,
+            _ => {
+                return Err(AssemblerError {
+                    message: String::from("The `rept` directive expects a number of times"),
+                    labels: vec![(
+                        Location {
+                            span: count_span,
+                            file_name: self.include_stack.last().unwrap().0.clone(),
+                        },
+                        None,
+                    )],
+                    help: None,
+                });
+            }
+        };
+
+        let mut copies = Program::with_capacity(body.len() * count as usize);
+        for counter in 0..count {
+            let mut copy = body.clone();
+            replace_repeat_counters(&mut copy, counter);
+            copies.append(&mut copy);
+        }
+        self.insert_after_line(copies);
+
+        Ok(0)
+    }
+
+    /// Assemble the block after `if` if the condition isn't zero, or the block after
+    /// `else` if it is, right after the `if` line.
+    fn conditional(
+        &mut self,
+        condition: Spanned<Value>,
+        then_body: Program,
+        else_body: Program,
+    ) -> Result<usize, AssemblerError> {
+        let condition_span = condition.span.clone();
+        let condition = match self.resolve_operand(
+            Spanned::new((
+                Operand {
+                    mode: OperandMode::Address,
+                    modifier: None,
+                    value: condition,
+                },
+                condition_span.clone(),
+            )),
+            false,
+        )? {
+            (
+                Spanned {
+                    val: ActualValue::Byte(byte),
+                    ..
+                },
+                _,
+            ) => byte != 0,
+            (
+                Spanned {
+                    val: ActualValue::Word(word),
+                    ..
+                },
+                _,
+            ) => word != 0,
+            _ => {
+                return Err(AssemblerError {
+                    message: String::from("The `if` directive expects a number"),
+                    labels: vec![(
+                        Location {
+                            span: condition_span,
+                            file_name: self.include_stack.last().unwrap().0.clone(),
+                        },
+                        None,
+                    )],
+                    help: Some(String::from(
+                        "The condition has to be known where the `if` is, \
+                        so it can only use numbers and macros defined before it",
+                    )),
+                });
+            }
+        };
+
+        self.insert_after_line(if condition { then_body } else { else_body });
+
+        Ok(0)
+    }
+
+    /// Put actions into the program right after the current line, so the rest of the
+    /// line comes first and the listing stays in order.
+    fn insert_after_line(&mut self, mut actions: Program) {
+        let mut remaining = self.program.by_ref().collect::<Vec<Action>>();
+        let line_end = remaining
+            .iter()
+            .position(|action| matches!(action, Action::LineEnd(_)))
+            .map_or(0, |index| index + 1);
+        let after_line = remaining.split_off(line_end);
+
+        remaining.append(&mut actions);
+        remaining.extend(after_line);
+        self.program = remaining.into_iter().peekable();
+    }
+
+    /// Look up the address a section is placed at by the layout script.
+    fn script_address(&self, name: &str) -> Option<usize> {
+        self.script?
+            .iter()
+            .find(|placement| placement.name == name)
+            .map(|placement| placement.address)
+    }
+
+    fn declare_section(
+        &mut self,
+        operand: ActualValue,
+        instruction_span: Range<usize>,
+    ) -> Result<usize, AssemblerError> {
+        let directive_location = Location {
+            span: instruction_span,
+            file_name: self.include_stack.last().unwrap().0.clone(),
+        };
+
+        let name = if let ActualValue::String(name) = operand {
+            name
+        } else {
+            return Err(AssemblerError {
+                message: String::from("The `sctdef` directive expects a string operand"),
+                labels: vec![(directive_location, None)],
+                help: None,
+            });
+        };
+
+        if let Some(first_location) = self.declared_sections.get(&name) {
+            return Err(AssemblerError {
+                message: format!("Section `{}` is declared more than once", name),
+                labels: vec![
+                    (directive_location, None),
+                    (
+                        first_location.clone(),
+                        Some(String::from("First declared here")),
+                    ),
+                ],
+                help: None,
+            });
+        }
+
+        self.declared_sections.insert(name, directive_location);
+        Ok(0)
+    }
+
+    fn change_section(
+        &mut self,
+        operand: ActualValue,
+        instruction_span: Range<usize>,
+    ) -> Result<usize, AssemblerError> {
+        let directive_location = Location {
+            span: instruction_span.clone(),
+            file_name: self.include_stack.last().unwrap().0.clone(),
+        };
+
+        if self.binary && self.script.is_none() {
+            return Err(AssemblerError {
+                message: String::from("The `sct` directive is not allowed in binary mode"),
+                labels: vec![(directive_location, None)],
+                help: Some(String::from(
+                    "Use a layout script with `--script` to place sections in binary mode",
+                )),
+            });
+        }
+
+        match operand {
+            ActualValue::String(name)
+                if self.strict_sections && !self.declared_sections.contains_key(&name) =>
+            {
+                let suggestion =
+                    suggest_symbol(&name, self.declared_sections.keys().map(String::as_str));
+                return Err(AssemblerError {
+                    message: format!("Section `{}` has not been declared", name),
+                    labels: vec![(directive_location, None)],
+                    help: Some(if let Some(declared) = suggestion {
+                        format!("Did you mean `{}`?", declared)
+                    } else {
+                        format!("Declare it first with `sctdef \"{}\"`", name)
+                    }),
+                });
+            }
+            ActualValue::String(name) => {
+                let section_index = self.object.iter().position(|section| section.name == *name);
+                if let Some(index) = section_index {
+                    self.current_section = index;
+                } else if self.binary {
+                    // The layout script decides where the new section starts.
+                    let address = if let Some(address) = self.script_address(&name) {
+                        address
+                    } else {
+                        return Err(AssemblerError {
+                            message: format!(
+                                "Section `{}` is not placed by the layout script",
+                                name
+                            ),
+                            labels: vec![(directive_location, None)],
+                            help: None,
+                        });
+                    };
+
+                    // Reuse the default section if nothing was put in it.
+                    let default = &self.object[0];
+                    if self.object.len() == 1
+                        && default.highest_origin == default.lowest_origin.unwrap_or(0)
+                        && default.labels.is_empty()
+                    {
+                        self.object[0].name = name.clone();
+                        self.object[0].location = Some(directive_location.clone());
+                        self.object[0].origin = 0;
+                        self.object[0].lowest_origin = None;
+                        self.object[0].highest_origin = 0;
+                    } else {
+                        self.current_section = self.object.len();
+                        self.object
+                            .push(Section::new(name.clone(), Some(directive_location.clone())));
+                    }
+                    self.set_origin(address);
+                } else {
+                    // The implicit default section has no location, and it is replaced
+                    // by the first declared section if nothing was put in it.
+                    if self.object.len() == 1 && self.object[0].location.is_none() {
+                        if self.object[self.current_section].origin == 0 {
+                            self.object[0].name = name.clone();
+                            self.object[0].location = Some(directive_location.clone());
+                        } else {
+                            return Err(AssemblerError {
+                                message: String::from(
+                                    "The `sct` directive must appear before any code",
+                                ),
+                                labels: vec![(directive_location, None)],
+                                help: None,
+                            });
+                        }
+                    } else {
+                        self.current_section = self.object.len();
+                        self.object
+                            .push(Section::new(name.clone(), Some(directive_location.clone())));
+                    }
+                }
+            }
+            _ => {
+                return Err(AssemblerError {
+                    message: String::from("The `sct` directive expects a string operand"),
+                    labels: vec![(directive_location, None)],
+                    help: Some(String::from(
+                        "The section name can be a string or a macro defined as a string",
+                    )),
+                });
+            }
+        }
+
+        // The line is listed at its offset into the section it switched to.
+        self.current_line_section_start = self.object[self.current_section].origin;
+        self.current_line_section_end = self.current_line_section_start;
+
+        Ok(0)
+    }
+
+    fn create_listing_line(&mut self, line_end: usize) {
+        let file_name = &self.include_stack.last().unwrap().0;
+        let file_id = self.id_table[file_name];
+        let line_number = self
+            .files
+            .line_index(file_id, self.current_line_source_start)
+            .unwrap_or(0)
+            + 1;
+
+        self.listing.push(Listing {
+            location: Some((
+                self.current_section,
+                self.current_line_section_start,
+                self.current_line_section_end,
+            )),
+            source_line: Some((file_name.clone(), line_number)),
+            code: String::from(
+                self.files.get(file_id).unwrap().source()[self.current_line_source_start..line_end]
+                    .trim_end(),
+            ),
+            numbers: std::mem::take(&mut self.current_line_numbers)
+                .into_iter()
+                .map(|(span, number)| {
+                    (
+                        span.start - self.current_line_source_start
+                            ..span.end - self.current_line_source_start,
+                        number,
+                    )
+                })
+                .collect(),
+            notes: vec![],
+        });
+    }
+
+    fn handle_label(&mut self, spanned_label: Spanned<Label>) -> Result<(), ()> {
+        let section = &self.object[self.current_section];
+        if section.labels.len() >= self.max_labels {
+            self.errors.push(AssemblerError {
+                message: format!(
+                    "Can't define more than {} labels in section `{}`",
+                    self.max_labels, section.name
+                ),
+                labels: vec![(
+                    Location {
+                        span: spanned_label.span,
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    None,
+                )],
+                help: Some(String::from("The limit can be raised with --max-labels")),
+            });
+            return Err(());
+        }
+
+        match spanned_label.val {
+            Label::Top(top_label) => {
+                // Addresses are only known in binary mode.
+                if let (Some(address), false) = (&top_label.address, self.binary) {
+                    self.errors.push(AssemblerError {
+                        message: String::from("Labels can only be given an address in binary mode"),
+                        labels: vec![(
+                            Location {
+                                span: address.span.clone(),
+                                file_name: self.include_stack.last().unwrap().0.clone(),
+                            },
+                            None,
+                        )],
+                        help: None,
+                    });
+                    return Err(());
+                }
+
+                self.macro_valid = true;
+
+                // Expect subsequent sublabels to go under this parent label.
+                self.last_parent_label = Some((
+                    Spanned::new((top_label.name.clone(), spanned_label.span.clone())),
+                    self.include_stack.last().unwrap().0.clone(),
+                ));
+
+                if let Err(error) =
+                    self.check_duplicate_label(&top_label.name, spanned_label.span.clone())
+                {
+                    self.errors.push(error);
+                    return Err(());
+                }
+
+                // And store it.
+                let label_offset = top_label
+                    .address
+                    .map_or(self.object[self.current_section].origin, |address| {
+                        address.val as usize
+                    });
+                self.record_definition(&top_label.name, spanned_label.span.clone());
+                self.object[self.current_section].labels.push(SectionLabel {
+                    name: top_label.name,
+                    visibility: top_label.visibility,
+                    offset: label_offset,
+                });
+            }
+            Label::Sub((parent_label, sublabel)) => {
+                // Expect a parent label to exist first.
+                let current_parent = if let Some(ref parent_label) = self.last_parent_label {
+                    parent_label
+                } else {
+                    let file_name = self.include_stack.last().unwrap().0.clone();
+                    let mut labels = vec![(
+                        Location {
+                            span: sublabel.span.clone(),
+                            file_name: file_name.clone(),
+                        },
+                        None,
+                    )];
+                    // The parent may have been written after the sublabel by mistake.
+                    let help = match self.later_parent_label(parent_label.as_ref()) {
+                        Some(later_parent) => {
+                            labels.push((
+                                Location {
+                                    span: later_parent.span,
+                                    file_name,
+                                },
+                                Some(String::from("Parent label defined after the sublabel")),
+                            ));
+                            format!(
+                                "Did you mean to put `{}` above `.{}`?",
+                                later_parent.val, sublabel.val
+                            )
+                        }
+                        None => format!(
+                            "Did you mean to create a parent label `{}`?",
+                            parent_label.as_ref().unwrap_or(&sublabel).val,
+                        ),
+                    };
+                    self.errors.push(AssemblerError {
+                        message: String::from("No parent label to put sublabel under"),
+                        labels,
+                        help: Some(help),
+                    });
+
+                    return Err(());
+                };
+
+                if let Some(parent_label) = &parent_label {
+                    // Make sure explicit parent label matches acual parent.
+                    if parent_label.val != current_parent.0.val {
+                        self.errors.push(AssemblerError {
+                            message: String::from(
+                                "Explicit parent label does not match most recent parent label",
+                            ),
+                            labels: vec![
+                                (
+                                    Location {
+                                        span: parent_label.span.clone(),
+                                        file_name: self.include_stack.last().unwrap().0.clone(),
+                                    },
+                                    Some(format!("Found `{}`", parent_label.val)),
+                                ),
+                                (
+                                    Location {
+                                        span: current_parent.0.span.clone(),
+                                        file_name: current_parent.1.clone(),
+                                    },
+                                    Some(format!("Expected `{}`", current_parent.0.val)),
+                                ),
+                            ],
+                            help: Some(format!(
+                                "Change `{}` to `{}`",
+                                parent_label.val, current_parent.0.val
+                            )),
+                        });
+
+                        return Err(());
+                    } else {
+                        let name = format!("{}.{}", parent_label.val, sublabel.val);
+                        if let Err(error) =
+                            self.check_duplicate_label(&name, spanned_label.span.clone())
+                        {
+                            self.errors.push(error);
+                            return Err(());
+                        }
+                        self.record_definition(&name, spanned_label.span.clone());
+                        let offset = self.object[self.current_section].origin;
+                        self.object[self.current_section].labels.push(SectionLabel {
+                            name,
+                            visibility: Visibility::Object,
+                            offset,
+                        });
+                    }
+                } else {
+                    let name = format!("{}.{}", current_parent.0.val, sublabel.val);
+                    if let Err(error) =
+                        self.check_duplicate_label(&name, spanned_label.span.clone())
+                    {
+                        self.errors.push(error);
+                        return Err(());
+                    }
+                    self.record_definition(&name, spanned_label.span.clone());
+                    let offset = self.object[self.current_section].origin;
+                    self.object[self.current_section].labels.push(SectionLabel {
+                        name,
+                        visibility: Visibility::Object,
+                        offset,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the bytes of an operand, resolving a reference to a macro if possible,
     /// and the modifier to a reference if it is not to a macro.
+    ///
+    /// The `<` and `>` modifiers take the high or low byte of a word:
+    ///
+    /// | Value                     | Result                                      |
+    /// |---------------------------|---------------------------------------------|
+    /// | word literal or macro     | the byte, taken right away                  |
+    /// | label or section size     | a reference that fills in one byte with the |
+    /// |                           | high or low byte once the address is known  |
+    /// | byte literal or macro     | an error, since there's no word to take     |
+    /// |                           | a byte of                                   |
+    ///
+    /// Since the operand is then a byte, an address is in the zeropage.
     fn resolve_operand(
         &mut self,
-        operand: Spanned<Operand>,
+        mut operand: Spanned<Operand>,
         branch: bool,
-    ) -> (Spanned<ActualValue>, AddressMode) {
+    ) -> Result<(Spanned<ActualValue>, AddressMode), AssemblerError> {
         // println!("macros:\n{:?}", self.macros);
         // println!("operand {:?}\n", operand);
 
+        self.record_number(&operand.value);
+
+        // Replace a coerced value with the literal it becomes so the address mode
+        // is decided by the coerced width.
+        if let Value::Coerced((width, value)) = &operand.value.val {
+            let mut value = value.as_ref().clone();
+            if let Value::Expr(_) | Value::Not(_) = value.val {
+                value.val = match self.evaluate(&value, branch)? {
+                    Evaluated::Number(number, word) => {
+                        self.number_value(number, word, &value.span)?
+                    }
+                    Evaluated::Label(name, _) | Evaluated::Distance(name, _, _) => {
+                        Value::Reference(name)
+                    }
+                    Evaluated::SectionStart(_) => Value::ProgramCounter,
+                };
+            } else {
+                self.record_number(&value);
+                if let Value::Reference(symbol) = &value.val {
+                    self.record_use(symbol, value.span.clone());
+                }
+            }
+            operand.value.val = self.coerce_value(*width, &value)?;
+        }
+
+        // In an object, `$` is evaluated to a reference to the section's start.
+        if operand.value.val == Value::ProgramCounter && (self.binary || branch) {
+            operand.value.val = self.program_counter(branch, &operand.value.span)?;
+        }
+
+        // An entry in a table of a macro's address is a literal, but a label's
+        // address isn't known yet so the index is added when it's resolved.
+        let mut addend = 0;
+        if let Value::Indexed((symbol, index)) = operand.value.val.clone() {
+            let base = match self.macros.get(&symbol) {
+                Some(Macro::Byte(byte, _)) => Some(*byte as u16),
+                Some(Macro::Word(word, _)) => Some(*word),
+                Some(Macro::String(..)) => {
+                    return Err(AssemblerError {
+                        message: format!("Cannot index string macro `{}`", symbol),
+                        labels: vec![(
+                            Location {
+                                span: operand.value.span.clone(),
+                                file_name: self.include_stack.last().unwrap().0.clone(),
+                            },
+                            None,
+                        )],
+                        help: Some(String::from(
+                            "Only labels and macros of addresses can be indexed",
+                        )),
+                    })
+                }
+                None => None,
+            };
+
+            operand.value.val = match base {
+                Some(base) => {
+                    self.record_use(&symbol, operand.value.span.clone());
+                    match base.checked_add(index) {
+                        Some(address) if address <= u8::MAX as u16 => Value::Byte(address as u8),
+                        Some(address) => Value::Word(address),
+                        None => {
+                            return Err(AssemblerError {
+                                message: format!(
+                                    "`{}[{}]` is past the end of memory, at `${:05x}`",
+                                    symbol,
+                                    index,
+                                    base as u32 + index as u32
+                                ),
+                                labels: vec![(
+                                    Location {
+                                        span: operand.value.span.clone(),
+                                        file_name: self.include_stack.last().unwrap().0.clone(),
+                                    },
+                                    None,
+                                )],
+                                help: None,
+                            })
+                        }
+                    }
+                }
+                None => {
+                    addend = index;
+                    Value::Reference(symbol)
+                }
+            };
+        }
+
+        // An expression of numbers becomes a literal, but one with a label is a
+        // reference to the label with the rest added when it's resolved. Uses of
+        // the symbols in it are recorded while evaluating. An immediate operand
+        // is a byte, so an expression becomes one there, keeping only the low byte
+        // of a value that doesn't fit.
+        let evaluated = matches!(
+            operand.value.val,
+            Value::Expr(_) | Value::Not(_) | Value::ProgramCounter
+        );
+        let mut subtrahend = None;
+        let mut section_start = false;
+        if evaluated {
+            // With `<` or `>` the modifier picks the byte of the word instead.
+            let immediate =
+                operand.mode == OperandMode::Immediate && operand.val.modifier.is_none();
+            operand.value.val = match self.evaluate(&operand.value, branch)? {
+                Evaluated::Number(number, _) if immediate => {
+                    match self.number_value(number, false, &operand.value.span)? {
+                        Value::Word(_) => {
+                            self.warn_overflow(number, &operand.value.span);
+                            Value::Byte(number as u8)
+                        }
+                        byte => byte,
+                    }
+                }
+                Evaluated::Number(number, word) => {
+                    self.number_value(number, word, &operand.value.span)?
+                }
+                Evaluated::Label(name, label_addend) => {
+                    addend = label_addend as u16;
+                    Value::Reference(name)
+                }
+                Evaluated::Distance(end, start, distance_addend) => {
+                    addend = distance_addend as u16;
+                    subtrahend = Some(start);
+                    Value::Reference(end)
+                }
+                Evaluated::SectionStart(offset) => {
+                    addend = offset as u16;
+                    section_start = true;
+                    Value::Reference(self.object[self.current_section].name.clone())
+                }
+            };
+        }
+
         let mut address_mode = operand.val.address_mode(branch);
         let span = operand.span.clone();
+        if let (Value::Reference(symbol), false) = (&operand.value.val, evaluated) {
+            self.record_use(symbol, span.clone());
+        }
+
         let resolved_value = match &operand.value.val {
             // If it's a reference then see if it is a macro first.
             Value::Reference(symbol) => match self.macros.get(symbol) {
                 // Reference's address mode is parsed as absolute, but when we resolve it
                 // to a byte macro it should be zeropage instead.
                 Some(Macro::Byte(byte, _)) => {
-                    address_mode = match address_mode {
-                        AddressMode::Absolute => AddressMode::Zeropage,
-                        AddressMode::AbsoluteX => AddressMode::ZeropageX,
-                        AddressMode::AbsoluteY => AddressMode::ZeropageY,
-                        _ => address_mode,
-                    };
-                    match address_mode {
-                        AddressMode::Indirect => ActualValue::Word(*byte as u16),
-                        _ => ActualValue::Byte(*byte),
+                    if let Some(modifier) = &operand.modifier {
+                        return Err(self.byte_modifier_error(
+                            modifier,
+                            operand.value.span.clone(),
+                            format!("`{}` is defined as a byte", symbol),
+                            String::from("Leave out the modifier to use the byte"),
+                        ));
+                    }
+                    address_mode = zeropage_mode(address_mode);
+                    ActualValue::Byte(*byte)
+                }
+                Some(Macro::Word(word, _)) => {
+                    if let Some(spanned_modifier) = &operand.modifier {
+                        address_mode = zeropage_mode(address_mode);
+                        match spanned_modifier.val {
+                            Modifier::HighByte => ActualValue::Byte((*word >> 8) as u8),
+                            Modifier::LowByte => ActualValue::Byte((*word) as u8),
+                        }
+                    } else {
+                        ActualValue::Word(*word)
+                    }
+                }
+                Some(Macro::String(string, _)) => ActualValue::String(string.clone()),
+                None => {
+                    if operand.modifier.is_some() {
+                        address_mode = zeropage_mode(address_mode);
+                    }
+                    ActualValue::Reference(Reference {
+                        name: symbol.clone(),
+                        // The origin is the index where the opcode byte will be inserted
+                        // because this function is called before doing that, so we add 1 to
+                        // indicate where in the section the referenced value will go.
+                        offset: self.object[self.current_section].origin + 1,
+                        modifier: operand.modifier.clone(),
+                        branch,
+                        section_size: false,
+                        section_start,
+                        addend,
+                        subtrahend,
+                        location: Location {
+                            span: span.clone(),
+                            file_name: self.include_stack.last().unwrap().0.clone(),
+                        },
+                    })
+                }
+            },
+            Value::Accumulator => ActualValue::Accumulator,
+            Value::Byte(byte) => {
+                if let Some(modifier) = &operand.modifier {
+                    return Err(self.byte_modifier_error(
+                        modifier,
+                        operand.value.span.clone(),
+                        String::from("This is a byte"),
+                        format!(
+                            "Write it as a word, like `${:04x}`, to take one of its bytes",
+                            byte
+                        ),
+                    ));
+                }
+                ActualValue::Byte(*byte)
+            }
+            Value::Word(word) => {
+                if let Some(spanned_modifier) = &operand.modifier {
+                    address_mode = zeropage_mode(address_mode);
+                    match spanned_modifier.val {
+                        Modifier::HighByte => ActualValue::Byte((*word >> 8) as u8),
+                        Modifier::LowByte => ActualValue::Byte((*word) as u8),
+                    }
+                } else {
+                    ActualValue::Word(*word)
+                }
+            }
+            Value::String(string) => ActualValue::String(string.clone()),
+            Value::Coerced(_) => unreachable!("Coerced values are replaced above"),
+            Value::Indexed(_) => unreachable!("Table entries are replaced above"),
+            Value::ProgramCounter => unreachable!("The program counter is replaced above"),
+            Value::Opcode((mnemonic, mode)) => match self.cpu.opcodes()[*mnemonic][*mode] {
+                Some(byte) => ActualValue::Byte(byte),
+                None => {
+                    return Err(AssemblerError {
+                        message: format!(
+                            "{} address mode is invalid for instruction `{}`",
+                            mode.string_rep(*mnemonic),
+                            mnemonic,
+                        ),
+                        labels: vec![(
+                            Location {
+                                span,
+                                file_name: self.include_stack.last().unwrap().0.clone(),
+                            },
+                            None,
+                        )],
+                        help: Some(invalid_mode_help(self.cpu, *mnemonic, *mode)),
+                    })
+                }
+            },
+            // The size isn't known until every section is assembled.
+            Value::SectionSize(name) => {
+                if branch {
+                    return Err(AssemblerError {
+                        message: String::from("The size of a section can't be a branch target"),
+                        labels: vec![(
+                            Location {
+                                span,
+                                file_name: self.include_stack.last().unwrap().0.clone(),
+                            },
+                            None,
+                        )],
+                        help: None,
+                    });
+                }
+                if operand.modifier.is_some() {
+                    address_mode = zeropage_mode(address_mode);
+                }
+                ActualValue::Reference(Reference {
+                    name: name.clone(),
+                    offset: self.object[self.current_section].origin + 1,
+                    modifier: operand.modifier.clone(),
+                    branch,
+                    section_size: true,
+                    section_start: false,
+                    addend: 0,
+                    subtrahend: None,
+                    location: Location {
+                        span: span.clone(),
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                })
+            }
+            Value::Data(_) => unreachable!("Data is inserted before resolving operands"),
+            Value::Alignment(_) => unreachable!("Alignment is handled before resolving operands"),
+            Value::Fill(_) => unreachable!("Filling is handled before resolving operands"),
+            Value::List(_) => unreachable!("Lists are handled before resolving operands"),
+            Value::Expr(_) | Value::Not(_) => unreachable!("Expressions are evaluated above"),
+            Value::Repeat(_) => {
+                unreachable!("Repeated blocks are handled before resolving operands")
+            }
+            Value::Conditional(_) => {
+                unreachable!("Conditional blocks are handled before resolving operands")
+            }
+            Value::RepeatCounter => unreachable!("Repeat counters are replaced when repeating"),
+            Value::Include(_) => unreachable!("Included programs are handled during parsing"),
+        };
+
+        Ok((Spanned::new((resolved_value, span)), address_mode))
+    }
+
+    /// Evaluate an expression whose macros are known. Labels can only have numbers
+    /// added to or subtracted from them, or be subtracted from each other.
+    fn evaluate(
+        &mut self,
+        value: &Spanned<Value>,
+        branch: bool,
+    ) -> Result<Evaluated, AssemblerError> {
+        let location = Location {
+            span: value.span.clone(),
+            file_name: self.include_stack.last().unwrap().0.clone(),
+        };
+
+        let (left, operator, right) = match &value.val {
+            Value::Expr((left, operator, right)) => (left, *operator, right),
+            Value::Byte(byte) => {
+                self.record_number(value);
+                return Ok(Evaluated::Number(*byte as i32, false));
+            }
+            Value::Word(word) => {
+                self.record_number(value);
+                return Ok(Evaluated::Number(*word as i32, true));
+            }
+            // A branch's `$` is the offset back to its own start.
+            Value::ProgramCounter if branch => return Ok(Evaluated::Number(-2, false)),
+            // In an object it's an offset into the section until the section is placed.
+            Value::ProgramCounter if !self.binary && !self.pic => {
+                return Ok(Evaluated::SectionStart(
+                    self.object[self.current_section].origin as i32,
+                ));
+            }
+            Value::ProgramCounter => {
+                return match self.program_counter(false, &value.span)? {
+                    Value::Word(word) => Ok(Evaluated::Number(word as i32, true)),
+                    _ => unreachable!("The program counter is an address"),
+                };
+            }
+            Value::Reference(symbol) | Value::Indexed((symbol, _)) => {
+                self.record_use(symbol, value.span.clone());
+                let index = match &value.val {
+                    Value::Indexed((_, index)) => *index as i32,
+                    _ => 0,
+                };
+                return match self.macros.get(symbol) {
+                    Some(Macro::Byte(byte, _)) => {
+                        Ok(Evaluated::Number(*byte as i32 + index, false))
+                    }
+                    Some(Macro::Word(word, _)) => Ok(Evaluated::Number(*word as i32 + index, true)),
+                    Some(Macro::String(..)) => Err(AssemblerError {
+                        message: format!(
+                            "String macro `{}` can't be used in an expression",
+                            symbol
+                        ),
+                        labels: vec![(location, None)],
+                        help: None,
+                    }),
+                    None => Ok(Evaluated::Label(symbol.clone(), index)),
+                };
+            }
+            Value::Not(value) => {
+                return match self.evaluate(value, branch)? {
+                    Evaluated::Number(number, word) => Ok(Evaluated::Number(!number, word)),
+                    Evaluated::Label(name, _) | Evaluated::Distance(name, _, _) => {
+                        Err(label_operation_error(&name, location))
+                    }
+                    Evaluated::SectionStart(_) => Err(label_operation_error("$", location)),
+                };
+            }
+            Value::Coerced((width, coerced)) => {
+                let coerced = match self.evaluate(coerced, branch)? {
+                    Evaluated::Number(number, word) => Spanned::new((
+                        self.number_value(number, word, &coerced.span)?,
+                        coerced.span.clone(),
+                    )),
+                    Evaluated::Label(name, _) | Evaluated::Distance(name, _, _) => {
+                        Spanned::new((Value::Reference(name), coerced.span.clone()))
                     }
-                }
-                Some(Macro::Word(word, _)) => {
-                    if let Some(spanned_modifier) = &operand.modifier {
-                        address_mode = match address_mode {
-                            AddressMode::Absolute => AddressMode::Zeropage,
-                            AddressMode::AbsoluteX => AddressMode::ZeropageX,
-                            AddressMode::AbsoluteY => AddressMode::ZeropageY,
-                            _ => address_mode,
-                        };
-                        match spanned_modifier.val {
-                            Modifier::HighByte => ActualValue::Byte((*word >> 8) as u8),
-                            Modifier::LowByte => ActualValue::Byte((*word) as u8),
-                        }
-                    } else {
-                        ActualValue::Word(*word)
+                    Evaluated::SectionStart(_) => {
+                        Spanned::new((Value::ProgramCounter, coerced.span.clone()))
                     }
+                };
+                return match self.coerce_value(*width, &coerced)? {
+                    Value::Byte(byte) => Ok(Evaluated::Number(byte as i32, false)),
+                    Value::Word(word) => Ok(Evaluated::Number(word as i32, true)),
+                    _ => unreachable!("Values are coerced to numbers"),
+                };
+            }
+            _ => {
+                return Err(AssemblerError {
+                    message: String::from("This value can't be used in an expression"),
+                    labels: vec![(location, None)],
+                    help: Some(String::from(
+                        "Expressions are made of numbers, labels, and macros of numbers",
+                    )),
+                })
+            }
+        };
+
+        let right_span = right.span.clone();
+        let left = self.evaluate(left, branch)?;
+        let right = self.evaluate(right, branch)?;
+
+        let too_large = || AssemblerError {
+            message: String::from("The expression is too large"),
+            labels: vec![(location.clone(), None)],
+            help: None,
+        };
+
+        Ok(match (left, operator, right) {
+            (Evaluated::Number(_, _), Operator::Divide, Evaluated::Number(0, _)) => {
+                return Err(AssemblerError {
+                    message: String::from("Division by zero"),
+                    labels: vec![(
+                        Location {
+                            span: right_span,
+                            file_name: location.file_name,
+                        },
+                        Some(String::from("This is zero")),
+                    )],
+                    help: None,
+                });
+            }
+            (
+                Evaluated::Number(left, left_word),
+                operator,
+                Evaluated::Number(right, right_word),
+            ) => Evaluated::Number(
+                match operator {
+                    Operator::Add => left.checked_add(right),
+                    Operator::Subtract => left.checked_sub(right),
+                    Operator::Multiply => left.checked_mul(right),
+                    Operator::Divide => left.checked_div(right),
+                    Operator::And => Some(left & right),
+                    Operator::Or => Some(left | right),
+                    Operator::Xor => Some(left ^ right),
+                    // Shifts work on the bits of a word, so shifting all of them
+                    // out leaves zero.
+                    Operator::ShiftLeft => Some(
+                        u32::try_from(right)
+                            .ok()
+                            .and_then(|right| (left as u16).checked_shl(right))
+                            .unwrap_or(0) as i32,
+                    ),
+                    Operator::ShiftRight => Some(
+                        u32::try_from(right)
+                            .ok()
+                            .and_then(|right| (left as u16).checked_shr(right))
+                            .unwrap_or(0) as i32,
+                    ),
                 }
-                Some(Macro::String(string, _)) => ActualValue::String(string.clone()),
-                None => ActualValue::Reference(Reference {
-                    name: symbol.clone(),
-                    // The origin is the index where the opcode byte will be inserted
-                    // because this function is called before doing that, so we add 1 to
-                    // indicate where in the section the referenced value will go.
-                    offset: self.object[self.current_section].origin + 1,
-                    modifier: operand.modifier.clone(),
-                    branch,
-                    location: Location {
+                .ok_or_else(too_large)?,
+                left_word || right_word,
+            ),
+            (Evaluated::Label(name, addend), Operator::Add, Evaluated::Number(number, _))
+            | (Evaluated::Number(number, _), Operator::Add, Evaluated::Label(name, addend)) => {
+                Evaluated::Label(name, addend.checked_add(number).ok_or_else(too_large)?)
+            }
+            (Evaluated::Label(name, addend), Operator::Subtract, Evaluated::Number(number, _)) => {
+                Evaluated::Label(name, addend.checked_sub(number).ok_or_else(too_large)?)
+            }
+            (Evaluated::SectionStart(offset), Operator::Add, Evaluated::Number(number, _))
+            | (Evaluated::Number(number, _), Operator::Add, Evaluated::SectionStart(offset)) => {
+                Evaluated::SectionStart(offset.checked_add(number).ok_or_else(too_large)?)
+            }
+            (Evaluated::SectionStart(offset), Operator::Subtract, Evaluated::Number(number, _)) => {
+                Evaluated::SectionStart(offset.checked_sub(number).ok_or_else(too_large)?)
+            }
+            (
+                Evaluated::Distance(end, start, addend),
+                Operator::Add,
+                Evaluated::Number(number, _),
+            )
+            | (
+                Evaluated::Number(number, _),
+                Operator::Add,
+                Evaluated::Distance(end, start, addend),
+            ) => Evaluated::Distance(
+                end,
+                start,
+                addend.checked_add(number).ok_or_else(too_large)?,
+            ),
+            (
+                Evaluated::Distance(end, start, addend),
+                Operator::Subtract,
+                Evaluated::Number(number, _),
+            ) => Evaluated::Distance(
+                end,
+                start,
+                addend.checked_sub(number).ok_or_else(too_large)?,
+            ),
+            // The distance between labels that are already defined in this section
+            // is known now, otherwise both are resolved later. Either way it's a
+            // word, so the width doesn't depend on where the labels are.
+            (
+                Evaluated::Label(end, end_addend),
+                Operator::Subtract,
+                Evaluated::Label(start, start_addend),
+            ) => {
+                let labels = &self.object[self.current_section].labels;
+                let offset = |name: &str| {
+                    labels
+                        .iter()
+                        .find(|label| label.name == name)
+                        .map(|label| label.offset as i32)
+                };
+                match (offset(&end), offset(&start)) {
+                    (Some(end_offset), Some(start_offset)) => Evaluated::Number(
+                        (end_offset + end_addend) - (start_offset + start_addend),
+                        true,
+                    ),
+                    _ => Evaluated::Distance(end, start, end_addend - start_addend),
+                }
+            }
+            (Evaluated::Label(name, _) | Evaluated::Distance(name, _, _), _, _)
+            | (_, _, Evaluated::Label(name, _) | Evaluated::Distance(name, _, _)) => {
+                return Err(label_operation_error(&name, location))
+            }
+            (Evaluated::SectionStart(_), _, _) | (_, _, Evaluated::SectionStart(_)) => {
+                return Err(label_operation_error("$", location))
+            }
+        })
+    }
+
+    /// The literal that an evaluated expression becomes. It's a byte if it fits in one
+    /// and no part of it was a word. Negative numbers become their two's complement.
+    fn number_value(
+        &self,
+        number: i32,
+        word: bool,
+        span: &Range<usize>,
+    ) -> Result<Value, AssemblerError> {
+        if !word && (i8::MIN as i32..=u8::MAX as i32).contains(&number) {
+            Ok(Value::Byte(number as u8))
+        } else if (i16::MIN as i32..=u16::MAX as i32).contains(&number) {
+            Ok(Value::Word(number as u16))
+        } else {
+            Err(AssemblerError {
+                message: format!("The expression's value `{}` does not fit in a word", number),
+                labels: vec![(
+                    Location {
                         span: span.clone(),
                         file_name: self.include_stack.last().unwrap().0.clone(),
                     },
-                }),
+                    None,
+                )],
+                help: None,
+            })
+        }
+    }
+
+    /// Warn that an immediate expression is too large for a byte, so only its low
+    /// byte is used.
+    fn warn_overflow(&mut self, number: i32, span: &Range<usize>) {
+        self.warnings.push(Warning {
+            category: WarningCategory::Overflow,
+            error: AssemblerError {
+                message: format!("The expression's value `{}` does not fit in a byte", number),
+                labels: vec![(
+                    Location {
+                        span: span.clone(),
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    Some(format!("Only the low byte `${:02x}` is used", number as u8)),
+                )],
+                help: Some(String::from(
+                    "Use `<` or `>` to choose which byte of a word to use",
+                )),
             },
-            Value::Accumulator => ActualValue::Accumulator,
-            Value::Byte(byte) => {
-                if address_mode == AddressMode::Indirect {
-                    ActualValue::Word(*byte as u16)
-                } else {
-                    ActualValue::Byte(*byte)
+        });
+    }
+
+    /// Remember a number literal written in the current line so the listing can
+    /// show it in hex. Character literals are also bytes but are left alone.
+    fn record_number(&mut self, value: &Spanned<Value>) {
+        let number = match value.val {
+            Value::Byte(byte) => format!("${:02x}", byte),
+            Value::Word(word) => format!("${:04x}", word),
+            _ => return,
+        };
+
+        let file_name = &self.include_stack.last().unwrap().0;
+        let source = self.files.get(self.id_table[file_name]).unwrap().source();
+        if source[value.span.clone()].starts_with('\'') {
+            return;
+        }
+
+        self.current_line_numbers.push((value.span.clone(), number));
+    }
+
+    /// The error for a `<` or `>` modifier on a byte, which has no word to take a byte of.
+    fn byte_modifier_error(
+        &self,
+        modifier: &Spanned<Modifier>,
+        value_span: Range<usize>,
+        label: String,
+        help: String,
+    ) -> AssemblerError {
+        let file_name = self.include_stack.last().unwrap().0.clone();
+        AssemblerError {
+            message: format!(
+                "The `{}` modifier requires a word",
+                match modifier.val {
+                    Modifier::HighByte => "<",
+                    Modifier::LowByte => ">",
+                }
+            ),
+            labels: vec![
+                (
+                    Location {
+                        span: value_span,
+                        file_name: file_name.clone(),
+                    },
+                    Some(label),
+                ),
+                (
+                    Location {
+                        span: modifier.span.clone(),
+                        file_name,
+                    },
+                    None,
+                ),
+            ],
+            help: Some(help),
+        }
+    }
+
+    /// The value of `$` in an operand. A branch only needs the offset to itself, but
+    /// position independent code has no address for it.
+    fn program_counter(&self, branch: bool, span: &Range<usize>) -> Result<Value, AssemblerError> {
+        if branch {
+            // The offset is from the end of the two byte branch back to its start.
+            return Ok(Value::Byte(-2i8 as u8));
+        }
+
+        if self.pic {
+            return Err(AssemblerError {
+                message: String::from(
+                    "The program counter isn't an address in position independent code",
+                ),
+                labels: vec![(
+                    Location {
+                        span: span.clone(),
+                        file_name: self.include_stack.last().unwrap().0.clone(),
+                    },
+                    None,
+                )],
+                help: Some(String::from(
+                    "Put a label on the line and refer to it instead",
+                )),
+            });
+        }
+
+        Ok(Value::Word(self.object[self.current_section].origin as u16))
+    }
+
+    /// Remember where a label or macro was referenced for the cross-reference table.
+    fn record_use(&mut self, symbol: &str, span: Range<usize>) {
+        let location = Location {
+            span,
+            file_name: self.include_stack.last().unwrap().0.clone(),
+        };
+        self.symbol_uses
+            .entry(symbol.to_string())
+            .or_default()
+            .push(location);
+    }
+
+    /// Find the next top level label in the current file, or the one with the given
+    /// name, for a sublabel that comes before any parent label.
+    fn later_parent_label(&self, name: Option<&Spanned<String>>) -> Option<Spanned<String>> {
+        let mut include_depth = 0;
+        for action in self.program.clone() {
+            match action {
+                Action::PushInclude(_) => include_depth += 1,
+                Action::PopInclude if include_depth == 0 => return None,
+                Action::PopInclude => include_depth -= 1,
+                Action::Label(Spanned {
+                    val: Label::Top(top_label),
+                    span,
+                }) if include_depth == 0 && name.is_none_or(|name| name.val == top_label.name) => {
+                    return Some(Spanned::new((top_label.name, span)));
                 }
+                _ => {}
             }
-            Value::Word(word) => {
-                if let Some(spanned_modifier) = &operand.modifier {
-                    address_mode = match address_mode {
-                        AddressMode::Absolute => AddressMode::Zeropage,
-                        AddressMode::AbsoluteX => AddressMode::ZeropageX,
-                        AddressMode::AbsoluteY => AddressMode::ZeropageY,
-                        _ => address_mode,
-                    };
-                    match spanned_modifier.val {
-                        Modifier::HighByte => ActualValue::Byte((*word >> 8) as u8),
-                        Modifier::LowByte => ActualValue::Byte((*word) as u8),
-                    }
-                } else {
-                    ActualValue::Word(*word)
+        }
+        None
+    }
+
+    /// Remember where a label was defined for the cross-reference table.
+    fn record_definition(&mut self, name: &str, span: Range<usize>) {
+        let location = Location {
+            span,
+            file_name: self.include_stack.last().unwrap().0.clone(),
+        };
+        self.warn_label_case(name, &location);
+        self.label_definitions
+            .entry(name.to_string())
+            .or_insert(location);
+    }
+
+    /// Make sure a label isn't defined twice, since references to it couldn't tell
+    /// which one is meant. Sublabels are checked by their full name, so the same
+    /// sublabel can be under different parents.
+    fn check_duplicate_label(&self, name: &str, span: Range<usize>) -> Result<(), AssemblerError> {
+        // Redefining a macro is reported when the macro is created.
+        if self.macros.contains_key(name) {
+            return Ok(());
+        }
+
+        let location = Location {
+            span,
+            file_name: self.include_stack.last().unwrap().0.clone(),
+        };
+        let (labels, help) = if let Some(defined) = self.label_definitions.get(name) {
+            (
+                vec![
+                    (location, None),
+                    (defined.clone(), Some(String::from("Already defined here"))),
+                ],
+                None,
+            )
+        } else if self
+            .equates
+            .iter()
+            .any(|equate| self.symbol_name(&equate.name) == name)
+        {
+            // An equate has no source to point to.
+            (
+                vec![(location, None)],
+                Some(format!("`{}` is given in the equates file", name)),
+            )
+        } else {
+            return Ok(());
+        };
+
+        Err(AssemblerError {
+            message: format!("The label `{}` has already been defined", name),
+            labels,
+            help,
+        })
+    }
+
+    /// Warn about a label whose name only differs in case from one defined before,
+    /// which is likely a typo when labels are case sensitive.
+    fn warn_label_case(&mut self, name: &str, location: &Location) {
+        if self.case_insensitive_labels {
+            return;
+        }
+
+        let first_spelling = self
+            .label_spellings
+            .entry(name.to_lowercase())
+            .or_insert_with(|| name.to_string());
+        if first_spelling == name {
+            return;
+        }
+
+        let first_spelling = first_spelling.clone();
+        let mut labels = vec![(location.clone(), None)];
+        if let Some(first_location) = self.label_definitions.get(&first_spelling) {
+            labels.push((
+                first_location.clone(),
+                Some(format!("`{}` is defined here", first_spelling)),
+            ));
+        }
+        self.warnings.push(Warning {
+            category: WarningCategory::LabelCase,
+            error: AssemblerError {
+                message: format!(
+                    "Labels `{}` and `{}` only differ in case",
+                    name, first_spelling
+                ),
+                labels,
+                help: Some(String::from(
+                    "Labels are case sensitive, so these are different labels. \
+                    Use --case-sensitive-labels=false to treat them as the same",
+                )),
+            },
+        });
+    }
+
+    /// The name of a label or macro as it's compared, which is lowercase if case
+    /// doesn't matter.
+    fn symbol_name(&self, name: &str) -> String {
+        if self.case_insensitive_labels {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// The file name and line number of a location.
+    fn source_line(&self, location: &Location) -> (String, usize) {
+        let line_number = self
+            .files
+            .line_index(self.id_table[&location.file_name], location.span.start)
+            .unwrap_or(0)
+            + 1;
+        (location.file_name.clone(), line_number)
+    }
+
+    /// Every label and macro with where it was defined and used, sorted by name.
+    fn cross_references(&self) -> Vec<CrossReference> {
+        let mut names = self
+            .label_definitions
+            .keys()
+            .chain(self.macros.keys())
+            .chain(self.symbol_uses.keys())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+
+        names
+            .into_iter()
+            .map(|name| CrossReference {
+                name: name.clone(),
+                definition: self
+                    .label_definitions
+                    .get(name)
+                    .or_else(|| self.macros.get(name).map(Macro::location))
+                    .filter(|location| **location != command_line_location())
+                    .map(|location| self.source_line(location)),
+                uses: self
+                    .symbol_uses
+                    .get(name)
+                    .map(|uses| {
+                        uses.iter()
+                            .map(|use_location| self.source_line(use_location))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Force a literal or macro to a byte or word. Coercing to a byte
+    /// fails if the value does not fit.
+    fn coerce_value(&self, width: Width, value: &Spanned<Value>) -> Result<Value, AssemblerError> {
+        let location = Location {
+            span: value.span.clone(),
+            file_name: self.include_stack.last().unwrap().0.clone(),
+        };
+
+        let number = match &value.val {
+            Value::Byte(byte) => *byte as u16,
+            Value::Word(word) => *word,
+            Value::Reference(symbol) => match self.macros.get(symbol) {
+                Some(Macro::Byte(byte, _)) => *byte as u16,
+                Some(Macro::Word(word, _)) => *word,
+                _ => {
+                    return Err(AssemblerError {
+                        message: format!("Cannot coerce `{}` to a {}", symbol, width),
+                        labels: vec![(location, None)],
+                        help: Some(String::from(
+                            "Only literal numbers and macros of them can be coerced",
+                        )),
+                    })
                 }
+            },
+            _ => {
+                return Err(AssemblerError {
+                    message: format!("Cannot coerce this value to a {}", width),
+                    labels: vec![(location, None)],
+                    help: Some(String::from(
+                        "Only literal numbers and macros of them can be coerced",
+                    )),
+                })
             }
-            Value::String(string) => ActualValue::String(string.clone()),
-            Value::Include(_) => unreachable!("Included programs are handled during parsing"),
         };
 
-        (Spanned::new((resolved_value, span)), address_mode)
+        match width {
+            Width::Word => Ok(Value::Word(number)),
+            Width::Byte if number <= u8::MAX as u16 => Ok(Value::Byte(number as u8)),
+            Width::Byte => Err(AssemblerError {
+                message: format!("Value `${:04x}` does not fit in a byte", number),
+                labels: vec![(location, None)],
+                help: Some(String::from(
+                    "Use the `<` or `>` modifier to take the high or low byte",
+                )),
+            }),
+        }
     }
 
     fn insert_byte(&mut self, byte: u8) {
         let offset = self.object[self.current_section].origin;
+        // Nothing is written past the end of memory, and the instruction is
+        // reported once it's done.
+        if offset >= MEMORY_SIZE {
+            self.past_memory_end = true;
+            return;
+        }
         if offset == 0 {
             self.object[self.current_section].lowest_origin = Some(0);
         }
-        self.object[self.current_section].data[offset] = byte;
+        // Writing over code is only a mistake once the addresses are final.
+        if self.binary
+            && self.object[self.current_section]
+                .used_ranges
+                .iter()
+                .any(|range| range.contains(&offset))
+        {
+            // Only the first byte of a run is warned about.
+            if self.last_overwrite != Some((self.current_section, offset.wrapping_sub(1))) {
+                self.line_overwrite.get_or_insert(offset);
+            }
+            self.last_overwrite = Some((self.current_section, offset));
+        }
+        let data = &mut self.object[self.current_section].data;
+        if data.len() <= offset {
+            data.resize(offset + 1, 0);
+        }
+        data[offset] = byte;
         self.object[self.current_section].origin += 1;
 
+        // Extend the last used range if this byte continues it.
+        let used_ranges = &mut self.object[self.current_section].used_ranges;
+        match used_ranges.last_mut() {
+            Some(range) if range.end == offset => range.end += 1,
+            _ => used_ranges.push(offset..offset + 1),
+        }
+
         if offset == self.object[self.current_section].highest_origin {
             self.object[self.current_section].highest_origin += 1;
         }
     }
 
+    /// Report an instruction that tried to put bytes past the end of memory.
+    fn check_memory_end(&mut self, instruction_span: Range<usize>) -> Result<(), AssemblerError> {
+        if !std::mem::take(&mut self.past_memory_end) {
+            return Ok(());
+        }
+
+        let start = self.current_line_section_start;
+        let remaining = MEMORY_SIZE.saturating_sub(start);
+        Err(AssemblerError {
+            message: format!("Assembling at ${:04x} goes past the end of memory", start),
+            labels: vec![(
+                Location {
+                    span: instruction_span,
+                    file_name: self.include_stack.last().unwrap().0.clone(),
+                },
+                None,
+            )],
+            help: Some(format!(
+                "Only {} {} before the end of memory",
+                remaining,
+                if remaining == 1 {
+                    "byte fits"
+                } else {
+                    "bytes fit"
+                }
+            )),
+        })
+    }
+
     fn insert_word(&mut self, word: u16) {
         self.insert_byte(word as u8);
         self.insert_byte((word >> 8) as u8);
     }
 }
+
+/// The error for using a label in an expression other than by adding or subtracting
+/// a number, or subtracting another label.
+fn label_operation_error(name: &str, location: Location) -> AssemblerError {
+    AssemblerError {
+        message: format!(
+            "`{}` can only have a number added to or subtracted from it",
+            name
+        ),
+        labels: vec![(location, None)],
+        help: Some(String::from(
+            "A label's address isn't known until every label is resolved, \
+            but the distance to another label can be taken like `end-start`",
+        )),
+    }
+}
+
+/// Replace the `rept` counter in the operands of a copy of a block.
+fn replace_repeat_counters(program: &mut Program, counter: u16) {
+    for action in program {
+        if let Action::Instruction(Spanned {
+            val:
+                Instruction {
+                    operand: Some(operand),
+                    ..
+                },
+            ..
+        }) = action
+        {
+            replace_repeat_counter(&mut operand.val.value.val, counter);
+        }
+    }
+}
+
+/// Replace the `rept` counter in a value with the number of the copy being made.
+/// The counter in a nested block is left for that block to replace, though its
+/// count may use this one.
+fn replace_repeat_counter(value: &mut Value, counter: u16) {
+    match value {
+        Value::RepeatCounter => {
+            *value = match u8::try_from(counter) {
+                Ok(byte) => Value::Byte(byte),
+                Err(_) => Value::Word(counter),
+            }
+        }
+        Value::Coerced((_, value)) | Value::Repeat((value, _)) => {
+            replace_repeat_counter(&mut value.val, counter)
+        }
+        Value::Alignment((boundary, fill, _)) | Value::Fill((boundary, fill, _)) => {
+            replace_repeat_counter(&mut boundary.val, counter);
+            if let Some(fill) = fill {
+                replace_repeat_counter(&mut fill.val, counter);
+            }
+        }
+        Value::Expr((left, _, right)) => {
+            replace_repeat_counter(&mut left.val, counter);
+            replace_repeat_counter(&mut right.val, counter);
+        }
+        Value::Not(value) => replace_repeat_counter(&mut value.val, counter),
+        // The blocks of an `if` are part of the `rept` block it's in.
+        Value::Conditional((condition, then_body, else_body)) => {
+            replace_repeat_counter(&mut condition.val, counter);
+            replace_repeat_counters(then_body, counter);
+            replace_repeat_counters(else_body, counter);
+        }
+        Value::List(operands) => {
+            for operand in operands {
+                replace_repeat_counter(&mut operand.val.value.val, counter);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Create the help message for an instruction that does not support an address mode.
+/// This lists the valid address modes and, if there is one, suggests a similarly
+/// spelled instruction that does support the address mode.
+/// Make the name of every label and macro in a program lowercase, so names that
+/// only differ in case are the same.
+fn lowercase_symbols(program: &mut Program) {
+    for action in program {
+        match action {
+            Action::Label(label) => match &mut label.val {
+                Label::Top(top_label) => top_label.name = top_label.name.to_lowercase(),
+                Label::Sub((parent_label, sublabel)) => {
+                    if let Some(parent_label) = parent_label {
+                        parent_label.val = parent_label.val.to_lowercase();
+                    }
+                    sublabel.val = sublabel.val.to_lowercase();
+                }
+            },
+            Action::Instruction(instruction) => {
+                if let Some(operand) = &mut instruction.val.operand {
+                    lowercase_value(&mut operand.val.value.val);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Make the names of the labels and macros referenced in a value lowercase.
+fn lowercase_value(value: &mut Value) {
+    match value {
+        Value::Reference(symbol) | Value::Indexed((symbol, _)) => *symbol = symbol.to_lowercase(),
+        Value::Expr((left, _, right)) => {
+            lowercase_value(&mut left.val);
+            lowercase_value(&mut right.val);
+        }
+        Value::Not(value) | Value::Coerced((_, value)) => lowercase_value(&mut value.val),
+        Value::List(operands) => {
+            for operand in operands {
+                lowercase_value(&mut operand.val.value.val);
+            }
+        }
+        Value::Alignment((amount, fill, name)) | Value::Fill((amount, fill, name)) => {
+            lowercase_value(&mut amount.val);
+            if let Some(fill) = fill {
+                lowercase_value(&mut fill.val);
+            }
+            if let Some(name) = name {
+                name.val = name.val.to_lowercase();
+            }
+        }
+        Value::Repeat((count, body)) => {
+            lowercase_value(&mut count.val);
+            lowercase_symbols(body);
+        }
+        Value::Conditional((condition, body, else_body)) => {
+            lowercase_value(&mut condition.val);
+            lowercase_symbols(body);
+            lowercase_symbols(else_body);
+        }
+        Value::Include((_, program)) => lowercase_symbols(program),
+        _ => {}
+    }
+}
+
+/// The address mode for an operand that's a byte instead of a word.
+fn zeropage_mode(address_mode: AddressMode) -> AddressMode {
+    match address_mode {
+        AddressMode::Absolute => AddressMode::Zeropage,
+        AddressMode::AbsoluteX => AddressMode::ZeropageX,
+        AddressMode::AbsoluteY => AddressMode::ZeropageY,
+        AddressMode::Indirect => AddressMode::IndirectZeropage,
+        _ => address_mode,
+    }
+}
+
+fn invalid_mode_help(cpu: Cpu, mnemonic: Mnemonic, address_mode: AddressMode) -> String {
+    let mut help = format!(
+        "Valid address modes are:\n    {}",
+        cpu.opcodes()[mnemonic]
+            .iter()
+            .filter(|(_, opcode)| opcode.is_some())
+            .map(|(mode, _)| mode.string_rep(mnemonic))
+            .collect::<Vec<String>>()
+            .join("\n    ")
+    );
+
+    if let Some(suggestion) = mnemonic_suggestion(cpu, mnemonic, address_mode) {
+        help.push('\n');
+        help.push_str(&suggestion);
+    }
+
+    help
+}
+
+/// Suggest an instruction like `mnemonic` that supports `address_mode`, if there is one.
+fn mnemonic_suggestion(cpu: Cpu, mnemonic: Mnemonic, address_mode: AddressMode) -> Option<String> {
+    suggest_mnemonic(cpu, mnemonic, address_mode).map(|suggestion| {
+        format!(
+            "Did you mean `{}`? It supports {} addressing",
+            suggestion,
+            address_mode.string_rep(suggestion).to_lowercase()
+        )
+    })
+}
+
+/// Find the instruction most similar in spelling to `mnemonic` that supports `address_mode`.
+/// Only instructions that are one edit away are considered so that the suggestion
+/// is likely to be a typo of what was written.
+fn suggest_mnemonic(cpu: Cpu, mnemonic: Mnemonic, address_mode: AddressMode) -> Option<Mnemonic> {
+    let written = mnemonic.to_string();
+    cpu.opcodes()
+        .iter()
+        .filter(|(candidate, modes)| *candidate != mnemonic && modes[address_mode].is_some())
+        .map(|(candidate, _)| (edit_distance(&written, &candidate.to_string()), candidate))
+        .filter(|(distance, _)| *distance <= 1)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The name closest to one that doesn't exist, if any is close enough to be a typo of it.
+fn suggest_symbol<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        // Ties go to the first name alphabetically so the suggestion doesn't depend on hashing.
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // The distances from the prefix of `a` seen so far to every prefix of `b`.
+    let mut distances: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut previous_diagonal = distances[0];
+        distances[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous_diagonal + usize::from(a_char != *b_char);
+            previous_diagonal = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(distances[j + 1] + 1);
+        }
+    }
+
+    distances[b.len()]
+}
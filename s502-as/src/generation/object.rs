@@ -0,0 +1,580 @@
+//! The file output for objects that are linked later.
+//!
+//! An object file starts with the magic bytes `s5o` and a format version byte,
+//! followed by the version of s502-as that assembled it and the name of the
+//! processor it was assembled for, like `65C02`, as strings. Then comes a long
+//! with the number of sections. Numbers are little endian, "word" means 2 bytes
+//! and "long" means 4. A string is a word with its length in bytes followed by
+//! that many bytes of UTF-8.
+//!
+//! Each section is:
+//! - its name as a string
+//! - a long with the offset past its highest byte
+//! - a word with the boundary the section has to be placed on
+//! - a long with the number of used ranges, then for each one a long with the
+//!   start offset, a long with the end offset, and the bytes in between
+//! - a long with the number of ranges reserved with `ds`, then for each one a
+//!   long with the start offset and a long with the end offset
+//! - a long with the number of labels, then for each one its name as a string,
+//!   a byte that is 1 if it's global and 0 if not, and a long with its offset
+//! - a long with the number of references, then for each one:
+//!   - the referenced name as a string
+//!   - a long with the offset in the section where the value goes
+//!   - a word added to the referenced address, from an index like `table[3]` or
+//!     an expression like `label+3`
+//!   - a byte with the modifier: 0 for none, 1 for `<`, and 2 for `>`. If there
+//!     is one then two longs follow with the start and end of its span
+//!   - a byte of flags: bit 0 is set for a branch, bit 1 if the name is a
+//!     section whose size is referenced instead of a label, bit 2 if another
+//!     label's address is subtracted, like in `end-start`, and bit 3 if the name
+//!     is a section whose start is referenced, like in `$`
+//!   - if bit 2 is set, the name of the subtracted label as a string
+//!   - the file name as a string and two longs with the span of the reference,
+//!     so the linker can point to it in errors
+//!
+//! After the sections is a byte that is 1 if the program gave an entry point with
+//! `entry`, followed by the name of its label as a string, or 0 if it didn't.
+
+use std::{fs, ops::Range};
+
+use super::*;
+
+/// The first bytes of every object file.
+const MAGIC: &[u8; 3] = b"s5o";
+/// Changed whenever the layout changes so old objects are rejected.
+const VERSION: u8 = 9;
+
+/// Write the sections, the entry point if there is one, and everything needed to
+/// link them to an object file.
+pub fn emit_object(
+    object: &Object,
+    entry: Option<&str>,
+    cpu: Cpu,
+    output_filename: &String,
+) -> Result<(), AssemblerError> {
+    fs::write(output_filename, encode_object(object, entry, cpu)?)
+        .map_err(|error| binary::write_error("object file", output_filename, error))
+}
+
+/// Write the global labels of each section with their offsets into it, one per
+/// line as `name section $offset`, for other sources to know what they can use.
+pub fn create_symbol_table(object: &Object, file_name: String) -> Result<(), AssemblerError> {
+    let symbols = object
+        .iter()
+        .flat_map(|section| {
+            section
+                .labels
+                .iter()
+                .filter(|label| label.visibility == Visibility::Global)
+                .map(|label| format!("{} {} ${:04x}\n", label.name, section.name, label.offset))
+        })
+        .collect::<String>();
+
+    fs::write(&file_name, symbols)
+        .map_err(|error| binary::write_error("symbol table", &file_name, error))
+}
+
+/// Write a listing of the assembled code. Addresses aren't known until the
+/// sections are linked, so each line shows its offset into its section, and a
+/// comment names the section whenever the code moves to a different one.
+#[allow(clippy::too_many_arguments)]
+pub fn create_listing(
+    object: &Object,
+    listings: &[Listing],
+    source_name: &str,
+    cpu: Cpu,
+    deterministic: bool,
+    code_only: bool,
+    canonical_numbers: bool,
+    file_name: String,
+) -> Result<(), AssemblerError> {
+    let mut listing = binary::listing_header(source_name, cpu, deterministic);
+    let mut current_section = None;
+
+    for line in listings {
+        if let Some((section_index, start, end)) = line.location {
+            if code_only && end == start {
+                continue;
+            }
+            if current_section != Some(section_index) {
+                listing.push(format!(
+                    "              * Section: {}",
+                    object[section_index].name
+                ));
+                current_section = Some(section_index);
+            }
+        }
+        listing.push(binary::listing_line(object, line, canonical_numbers));
+    }
+
+    listing.push(binary::listing_footer(object));
+    fs::write(&file_name, listing.join("\n"))
+        .map_err(|error| binary::write_error("listing file", &file_name, error))
+}
+
+/// The contents of an object file, laid out as described at the top.
+fn encode_object(
+    object: &Object,
+    entry: Option<&str>,
+    cpu: Cpu,
+) -> Result<Vec<u8>, AssemblerError> {
+    let mut bytes = Vec::with_capacity(1024);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    write_string(&mut bytes, env!("CARGO_PKG_VERSION"))?;
+    write_string(&mut bytes, cpu.name())?;
+    write_long(&mut bytes, object.len());
+
+    for section in object {
+        write_string(&mut bytes, &section.name)?;
+        write_long(&mut bytes, section.highest_origin);
+        write_word(&mut bytes, section.alignment);
+
+        let ranges = binary::merge_ranges(section.used_ranges.clone());
+        write_long(&mut bytes, ranges.len());
+        for range in ranges {
+            write_long(&mut bytes, range.start);
+            write_long(&mut bytes, range.end);
+            bytes.extend_from_slice(&section.data[range]);
+        }
+
+        write_long(&mut bytes, section.reserved_ranges.len());
+        for range in &section.reserved_ranges {
+            write_span(&mut bytes, range);
+        }
+
+        write_long(&mut bytes, section.labels.len());
+        for label in &section.labels {
+            write_string(&mut bytes, &label.name)?;
+            bytes.push((label.visibility == Visibility::Global) as u8);
+            write_long(&mut bytes, label.offset);
+        }
+
+        write_long(&mut bytes, section.references.len());
+        for reference in &section.references {
+            write_string(&mut bytes, &reference.name)?;
+            write_long(&mut bytes, reference.offset);
+            write_word(&mut bytes, reference.addend as usize);
+            match &reference.modifier {
+                None => bytes.push(0),
+                Some(modifier) => {
+                    bytes.push(match modifier.val {
+                        Modifier::HighByte => 1,
+                        Modifier::LowByte => 2,
+                    });
+                    write_span(&mut bytes, &modifier.span);
+                }
+            }
+            bytes.push(
+                reference.branch as u8
+                    | (reference.section_size as u8) << 1
+                    | (reference.subtrahend.is_some() as u8) << 2
+                    | (reference.section_start as u8) << 3,
+            );
+            if let Some(subtrahend) = &reference.subtrahend {
+                write_string(&mut bytes, subtrahend)?;
+            }
+            write_string(&mut bytes, &reference.location.file_name)?;
+            write_span(&mut bytes, &reference.location.span);
+        }
+    }
+
+    match entry {
+        None => bytes.push(0),
+        Some(entry) => {
+            bytes.push(1);
+            write_string(&mut bytes, entry)?;
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Read the sections and the entry point back from the contents of an object file
+/// that's linked for the given processor. An object from another version of s502-as
+/// is rejected, as is one for the 65C02 linked for a 6502, which lacks its
+/// instructions.
+pub fn read_object(
+    file_name: &str,
+    bytes: &[u8],
+    cpu: Cpu,
+) -> Result<(Object, Option<String>), AssemblerError> {
+    let mut reader = ObjectReader { bytes, position: 0 };
+
+    if reader.take(MAGIC.len()) != Some(MAGIC) {
+        return Err(AssemblerError {
+            message: format!("`{}` is not an object file", file_name),
+            labels: vec![],
+            help: None,
+        });
+    }
+    let version = reader.byte();
+    if version != Some(VERSION) {
+        return Err(AssemblerError {
+            message: format!("`{}` is from a different version of s502-as", file_name),
+            labels: vec![],
+            help: Some(String::from("Assemble it again with this version")),
+        });
+    }
+
+    let ends_early = || AssemblerError {
+        message: format!("Object file `{}` ends too early", file_name),
+        labels: vec![],
+        help: None,
+    };
+
+    let assembler_version = reader.string().ok_or_else(ends_early)?;
+    if assembler_version != env!("CARGO_PKG_VERSION") {
+        return Err(AssemblerError {
+            message: format!(
+                "`{}` was assembled by s502-as {}, not {}",
+                file_name,
+                assembler_version,
+                env!("CARGO_PKG_VERSION")
+            ),
+            labels: vec![],
+            help: Some(String::from("Assemble it again with this version")),
+        });
+    }
+
+    let object_cpu = reader
+        .string()
+        .and_then(|name| Cpu::from_name(&name))
+        .ok_or_else(ends_early)?;
+    if object_cpu != cpu && object_cpu != Cpu::Nmos6502 {
+        return Err(AssemblerError {
+            message: format!(
+                "`{}` was assembled for the {}, which the {} can't run",
+                file_name,
+                object_cpu.name(),
+                cpu.name()
+            ),
+            labels: vec![],
+            help: Some(format!(
+                "Assemble it again with `--cpu {}`",
+                cpu.name().to_lowercase()
+            )),
+        });
+    }
+
+    reader
+        .sections()
+        .and_then(|object| Some((object, reader.entry()?)))
+        .ok_or_else(ends_early)
+}
+
+fn write_word(bytes: &mut Vec<u8>, word: usize) {
+    bytes.extend_from_slice(&(word as u16).to_le_bytes());
+}
+
+fn write_long(bytes: &mut Vec<u8>, long: usize) {
+    bytes.extend_from_slice(&(long as u32).to_le_bytes());
+}
+
+/// Write a string, which is an error if its length doesn't fit in the word before it.
+fn write_string(bytes: &mut Vec<u8>, string: &str) -> Result<(), AssemblerError> {
+    if string.len() > u16::MAX as usize {
+        return Err(AssemblerError {
+            message: format!(
+                "The name `{}...` is too long to write in an object file",
+                string.chars().take(32).collect::<String>()
+            ),
+            labels: vec![],
+            help: Some(format!(
+                "Names in object files are at most {} bytes long",
+                u16::MAX
+            )),
+        });
+    }
+
+    write_word(bytes, string.len());
+    bytes.extend_from_slice(string.as_bytes());
+    Ok(())
+}
+
+fn write_span(bytes: &mut Vec<u8>, span: &Range<usize>) {
+    write_long(bytes, span.start);
+    write_long(bytes, span.end);
+}
+
+/// Reads the parts of an object file in order. Each method returns None if the
+/// file ends before the part does or the part is invalid.
+struct ObjectReader<'bytes> {
+    bytes: &'bytes [u8],
+    position: usize,
+}
+
+impl<'bytes> ObjectReader<'bytes> {
+    fn take(&mut self, count: usize) -> Option<&'bytes [u8]> {
+        let taken = self.bytes.get(self.position..self.position + count)?;
+        self.position += count;
+        Some(taken)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn word(&mut self) -> Option<usize> {
+        self.take(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    fn long(&mut self) -> Option<usize> {
+        self.take(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = self.word()?;
+        String::from_utf8(self.take(length)?.to_vec()).ok()
+    }
+
+    fn span(&mut self) -> Option<Range<usize>> {
+        Some(self.long()?..self.long()?)
+    }
+
+    fn sections(&mut self) -> Option<Object> {
+        (0..self.long()?).map(|_| self.section()).collect()
+    }
+
+    /// The entry point, which is Some(None) if the object doesn't have one.
+    fn entry(&mut self) -> Option<Option<String>> {
+        match self.byte()? {
+            0 => Some(None),
+            1 => Some(Some(self.string()?)),
+            _ => None,
+        }
+    }
+
+    fn section(&mut self) -> Option<Section> {
+        let name = self.string()?;
+        let highest_origin = self.long()?;
+        let alignment = self.word()?;
+        if highest_origin > 0x10000 || alignment == 0 {
+            return None;
+        }
+
+        let mut data = Vec::new();
+        let mut used_ranges = Vec::new();
+        for _ in 0..self.long()? {
+            let range = self.span()?;
+            if range.start > range.end || range.end > highest_origin {
+                return None;
+            }
+            if data.len() < range.end {
+                data.resize(range.end, 0);
+            }
+            data[range.clone()].copy_from_slice(self.take(range.len())?);
+            used_ranges.push(range);
+        }
+
+        let reserved_ranges = (0..self.long()?)
+            .map(|_| self.span().filter(|range| range.end <= highest_origin))
+            .collect::<Option<Vec<Range<usize>>>>()?;
+
+        let labels = (0..self.long()?)
+            .map(|_| {
+                Some(SectionLabel {
+                    name: self.string()?,
+                    visibility: if self.byte()? == 1 {
+                        Visibility::Global
+                    } else {
+                        Visibility::Object
+                    },
+                    offset: self.long()?,
+                })
+            })
+            .collect::<Option<Vec<SectionLabel>>>()?;
+
+        let references = (0..self.long()?)
+            .map(|_| self.reference())
+            .collect::<Option<Vec<Reference>>>()?;
+
+        Some(Section {
+            name,
+            data,
+            origin: highest_origin,
+            lowest_origin: used_ranges.first().map(|range| range.start),
+            highest_origin,
+            used_ranges,
+            reserved_ranges,
+            alignment,
+            labels,
+            references,
+            location: None,
+        })
+    }
+
+    fn reference(&mut self) -> Option<Reference> {
+        let name = self.string()?;
+        let offset = self.long()?;
+        let addend = self.word()? as u16;
+        let modifier = match self.byte()? {
+            0 => None,
+            1 => Some(Spanned::new((Modifier::HighByte, self.span()?))),
+            2 => Some(Spanned::new((Modifier::LowByte, self.span()?))),
+            _ => return None,
+        };
+        let flags = self.byte()?;
+        let subtrahend = if flags & 4 != 0 {
+            Some(self.string()?)
+        } else {
+            None
+        };
+        let file_name = self.string()?;
+        let span = self.span()?;
+
+        Some(Reference {
+            name,
+            offset,
+            modifier,
+            branch: flags & 1 != 0,
+            section_size: flags & 2 != 0,
+            section_start: flags & 8 != 0,
+            addend,
+            subtrahend,
+            location: Location { span, file_name },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An object read back from its file has the same sections.
+    #[test]
+    fn round_trip() {
+        let data = vec![0x4c, 0x00, 0x00, 0xea];
+        let object = vec![Section {
+            name: String::from("code"),
+            data,
+            origin: 8,
+            lowest_origin: Some(0),
+            highest_origin: 8,
+            used_ranges: vec![0..3, 3..4],
+            reserved_ranges: vec![4..6, 7..8],
+            alignment: 256,
+            labels: vec![
+                SectionLabel {
+                    name: String::from("start"),
+                    visibility: Visibility::Global,
+                    offset: 0,
+                },
+                SectionLabel {
+                    name: String::from("start.loop"),
+                    visibility: Visibility::Object,
+                    offset: 3,
+                },
+            ],
+            references: vec![Reference {
+                name: String::from("data"),
+                offset: 1,
+                modifier: Some(Spanned::new((Modifier::LowByte, 10..11))),
+                branch: false,
+                section_size: false,
+                section_start: false,
+                addend: 3,
+                subtrahend: Some(String::from("start")),
+                location: Location {
+                    span: 10..15,
+                    file_name: String::from("test.65a"),
+                },
+            }],
+            location: None,
+        }];
+
+        let bytes = encode_object(&object, Some("start"), Cpu::Cmos65c02).unwrap();
+        let (read, entry) = read_object("test.65o", &bytes, Cpu::Cmos65c02).unwrap();
+        assert_eq!(entry.as_deref(), Some("start"));
+        assert_eq!(read.len(), 1);
+        let section = &read[0];
+        assert_eq!(section.name, "code");
+        assert_eq!(section.highest_origin, 8);
+        assert_eq!(section.lowest_origin, Some(0));
+        // Adjacent ranges are merged when written.
+        assert_eq!(section.used_ranges, vec![0..4]);
+        assert_eq!(section.reserved_ranges, object[0].reserved_ranges);
+        assert_eq!(section.alignment, 256);
+        assert_eq!(section.data[0..4], [0x4c, 0x00, 0x00, 0xea]);
+        assert_eq!(section.labels, object[0].labels);
+        assert_eq!(section.references, object[0].references);
+
+        // The header names the version of s502-as and the processor.
+        let header = [
+            MAGIC.as_slice(),
+            &[VERSION],
+            &(env!("CARGO_PKG_VERSION").len() as u16).to_le_bytes(),
+            env!("CARGO_PKG_VERSION").as_bytes(),
+            b"\x05\x0065C02",
+        ]
+        .concat();
+        assert!(bytes.starts_with(&header));
+
+        // Code for the 65C02 can't be linked for a 6502, but the other way around can.
+        let error = read_object("test.65o", &bytes, Cpu::Nmos6502)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.message,
+            "`test.65o` was assembled for the 65C02, which the 6502 can't run"
+        );
+        let bytes = encode_object(&object, None, Cpu::Nmos6502).unwrap();
+        assert!(read_object("test.65o", &bytes, Cpu::Cmos65c02).is_ok());
+
+        // An object from another version of s502-as is rejected.
+        let mut other_version = header[..MAGIC.len() + 1].to_vec();
+        other_version.extend_from_slice(b"\x05\x000.0.0");
+        other_version
+            .extend_from_slice(&bytes[MAGIC.len() + 3 + env!("CARGO_PKG_VERSION").len()..]);
+        let error = read_object("test.65o", &other_version, Cpu::Nmos6502)
+            .err()
+            .unwrap();
+        assert!(error
+            .message
+            .starts_with("`test.65o` was assembled by s502-as 0.0.0"));
+    }
+
+    /// Counts are longs, so a section can have more labels than fit in a word.
+    #[test]
+    fn many_labels() {
+        let mut section = Section::new(String::from("code"), None);
+        section.labels = (0..70000)
+            .map(|index| SectionLabel {
+                name: format!("label{}", index),
+                visibility: Visibility::Object,
+                offset: 0,
+            })
+            .collect();
+
+        let bytes = encode_object(&vec![section], None, Cpu::Nmos6502).unwrap();
+        let (read, entry) = read_object("test.65o", &bytes, Cpu::Nmos6502).unwrap();
+        assert_eq!(entry, None);
+        assert_eq!(read[0].labels.len(), 70000);
+        assert_eq!(read[0].labels[69999].name, "label69999");
+    }
+
+    /// A name too long for its length to fit in a word is an error instead of a
+    /// corrupt object.
+    #[test]
+    fn long_name() {
+        let object = vec![Section::new("a".repeat(70000), None)];
+        let error = encode_object(&object, None, Cpu::Nmos6502).err().unwrap();
+        assert_eq!(
+            error.message,
+            format!(
+                "The name `{}...` is too long to write in an object file",
+                "a".repeat(32)
+            )
+        );
+        assert!(encode_object(&object, Some(&"a".repeat(65535)), Cpu::Nmos6502).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let truncated = [MAGIC.as_slice(), &[VERSION, 1]].concat();
+        assert!(read_object("test.65o", &truncated, Cpu::Nmos6502).is_err());
+        assert!(read_object("test.65o", b"bin", Cpu::Nmos6502).is_err());
+    }
+}
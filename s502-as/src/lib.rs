@@ -0,0 +1,107 @@
+//! The s502 assembler as a library, for embedding it in other programs like
+//! emulators and editors. The `s502-as` binary is a command line interface
+//! over the same modules.
+//!
+//! ```
+//! let (object, _listings) =
+//!     s502_as::assemble_source("example.65a", "    lda #$01\n    jmp end\nend\n", true)
+//!         .unwrap();
+//! assert_eq!(object[0].data[..5], [0xa9, 0x01, 0x4c, 0x05, 0x00]);
+//! ```
+
+#[macro_use]
+extern crate indoc;
+
+use std::collections::HashMap;
+
+use codespan_reporting::files::SimpleFiles;
+
+use ast::{Cpu, Include, Location};
+use error::{AssemblerError, Warning};
+use generation::{GeneratorContext, Listing, Object};
+use parser::ParserContext;
+
+pub mod ast;
+pub mod equates;
+pub mod error;
+pub mod generation;
+mod lines;
+pub mod parser;
+pub mod pragma;
+pub mod script;
+
+/// Assemble source code that isn't read from a file, returning the sections it
+/// assembled to and the listing of each line. Files it includes with `inl` are
+/// still read relative to the working directory. In binary mode the sections
+/// are placed at their addresses and references to labels are resolved,
+/// otherwise they're relocatable like in an object file.
+///
+/// Warnings are left out.
+pub fn assemble_source(
+    name: &str,
+    source: &str,
+    binary: bool,
+) -> Result<(Object, Vec<Listing>), Vec<AssemblerError>> {
+    assemble_source_for(
+        name,
+        source,
+        binary,
+        Cpu::Nmos6502,
+        &mut SimpleFiles::new(),
+        &mut HashMap::new(),
+        &mut vec![],
+    )
+}
+
+/// Assemble source code like [`assemble_source`] for the given processor. The
+/// files it reads are added to `files` and `id_table` and its warnings to
+/// `warnings`, so they can be shown with [`error::report_errors`] and
+/// [`error::report_warnings`].
+pub fn assemble_source_for(
+    name: &str,
+    source: &str,
+    binary: bool,
+    cpu: Cpu,
+    files: &mut SimpleFiles<String, String>,
+    id_table: &mut HashMap<String, usize>,
+    warnings: &mut Vec<Warning>,
+) -> Result<(Object, Vec<Listing>), Vec<AssemblerError>> {
+    // The top level source is included by the caller instead of the command line.
+    let mut include_stack = vec![Include {
+        included: name.to_string(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<command line>".to_string(),
+        },
+    }];
+
+    let program_result = ParserContext::new(
+        name.to_string(),
+        source,
+        files,
+        &mut include_stack,
+        id_table,
+    )
+    .parse_program();
+
+    let file_id = files.add(name.to_string(), source.to_string());
+    id_table.insert(name.to_string(), file_id);
+    id_table.insert("<command line>".to_string(), file_id);
+
+    let (mut object, listings, cross_references, _) = GeneratorContext::new(
+        program_result?,
+        binary,
+        name.to_string(),
+        id_table,
+        files,
+        warnings,
+    )
+    .with_cpu(cpu)
+    .generate_code()?;
+
+    if binary {
+        generation::binary::resolve_binary(&mut object, &cross_references)?;
+    }
+
+    Ok((object, listings))
+}
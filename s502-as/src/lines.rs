@@ -0,0 +1,133 @@
+//! Files that are read one entry per line, like layout scripts and equates files.
+//!
+//! Numbers are written the same way as in assembly source, and `*` starts a comment
+//! that runs to the end of the line.
+
+use std::ops::Range;
+
+use logos::Logos;
+
+use crate::{ast::Location, error::AssemblerError, parser::lexer::Token};
+
+/// How a kind of file is described in its errors.
+pub(crate) struct LineFormat {
+    /// What the file is called, like `layout script`.
+    pub name: &'static str,
+    /// Help describing a line, given with an unexpected token.
+    pub help: &'static str,
+    /// The message for an entry that is given more than once.
+    pub duplicate: fn(&str) -> String,
+    /// The label pointing at where the entry was first given.
+    pub first: &'static str,
+}
+
+/// The tokens of one line, without its comment.
+pub(crate) struct Line<'format> {
+    tokens: std::vec::IntoIter<(Token, Range<usize>)>,
+    format: &'format LineFormat,
+    /// From the first token of the line to the last.
+    pub location: Location,
+}
+
+impl Line<'_> {
+    /// An error for a token that isn't what was expected at its place in the line.
+    pub fn unexpected(&self, token: Token, span: Range<usize>, expected: &str) -> AssemblerError {
+        AssemblerError {
+            message: format!("Expected {} in {}", expected, self.format.name),
+            labels: vec![(
+                Location {
+                    span,
+                    file_name: self.location.file_name.clone(),
+                },
+                Some(format!("Found {}", token)),
+            )],
+            help: Some(String::from(self.format.help)),
+        }
+    }
+}
+
+impl Iterator for Line<'_> {
+    type Item = (Token, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tokens.next()
+    }
+}
+
+/// Parse each line that isn't empty with `parse_line`, which returns the name of the
+/// entry and the entry. Tokens left after it are an error, as is a name that was
+/// already given. Returns the entries in the order they are written.
+pub(crate) fn parse_lines<T>(
+    file_name: &str,
+    source: &str,
+    format: &LineFormat,
+    mut parse_line: impl FnMut(&mut Line) -> Result<(String, T), AssemblerError>,
+) -> Result<Vec<T>, Vec<AssemblerError>> {
+    let mut entries: Vec<(String, T, Location)> = Vec::new();
+    let mut errors = Vec::new();
+    let mut lexer = Token::lexer(source).spanned().peekable();
+
+    while lexer.peek().is_some() {
+        // Collect the tokens of this line. Nothing multiplies here, so a `*` after
+        // the values starts a comment.
+        let mut tokens = Vec::new();
+        let mut comment = false;
+        for (token, span) in lexer.by_ref() {
+            match token {
+                Token::Eol => break,
+                Token::Star => comment = true,
+                _ if !comment => tokens.push((token, span)),
+                _ => {}
+            }
+        }
+
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let mut line = Line {
+            location: Location {
+                span: tokens[0].1.start..tokens.last().unwrap().1.end,
+                file_name: file_name.to_string(),
+            },
+            tokens: tokens.into_iter(),
+            format,
+        };
+
+        let (name, entry) = match parse_line(&mut line) {
+            Ok(entry) => entry,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+
+        if let Some((token, span)) = line.next() {
+            errors.push(line.unexpected(token, span, "the end of the line"));
+            continue;
+        }
+
+        if let Some((_, _, first_location)) = entries
+            .iter()
+            .find(|(first_name, _, _)| *first_name == name)
+        {
+            errors.push(AssemblerError {
+                message: (format.duplicate)(&name),
+                labels: vec![
+                    (line.location, None),
+                    (first_location.clone(), Some(String::from(format.first))),
+                ],
+                help: None,
+            });
+            continue;
+        }
+
+        entries.push((name, entry, line.location));
+    }
+
+    if !errors.is_empty() {
+        Err(errors)
+    } else {
+        Ok(entries.into_iter().map(|(_, entry, _)| entry).collect())
+    }
+}
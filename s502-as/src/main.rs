@@ -3,26 +3,28 @@ extern crate afl;
 #[macro_use]
 extern crate indoc;
 
-use std::{collections::HashMap, fs, path::Path};
-
-use ast::{Include, Location};
-use clap::{arg, command};
-use codespan_reporting::{
-    diagnostic::Diagnostic,
-    files::SimpleFiles,
-    term::{
-        self,
-        termcolor::{ColorChoice, StandardStream},
-        Config,
-    },
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+    thread,
 };
 
-mod ast;
-mod error;
-mod generation;
-mod parser;
+use ast::{Cpu, Include, Location};
+use clap::{arg, command, value_parser, ArgAction};
+use codespan_reporting::files::SimpleFiles;
 
-use error::report_errors;
+use s502_as::{ast, equates, error, generation, parser, pragma, script};
+
+use error::{report_errors, report_warnings, WarningCategory, WarningLevel, WarningSettings};
+
+/// The exit status when a source or a file shared by every source couldn't be
+/// read or assembled.
+const EXIT_FAILED: i32 = 1;
+/// The exit status when the command line is invalid, the same as for the usage
+/// errors that clap reports.
+const EXIT_USAGE: i32 = 2;
 
 /// The normal entry point for running.
 #[cfg(not(fuzzing))]
@@ -43,7 +45,10 @@ fn main() {
         .arg(
             arg!(-l --listing "Output a listing file").long_help(indoc! {
                 "Output a listing file with the assembled
-                binary code alongside the source code."
+                binary code alongside the source code.
+
+                Without -b, addresses aren't known until the object is linked, so
+                each line shows its offset into its section instead."
             }),
         )
         .arg(
@@ -58,7 +63,335 @@ fn main() {
                 See the `-o` option for details on specifying the file name."
             }),
         )
-        // TODO Maybe add -p pic platform independent code, pass to emit_binary, error if using an absaolute reference to label
+        .arg(
+            arg!(--equates [EQUATES] "File of names for fixed addresses").long_help(indoc! {
+                "A file naming fixed addresses, like hardware registers, that can be shared
+                between programs.
+
+                Each line has the form `name address`. Each name becomes a label at its
+                address, so unlike an `equ` macro it is listed in symbol tables. Only
+                allowed in binary mode, where addresses are known."
+            }),
+        )
+        .arg(
+            arg!(--script [SCRIPT] "Layout script placing sections in binary mode").long_help(
+                indoc! {
+                    "A layout script that places sections in memory.
+
+                    This lets binary mode use the `sct` directive. Each line of the script
+                    has the form `\"name\" address [fill]` and places the section with that
+                    name at the address. The optional fill byte pads the gap between the
+                    end of the section and the next one in the output binary."
+                },
+            ),
+        )
+        .arg(
+            arg!(--org [ADDRESS] "Initial origin in binary mode").long_help(indoc! {
+                "Set the address that code starts at in binary mode.
+
+                This is equivalent to an `org` directive at the start of the program, and
+                an `org` in the source still changes it. The address is written the same
+                way as a number in source code, such as `$2000`."
+            }),
+        )
+        .arg(
+            arg!(--define <DEFINITION> "Define a macro, like `DEBUG=1`")
+                .required(false)
+                .action(ArgAction::Append)
+                .long_help(indoc! {
+                    "Define a macro before assembling, as if the source started with
+                    `NAME equ VALUE`, so build variants don't need the source edited. This may
+                    be given more than once.
+
+                    The value is a number, which is a byte if it fits in one and a word
+                    otherwise, or a string in double quotes like `NAME=\"game\"`. Defining a
+                    macro in the source with the same name is an error."
+                }),
+        )
+        .arg(
+            arg!(--"rom-range" <RANGE> "Warn about writes to a range of ROM")
+                .required(false)
+                .action(ArgAction::Append)
+                .long_help(indoc! {
+                    "Declare a range of addresses as ROM, like `$e000-$ffff`, and warn about
+                    instructions that write to it. This may be given more than once.
+
+                    Only writes to literal addresses and macros are checked, not labels."
+                }),
+        )
+        .arg(
+            arg!(--lint "Warn about redundant instruction sequences").long_help(indoc! {
+                "Warn about sequences of instructions that are likely mistakes.
+
+                This detects a transfer that is immediately undone, like `tax` followed by
+                `txa`, and an `lda` followed by an `sta` to the same address."
+            }),
+        )
+        .arg(
+            arg!(--pedantic "Enable every category of warning").long_help(indoc! {
+                "Enable every category of warning, including the lints that are off by
+                default. Combine it with -Werror to make all of them errors, or use -Wno- to
+                turn some back off."
+            }),
+        )
+        .arg(
+            arg!(--manifest [MANIFEST] "Output a JSON manifest of the binary").long_help(indoc! {
+                "Write a JSON summary of the build to the given file in binary mode.
+
+                The manifest lists each output file with its format, size, and a hash of
+                its contents, then the number of symbols and the base address and size of
+                each section. Its contents only depend on the assembled program."
+            }),
+        )
+        .arg(
+            arg!(--"expect-hash" <HASH> "Fail if the binary's hash is different")
+                .required(false)
+                .long_help(indoc! {
+                    "Check the hash of the binary against the given one in binary mode, and
+                    report an error if they're different so a CI build can catch a change in
+                    the generated bytes.
+
+                    The hash is the one written to the manifest for a `bin` output, either
+                    with or without its `fnv1a64:` prefix. Use --deterministic so the hash doesn't change between
+                    builds of the same source."
+                }),
+        )
+        .arg(
+            arg!(--coverage [COVERAGE] "Output the address ranges used by each section").long_help(
+                indoc! {
+                    "Write the address ranges that have code in them to the given file in
+                    binary mode.
+
+                    Each line has a section name followed by a range of addresses written as
+                    `$start-$end`, where the end is the last used address. Adjacent and
+                    overlapping ranges are merged and sorted by address."
+                },
+            ),
+        )
+        .arg(
+            arg!(--"symbol-format" [FORMAT] "Format of the symbol table")
+                .value_parser(["s502", "mesen"])
+                .long_help(indoc! {
+                    "The format of the symbol table written with -s in binary mode.
+
+                    `s502` writes <source_file_name>_symbols.65a with an `equ` macro for each
+                    exported label. `mesen` writes <source_file_name>.mlb, a Mesen label file
+                    with every label given as an offset into the binary. Sublabels are written
+                    with `_` in place of `.`."
+                }),
+        )
+        .arg(
+            arg!(--"symbols-only" "Only output the symbol table").long_help(indoc! {
+                "Assemble the program and write its symbol table without writing the binary
+                or any other output in binary mode.
+
+                This implies -s, and the symbol table is written the same way."
+            }),
+        )
+        .arg(
+            arg!(--deterministic "Leave build metadata out of the outputs").long_help(indoc! {
+                "Leave out anything that changes between builds of the same source, like
+                the date in the listing header, so the outputs can be compared. The
+                `datestamp` directive writes zeros instead of the date."
+            }),
+        )
+        .arg(
+            arg!(--format <FORMAT> "Format of the output binary")
+                .required(false)
+                .value_parser(["bin", "rle", "ihex", "srec", "basic-poke", "flat64k"])
+                .action(ArgAction::Append)
+                .long_help(indoc! {
+                    "The format of the output file in binary mode. This may be given more than
+                    once to write each format, and then each file's extension is the format's:
+                    `.bin`, `.rle`, `.hex`, `.s19`, `.bas`, or `.img`.
+
+                    `bin` writes the raw bytes and `ihex` writes them as Intel HEX records.
+                    `srec` writes the used addresses as Motorola S-records.
+                    `basic-poke` writes a BASIC program that pokes the used addresses. Its
+                    lines are numbered from 10 in steps of 10, with a FOR loop for each run
+                    of addresses that READs and POKEs its bytes, then END, then the bytes in
+                    DATA lines of up to 16 each.
+                    `flat64k` writes all 65536 bytes of memory with the code at its addresses
+                    and every other byte set by --fill, for emulators that load a full image.
+                    `rle` writes the used addresses run-length encoded for a small
+                    decompression routine, and they have to be contiguous.
+                    It starts with the load address as a little endian word, followed by
+                    packets that each start with a control byte. A control byte of 0 ends the
+                    data, one below $80 is followed by that many bytes to copy, and one of $80
+                    or above is followed by a single byte to repeat (control - $7e) times."
+                }),
+        )
+        .arg(
+            arg!(--fill [BYTE] "Byte that fills memory outside of the code in flat64k output")
+                .long_help(indoc! {
+                    "The byte that fills every address nothing was assembled to in
+                    `--format flat64k` output. It's written the same way as a number in source
+                    code, such as `$ea`, and defaults to 0."
+                }),
+        )
+        .arg(
+            arg!(-W <WARNING> "Enable, disable, or make errors of warnings")
+                .required(false)
+                .action(ArgAction::Append)
+                .long_help(indoc! {
+                    "Control a category of warnings. This may be given more than once and later
+                    options override earlier ones.
+
+                    -W<category> enables the category, -Wno-<category> disables it,
+                    -Werror=<category> makes it an error, and -Werror makes every enabled
+                    category an error. Errors stop the output from being written.
+
+                    The categories are `empty-section`, `redundant` (off by default, the same
+                    as --lint), `jmp-indirect-bug`, `indent` (only checked with --indent),
+                    `unused-macro` (off by default), `overwrite` (only checked with -b),
+                    `rom-write` (only checked with --rom-range), `immediate-address`,
+                    `label-case`, and `overflow`.
+                    --pedantic enables all of them."
+                }),
+        )
+        .arg(
+            arg!(--"message-format" [FORMAT] "Format of errors and warnings")
+                .value_parser(["human", "json"])
+                .long_help(indoc! {
+                    "How errors and warnings about the source are written to stderr.
+
+                    `human`, the default, shows excerpts of the source pointing at each
+                    problem. `json` writes each one as a JSON object on its own line, with
+                    `severity`, `message`, `help`, and `labels`. Each label has the `file`,
+                    the `start` and `end` byte offsets into it, and its `label` text, and
+                    the first one is where the problem is."
+                }),
+        )
+        .arg(
+            arg!(--"listing-code-only" "Only list lines that produced bytes").long_help(indoc! {
+                "Leave lines that didn't assemble to any bytes, like comments, blank lines,
+                and `equ` directives, out of the listing file."
+            }),
+        )
+        .arg(
+            arg!(--"listing-canon-numbers" "Write numbers in the listing in hex").long_help(
+                indoc! {
+                    "Rewrite the literal numbers in operands in the listing file as hex, like
+                    `$0a` for `10` or `%1010`, so they read the same way however they were
+                    written in the source."
+                },
+            ),
+        )
+        .arg(
+            arg!(--"listing-notes" "Show warnings beneath their lines in the listing").long_help(
+                indoc! {
+                    "Write each warning that isn't turned off into the listing file beneath
+                    the line it's about, so the listing can be reviewed on its own."
+                },
+            ),
+        )
+        .arg(
+            arg!(--"strict-sections" "Require sections to be declared before use").long_help(
+                indoc! {
+                    "Require every section to be declared with the `sctdef` directive, like
+                    `sctdef \"code\"`, before the `sct` directive uses it. Otherwise a
+                    misspelled section name silently creates a new section."
+                },
+            ),
+        )
+        .arg(
+            arg!(--"strict-align" "Require `align` boundaries to be powers of two").long_help(
+                indoc! {
+                    "Make it an error to give the `align` directive a boundary that isn't a
+                    power of two, like `align 100` instead of `align 128`."
+                },
+            ),
+        )
+        .arg(
+            arg!(--"case-sensitive-labels" [BOOL] "Whether label names are case sensitive")
+                .value_parser(value_parser!(bool))
+                .long_help(indoc! {
+                    "Whether labels and macros whose names only differ in case, like `Loop` and
+                    `loop`, are different. This is `true` by default, and the `label-case`
+                    warning points out names that only differ in case. With
+                    --case-sensitive-labels=false they are the same, and names are written in
+                    lowercase in symbol tables."
+                }),
+        )
+        .arg(
+            arg!(--indent [INDENTATION] "Warn about lines indented the other way")
+                .value_parser(["tabs", "spaces"])
+                .long_help(indoc! {
+                    "Warn about lines that are indented with a character other than the given
+                    one. This is controlled by the `indent` warning category."
+                }),
+        )
+        .arg(
+            arg!(--"debug-bundle" [BUNDLE] "Output a JSON file of debugging information")
+                .long_help(indoc! {
+                    "Write the symbols, the file and line that each address was assembled from,
+                    and the layout of the sections to the given file as JSON in binary mode.
+                    Its contents only depend on the assembled program."
+                }),
+        )
+        .arg(
+            arg!(--xref [XREF] "Output a cross-reference table").long_help(indoc! {
+                "Write every label and macro to the given file in binary mode, along with the
+                file and line where it is defined and each line that references it."
+            }),
+        )
+        .arg(
+            arg!(--cpu [CPU] "The processor to assemble for")
+                .value_parser(["6502", "65c02"])
+                .long_help(indoc! {
+                    "With `6502`, the default, only the instructions of the original NMOS 6502
+                    are allowed. With `65c02`, the 65C02's `bra`, `phx`, `phy`, `plx`, `ply`,
+                    and `stz` instructions, `bit` immediate, and `(zp)` indirect addressing
+                    like `lda ($10)` are allowed too.
+
+                    A source can choose its CPU, origin, and output format itself with lines
+                    like `*pragma cpu 65c02` before its code. Options given here take
+                    precedence over them."
+                }),
+        )
+        .arg(
+            arg!(--accumulator [ACCUMULATOR] "Whether shifts and rotates need the `a` operand")
+                .value_parser(["implicit", "explicit"])
+                .long_help(indoc! {
+                    "With `explicit`, the default, accumulator mode is written with an `a`
+                    operand, like `asl a`. With `implicit`, `asl`, `lsr`, `rol`, and `ror`
+                    without an operand also act on the accumulator."
+                }),
+        )
+        .arg(
+            arg!(--"max-labels" [COUNT] "The most labels a section may have")
+                .value_parser(value_parser!(usize))
+                .long_help(indoc! {
+                    "Make it an error for a section to have more than this many labels, 65536
+                    by default, so generated code that runs away fails cleanly instead of
+                    using up memory."
+                }),
+        )
+        .arg(
+            arg!(--"max-macros" [COUNT] "The most macros a program may define")
+                .value_parser(value_parser!(usize))
+                .long_help(indoc! {
+                    "Make it an error to define more than this many macros with `equ`, 65536
+                    by default."
+                }),
+        )
+        .arg(
+            arg!(--"max-include-depth" [DEPTH] "How deeply includes may be nested")
+                .value_parser(value_parser!(usize))
+                .long_help(indoc! {
+                    "Make it an error for `inl` to nest includes more than this many files
+                    deep, 64 by default. Each level is parsed recursively, so this stops a
+                    deep chain of includes from overflowing the stack."
+                }),
+        )
+        .arg(
+            arg!(-p --pic "Assemble position independent code").long_help(indoc! {
+                "Assemble code that can run from any address.
+
+                References to labels are only allowed in branches, which are relative, so
+                an absolute address such as `jmp label` or `dfw label` is an error."
+            }),
+        )
         .arg(
             arg!(-o [OUTPUT] "Output file name")
                 .multiple_values(false)
@@ -67,13 +400,45 @@ fn main() {
                     
                     If no file extension is present then one will be added to the specified
                     name: `.bin` for a binary file, `.65o` for an object file, and `.65s` for
-                    a symbol table Otherwise, it will be used only for the output binary or
-                    object file, and the default scheme wil be used for the symbol table."
+                    a symbol table. Otherwise, it will be used only for the output binary or
+                    object file, and the default scheme will be used for the symbol table."
                 }),
         )
-        .arg(arg!(<SOURCES> "s502 source and symbol table files").multiple_values(true))
+        .arg(
+            arg!(--expr [EXPRESSION] "Assemble a single line and print its bytes")
+                .conflicts_with("SOURCES")
+                .long_help(indoc! {
+                    "Assemble a single line given on the command line, such as `lda #$05`,
+                    and print the bytes it assembles to in hex instead of writing any files."
+                }),
+        )
+        .arg(
+            arg!(<SOURCES> "s502 source and symbol table files")
+                .multiple_values(true)
+                .required_unless_present("expr"),
+        )
         .get_matches();
 
+    if arg_matches
+        .get_one::<String>("message-format")
+        .map(String::as_str)
+        == Some("json")
+    {
+        error::set_message_format(error::MessageFormat::Json);
+    }
+
+    // A pragma in the source can set the CPU if it's not given here.
+    let cli_cpu = arg_matches
+        .get_one::<String>("cpu")
+        .and_then(|name| Cpu::from_name(name));
+
+    if let Some(expression) = arg_matches.get_one::<String>("expr") {
+        if !assemble_expression(expression, cli_cpu.unwrap_or(Cpu::Nmos6502)) {
+            process::exit(EXIT_FAILED);
+        }
+        return;
+    }
+
     // Transform sources into `String`s and partition out source code files.
     // TODO handle other names later, partition symbol tables from unrecognized
     let (source_names, _other_names): (Vec<String>, Vec<String>) = arg_matches
@@ -89,77 +454,301 @@ fn main() {
 
     // This takes file IDs and spans to fetch excerpts from source code in error reporting.
     let mut files = SimpleFiles::<String, String>::new();
-    let stderr_writer = StandardStream::stderr(ColorChoice::Always);
-    let codespan_config = Config::default();
-
-    if source_names.len() == 0 {
-        let diagnostic = Diagnostic::<usize>::error()
-            .with_message("Expected at least one .65a source file".to_string());
-        let _ = term::emit(
-            &mut stderr_writer.lock(),
-            &codespan_config,
-            &files,
-            &diagnostic,
-        );
+
+    if source_names.is_empty() {
+        error::report_error("Expected at least one .65a source file".to_string(), None);
+        process::exit(EXIT_USAGE);
     }
 
+    // Without any, the format is the one from a pragma in the source or a plain binary.
+    let mut cli_formats = arg_matches
+        .get_many::<String>("format")
+        .into_iter()
+        .flatten()
+        .filter_map(|format| generation::binary::OutputFormat::from_name(format))
+        .collect::<Vec<_>>();
+    cli_formats.dedup();
+
     let output_filename = if arg_matches.contains_id("OUTPUT") {
         if source_names.len() > 1 {
-            let diagnostic = Diagnostic::<usize>::error().with_message(format!(
+            error::report_error(
                 "Cannot specify output file name when there is more than one source file"
-            ));
-            let _ = term::emit(
-                &mut stderr_writer.lock(),
-                &codespan_config,
-                &files,
-                &diagnostic,
+                    .to_string(),
+                None,
             );
-            return;
+            process::exit(EXIT_USAGE);
         } else {
-            let mut name = arg_matches.get_one::<String>("OUTPUT").unwrap().clone();
-            Some(
-                if Path::new(&name)
-                    .extension()
-                    .filter(|&extension| extension == "65a")
-                    .is_some()
-                {
-                    name
-                } else {
-                    name.push_str(".65a");
-                    name
-                },
-            )
+            let name = arg_matches.get_one::<String>("OUTPUT").unwrap().clone();
+            // Check before adding an extension, which would quietly write a file
+            // named after the directory or a hidden file inside it.
+            if Path::new(&name).is_dir() {
+                error::report_error(
+                    format!("Output path `{}` is a directory", name),
+                    Some(format!(
+                        "Give a file name inside it instead, like `{}`",
+                        Path::new(&name).join("out").display()
+                    )),
+                );
+                process::exit(EXIT_USAGE);
+            }
+            Some(name)
+        }
+    } else {
+        None
+    };
+
+    // A source given twice would be assembled to the same files at once. One that
+    // can't be found is compared by name and reported when it's read.
+    let mut seen_sources = HashSet::new();
+    for name in &source_names {
+        let path = Path::new(name)
+            .canonicalize()
+            .unwrap_or_else(|_| PathBuf::from(name));
+        if !seen_sources.insert(path) {
+            error::report_error(
+                format!("Source file `{}` is given more than once", name),
+                None,
+            );
+            process::exit(EXIT_USAGE);
+        }
+    }
+
+    // These files describe a single binary.
+    for option in ["manifest", "coverage", "debug-bundle", "xref"] {
+        if arg_matches.contains_id(option) && source_names.len() > 1 {
+            error::report_error(
+                format!(
+                    "Cannot specify {} file name when there is more than one source file",
+                    option
+                ),
+                None,
+            );
+            process::exit(EXIT_USAGE);
+        }
+    }
+
+    let origin = if let Some(address) = arg_matches.get_one::<String>("org") {
+        match parser::lexer::parse_number(address) {
+            Some(origin) => Some(origin),
+            None => {
+                error::report_error(
+                    format!("Invalid address `{}` given to --org", address),
+                    None,
+                );
+                process::exit(EXIT_USAGE);
+            }
         }
     } else {
         None
     };
 
-    // TODO spawn a thread that does all of this for parallel compilation, deal with mutex to terminal
-    for file_name in source_names {
+    let expected_hash = if let Some(hash) = arg_matches.get_one::<String>("expect-hash") {
+        let digits = hash.strip_prefix("fnv1a64:").unwrap_or(hash);
+        match u64::from_str_radix(digits, 16) {
+            Ok(expected_hash) if !digits.starts_with('+') => Some(expected_hash),
+            _ => {
+                error::report_error(
+                    format!("Invalid hash `{}` given to --expect-hash", hash),
+                    None,
+                );
+                process::exit(EXIT_USAGE);
+            }
+        }
+    } else {
+        None
+    };
+
+    let fill = if let Some(fill) = arg_matches.get_one::<String>("fill") {
+        match parser::lexer::parse_number(fill) {
+            Some(fill) if fill <= 0xff => fill as u8,
+            _ => {
+                error::report_error(
+                    format!("Invalid fill byte `{}` given to --fill", fill),
+                    None,
+                );
+                process::exit(EXIT_USAGE);
+            }
+        }
+    } else {
+        0
+    };
+
+    let mut definitions = vec![];
+    for definition in arg_matches
+        .get_many::<String>("define")
+        .into_iter()
+        .flatten()
+    {
+        match parser::lexer::parse_definition(definition) {
+            Some(definition) => definitions.push(definition),
+            None => {
+                error::report_error(
+                    format!(
+                        "Invalid definition `{}` given to --define, expected one like `DEBUG=1`",
+                        definition
+                    ),
+                    None,
+                );
+                process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    let mut rom_ranges = vec![];
+    for range in arg_matches
+        .get_many::<String>("rom-range")
+        .into_iter()
+        .flatten()
+    {
+        let bounds = range.split_once('-').and_then(|(start, end)| {
+            Some((
+                parser::lexer::parse_number(start)?,
+                parser::lexer::parse_number(end)?,
+            ))
+        });
+        match bounds {
+            Some((start, end)) if start <= end => rom_ranges.push(start..=end),
+            _ => {
+                error::report_error(
+                    format!(
+                        "Invalid range `{}` given to --rom-range, expected one like `$e000-$ffff`",
+                        range
+                    ),
+                    None,
+                );
+                process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    // `--lint` and `--pedantic` come first so `-W` can still turn categories off.
+    let mut warning_settings = WarningSettings::default();
+    if arg_matches.contains_id("lint") {
+        let _ = warning_settings.apply(WarningCategory::Redundant.name());
+    }
+    if arg_matches.contains_id("pedantic") {
+        warning_settings.enable_all();
+    }
+    for option in arg_matches
+        .get_many::<String>("WARNING")
+        .into_iter()
+        .flatten()
+    {
+        if let Err(message) = warning_settings.apply(option) {
+            error::report_error(message, None);
+            process::exit(EXIT_USAGE);
+        }
+    }
+
+    // Read the layout script up front because it is shared by every source.
+    let script = if let Some(script_name) = arg_matches.get_one::<String>("script") {
+        let script_source = match parser::read_source(script_name) {
+            Err(error) => {
+                error::report_error(format!("Could not read {}: {}", script_name, error), None);
+                process::exit(EXIT_FAILED);
+            }
+            Ok(script_source) => script_source,
+        };
+
+        match script::parse_script(script_name, &script_source) {
+            Err(errors) => {
+                let script_id = files.add(script_name.clone(), script_source);
+                let id_table = HashMap::from([(script_name.clone(), script_id)]);
+                report_errors(errors, &id_table, &files);
+                process::exit(EXIT_FAILED);
+            }
+            Ok(placements) => placements,
+        }
+    } else {
+        vec![]
+    };
+
+    // The equates are also shared by every source.
+    let equates = if let Some(equates_name) = arg_matches.get_one::<String>("equates") {
+        // Their addresses are fixed, but labels in an object are offsets into a section.
+        if !arg_matches.contains_id("binary") {
+            error::report_error("Equates can only be given in binary mode".to_string(), None);
+            process::exit(EXIT_USAGE);
+        }
+
+        let equates_source = match parser::read_source(equates_name) {
+            Err(error) => {
+                error::report_error(format!("Could not read {}: {}", equates_name, error), None);
+                process::exit(EXIT_FAILED);
+            }
+            Ok(equates_source) => equates_source,
+        };
+
+        match equates::parse_equates(equates_name, &equates_source) {
+            Err(errors) => {
+                let equates_id = files.add(equates_name.clone(), equates_source);
+                let id_table = HashMap::from([(equates_name.clone(), equates_id)]);
+                report_errors(errors, &id_table, &files);
+                process::exit(EXIT_FAILED);
+            }
+            Ok(equates) => equates,
+        }
+    } else {
+        vec![]
+    };
+
+    // Each source is a separate compilation unit, so they're assembled on their own
+    // threads. Returns whether the source assembled without errors.
+    let assemble = |file_name: String| -> bool {
+        // Each unit has its own files, so only writing to the terminal is shared.
+        let mut files = SimpleFiles::<String, String>::new();
+
         // Skip the sources that couldn't be read because they're separate compilation units.
-        let source = fs::read_to_string(&file_name);
+        let source = parser::read_source(&file_name);
         let source = match source {
             Err(error) => {
-                let diagnostic = Diagnostic::<usize>::error()
-                    .with_message(format!("Could not read {}: {}", file_name, error));
-                let _ = term::emit(
-                    &mut stderr_writer.lock(),
-                    &codespan_config,
-                    &files,
-                    &diagnostic,
-                );
-                continue;
+                error::report_error(format!("Could not read {}: {}", file_name, error), None);
+                return false;
             }
             Ok(source) => source,
         };
 
-        let output_filename = output_filename.clone().unwrap_or(
-            Path::new(&file_name)
-                .with_extension("bin")
+        // Pragmas at the start of the source set options that aren't given on the command line.
+        let pragma_result = pragma::parse_pragmas(&file_name, &source);
+        let pragmas = pragma_result.as_ref().ok();
+        let cpu = cli_cpu
+            .or_else(|| pragmas.and_then(|pragmas| pragmas.cpu))
+            .unwrap_or(Cpu::Nmos6502);
+        let origin = origin.or_else(|| pragmas.and_then(|pragmas| pragmas.origin));
+        let output_formats = if cli_formats.is_empty() {
+            vec![pragmas
+                .and_then(|pragmas| pragmas.format)
+                .unwrap_or(generation::binary::OutputFormat::Binary)]
+        } else {
+            cli_formats.clone()
+        };
+
+        // A single format is written with its own extension, except RLE which has
+        // always been written to a `.bin` file.
+        let extension = match output_formats.as_slice() {
+            _ if !arg_matches.contains_id("binary") => "65o",
+            [format] if *format != generation::binary::OutputFormat::Rle => format.extension(),
+            _ => "bin",
+        };
+        // A name given without an extension is used for the object's symbol table too.
+        let symbol_filename = match &output_filename {
+            Some(name) if Path::new(name).extension().is_none() => format!("{}.65s", name),
+            _ => Path::new(&file_name)
+                .with_extension("65s")
                 .into_os_string()
                 .into_string()
                 .unwrap(),
-        );
+        };
+        // Only add the default extension if one wasn't given.
+        let output_filename = match &output_filename {
+            Some(name) if Path::new(name).extension().is_some() => name.clone(),
+            Some(name) => format!("{}.{}", name, extension),
+            None => Path::new(&file_name)
+                .with_extension(extension)
+                .into_os_string()
+                .into_string()
+                .unwrap(),
+        };
 
         // Build a context for the parser.
 
@@ -184,6 +773,12 @@ fn main() {
             &mut files,
             &mut include_stack,
             &mut id_table,
+        )
+        .with_max_include_depth(
+            arg_matches
+                .get_one::<usize>("max-include-depth")
+                .copied()
+                .unwrap_or(parser::DEFAULT_MAX_INCLUDE_DEPTH),
         );
 
         let program_result = parser_context.parse_program();
@@ -200,41 +795,274 @@ fn main() {
         // looking up who included the top level file..
         id_table.insert("<command line>".to_string(), file_id);
 
+        if let Err(errors) = pragma_result {
+            report_errors(errors, &id_table, &files);
+            return false;
+        }
+
         match program_result {
             // Report errors if there are any.
             Err(errors) => {
                 report_errors(errors, &id_table, &files);
+                false
             }
             Ok(program) => {
-                let gen_result = generation::GeneratorContext::new(
+                let mut warnings = vec![];
+                let mut generator = generation::GeneratorContext::new(
                     program,
                     arg_matches.contains_id("binary"),
                     file_name.clone(),
                     &id_table,
                     &files,
-                )
-                .generate_code();
+                    &mut warnings,
+                );
+                if arg_matches.contains_id("script") {
+                    generator = generator.with_script(&script);
+                }
+                if let Some(origin) = origin {
+                    generator = generator.with_origin(origin);
+                }
+                generator = generator
+                    .with_equates(&equates)
+                    .with_definitions(&definitions)
+                    .with_rom_ranges(&rom_ranges);
+                match arg_matches.get_one::<String>("indent").map(String::as_str) {
+                    Some("tabs") => {
+                        generator = generator.with_indentation(generation::Indentation::Tabs)
+                    }
+                    Some("spaces") => {
+                        generator = generator.with_indentation(generation::Indentation::Spaces)
+                    }
+                    _ => {}
+                }
+                generator = generator.with_cpu(cpu);
+                if arg_matches
+                    .get_one::<String>("accumulator")
+                    .map(String::as_str)
+                    == Some("implicit")
+                {
+                    generator = generator.with_implicit_accumulator();
+                }
+                if arg_matches.contains_id("pic") {
+                    generator = generator.with_pic();
+                }
+                if arg_matches.contains_id("strict-sections") {
+                    generator = generator.with_strict_sections();
+                }
+                if arg_matches.contains_id("strict-align") {
+                    generator = generator.with_strict_alignment();
+                }
+                if arg_matches.contains_id("deterministic") {
+                    generator = generator.with_deterministic();
+                }
+                if arg_matches.get_one::<bool>("case-sensitive-labels") == Some(&false) {
+                    generator = generator.with_case_insensitive_labels();
+                }
+                generator = generator.with_table_limits(
+                    arg_matches
+                        .get_one::<usize>("max-labels")
+                        .copied()
+                        .unwrap_or(generation::DEFAULT_MAX_LABELS),
+                    arg_matches
+                        .get_one::<usize>("max-macros")
+                        .copied()
+                        .unwrap_or(generation::DEFAULT_MAX_MACROS),
+                );
+                if warning_settings.level(WarningCategory::Redundant) != WarningLevel::Off {
+                    generator = generator.with_lint();
+                }
+                let mut gen_result = generator.generate_code();
+                if let (true, Ok((_, listings, _, _))) =
+                    (arg_matches.contains_id("listing-notes"), &mut gen_result)
+                {
+                    generation::annotate_listings(
+                        listings,
+                        &warnings,
+                        &warning_settings,
+                        &id_table,
+                        &files,
+                    );
+                }
+                let promoted = report_warnings(warnings, &warning_settings, &id_table, &files);
 
                 if let Err(errors) = gen_result {
                     report_errors(errors, &id_table, &files);
-                    continue;
+                    return false;
+                }
+                // Warnings made errors stop the output the same way.
+                if promoted {
+                    return false;
                 }
 
-                let (mut object, listings) = gen_result.unwrap();
+                let (mut object, listings, cross_references, entry) = gen_result.unwrap();
 
                 if arg_matches.contains_id("binary") {
-                    let emit_result =
-                        generation::binary::emit_binary(&mut object[0], &output_filename);
+                    let symbols_only = arg_matches.contains_id("symbols-only");
+                    // The files that are written and what's in them, for the manifest.
+                    let mut outputs = vec![];
+                    let mut contents = vec![];
 
-                    if let Err(errors) = emit_result {
-                        report_errors(errors, &id_table, &files);
-                        continue;
+                    if !symbols_only {
+                        // With more than one format, each file gets its format's extension.
+                        outputs = if output_formats.len() == 1 {
+                            vec![(output_formats[0], output_filename.clone())]
+                        } else {
+                            output_formats
+                                .iter()
+                                .map(|format| {
+                                    (
+                                        *format,
+                                        Path::new(&output_filename)
+                                            .with_extension(format.extension())
+                                            .into_os_string()
+                                            .into_string()
+                                            .unwrap(),
+                                    )
+                                })
+                                .collect()
+                        };
+                        let emit_result = generation::binary::emit_binary(
+                            &mut object,
+                            &cross_references,
+                            &script,
+                            &outputs,
+                            fill,
+                        );
+
+                        match emit_result {
+                            Err(errors) => {
+                                report_errors(errors, &id_table, &files);
+                                return false;
+                            }
+                            Ok(written) => contents = written,
+                        }
+
+                        if let Some(expected_hash) = expected_hash {
+                            let hash_result =
+                                generation::binary::check_hash(&object, &script, expected_hash);
+                            if let Err(error) = hash_result {
+                                report_errors(vec![error], &id_table, &files);
+                                return false;
+                            }
+                        }
+
+                        if arg_matches.contains_id("listing") {
+                            let listing_result = generation::binary::create_listing(
+                                &object,
+                                &listings,
+                                &file_name,
+                                cpu,
+                                arg_matches.contains_id("deterministic"),
+                                arg_matches.contains_id("listing-code-only"),
+                                arg_matches.contains_id("listing-canon-numbers"),
+                                format!(
+                                    "{}_listing.txt",
+                                    Path::new(&file_name).with_extension("").to_str().unwrap()
+                                ),
+                            );
+                            if let Err(error) = listing_result {
+                                report_errors(vec![error], &id_table, &files);
+                                return false;
+                            }
+                        }
+                    }
+
+                    if arg_matches.contains_id("symbol") || symbols_only {
+                        let stem = Path::new(&file_name).with_extension("");
+                        let (symbol_format, symbol_file_name) = match arg_matches
+                            .get_one::<String>("symbol-format")
+                            .map(String::as_str)
+                        {
+                            Some("mesen") => (
+                                generation::binary::SymbolFormat::Mesen,
+                                format!("{}.mlb", stem.to_str().unwrap()),
+                            ),
+                            _ => (
+                                generation::binary::SymbolFormat::S502,
+                                format!("{}_symbols.65a", stem.to_str().unwrap()),
+                            ),
+                        };
+                        let symbol_result = generation::binary::create_symbol_table(
+                            &object,
+                            symbol_format,
+                            symbol_file_name,
+                        );
+                        if let Err(error) = symbol_result {
+                            report_errors(vec![error], &id_table, &files);
+                            return false;
+                        }
+                    }
+
+                    if let Some(xref_name) = arg_matches.get_one::<String>("xref") {
+                        let xref_result = generation::binary::create_cross_reference(
+                            &cross_references,
+                            xref_name.clone(),
+                        );
+                        if let Err(error) = xref_result {
+                            report_errors(vec![error], &id_table, &files);
+                            return false;
+                        }
+                    }
+
+                    // The other outputs describe the binary, which wasn't written.
+                    if symbols_only {
+                        return true;
+                    }
+
+                    if let Some(coverage_name) = arg_matches.get_one::<String>("coverage") {
+                        let coverage_result =
+                            generation::binary::create_coverage(&object, coverage_name.clone());
+                        if let Err(error) = coverage_result {
+                            report_errors(vec![error], &id_table, &files);
+                            return false;
+                        }
+                    }
+
+                    if let Some(manifest_name) = arg_matches.get_one::<String>("manifest") {
+                        let manifest_result = generation::binary::create_manifest(
+                            &object,
+                            &outputs,
+                            &contents,
+                            manifest_name.clone(),
+                        );
+                        if let Err(error) = manifest_result {
+                            report_errors(vec![error], &id_table, &files);
+                            return false;
+                        }
+                    }
+
+                    if let Some(bundle_name) = arg_matches.get_one::<String>("debug-bundle") {
+                        let bundle_result = generation::binary::create_debug_bundle(
+                            &object,
+                            &listings,
+                            bundle_name.clone(),
+                        );
+                        if let Err(error) = bundle_result {
+                            report_errors(vec![error], &id_table, &files);
+                            return false;
+                        }
+                    }
+                } else {
+                    let emit_result = generation::object::emit_object(
+                        &object,
+                        entry.as_deref(),
+                        cpu,
+                        &output_filename,
+                    );
+                    if let Err(error) = emit_result {
+                        report_errors(vec![error], &id_table, &files);
+                        return false;
                     }
 
                     if arg_matches.contains_id("listing") {
-                        let listing_result = generation::binary::create_listing(
-                            &mut object[0],
-                            listings,
+                        let listing_result = generation::object::create_listing(
+                            &object,
+                            &listings,
+                            &file_name,
+                            cpu,
+                            arg_matches.contains_id("deterministic"),
+                            arg_matches.contains_id("listing-code-only"),
+                            arg_matches.contains_id("listing-canon-numbers"),
                             format!(
                                 "{}_listing.txt",
                                 Path::new(&file_name).with_extension("").to_str().unwrap()
@@ -242,28 +1070,104 @@ fn main() {
                         );
                         if let Err(error) = listing_result {
                             report_errors(vec![error], &id_table, &files);
-                            continue;
+                            return false;
                         }
                     }
 
                     if arg_matches.contains_id("symbol") {
-                        let symbol_result = generation::binary::create_symbol_table(
-                            &mut object[0],
-                            format!(
-                                "{}_symbols.65a",
-                                Path::new(&file_name).with_extension("").to_str().unwrap()
-                            ),
+                        let symbol_result = generation::object::create_symbol_table(
+                            &object,
+                            symbol_filename.clone(),
                         );
                         if let Err(error) = symbol_result {
                             report_errors(vec![error], &id_table, &files);
-                            continue;
+                            return false;
                         }
                     }
-                } else {
-                    // TODO emit object and listing
                 }
+
+                true
             }
         }
+    };
+
+    // A few threads take the next source until there are none left, rather than
+    // starting a thread for each of what may be hundreds of sources.
+    let remaining_sources = Mutex::new(source_names.iter());
+    let thread_count = thread::available_parallelism()
+        .map_or(1, |count| count.get())
+        .min(source_names.len());
+    let failed_count = thread::scope(|scope| {
+        let workers = (0..thread_count)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut failed_count = 0;
+                    loop {
+                        let next_source = remaining_sources.lock().unwrap().next();
+                        match next_source {
+                            Some(file_name) => {
+                                if !error::buffered(|| assemble(file_name.clone())) {
+                                    failed_count += 1;
+                                }
+                            }
+                            None => return failed_count,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .sum::<usize>()
+    });
+
+    if failed_count > 0 {
+        // With several sources, the errors of the one that failed can be far up.
+        if source_names.len() > 1 {
+            error::report_summary(failed_count, source_names.len() - failed_count);
+        }
+        process::exit(EXIT_FAILED);
+    }
+}
+
+/// Assemble one line from the command line in binary mode and print its bytes.
+/// Returns whether it assembled without errors.
+#[cfg(not(fuzzing))]
+#[cfg(not(tarpaulin_include))]
+fn assemble_expression(expression: &str, cpu: Cpu) -> bool {
+    let source = format!("{}\n", expression);
+
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut id_table = HashMap::<String, usize>::new();
+    let mut warnings = vec![];
+    let result = s502_as::assemble_source_for(
+        "<expr>",
+        &source,
+        true,
+        cpu,
+        &mut files,
+        &mut id_table,
+        &mut warnings,
+    );
+    report_warnings(warnings, &WarningSettings::default(), &id_table, &files);
+
+    match result {
+        Err(errors) => {
+            report_errors(errors, &id_table, &files);
+            false
+        }
+        Ok((object, _)) => {
+            println!(
+                "{}",
+                generation::binary::image_bytes(&object)
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            );
+            true
+        }
     }
 }
 
@@ -360,24 +1264,37 @@ fn main() {
                 report_errors(errors, &id_table, &files);
             }
             Ok(program) => {
+                let mut warnings = vec![];
                 let gen_result = generation::GeneratorContext::new(
                     program,
                     arg_matches.contains_id("binary"),
                     file_name,
                     &id_table,
                     &files,
+                    &mut warnings,
                 )
+                // Arbitrary input shouldn't be able to exhaust memory.
+                .with_table_limits(1024, 1024)
                 .generate_code();
+                report_warnings(warnings, &WarningSettings::default(), &id_table, &files);
 
                 if let Err(errors) = gen_result {
                     report_errors(errors, &id_table, &files);
                 }
 
-                let (mut object, listings) = gen_result.unwrap();
+                let (mut object, listings, cross_references, _) = gen_result.unwrap();
 
                 if arg_matches.contains_id("binary") {
-                    let emit_result =
-                        generation::binary::emit_binary(&mut object[0], &output_filename);
+                    let emit_result = generation::binary::emit_binary(
+                        &mut object,
+                        &cross_references,
+                        &[],
+                        &[(
+                            generation::binary::OutputFormat::Binary,
+                            output_filename.clone(),
+                        )],
+                        0,
+                    );
 
                     if let Err(errors) = emit_result {
                         report_errors(errors, &id_table, &files);
@@ -386,8 +1303,13 @@ fn main() {
 
                     if arg_matches.contains_id("listing") {
                         let listing_result = generation::binary::create_listing(
-                            &mut object[0],
-                            listings,
+                            &object,
+                            &listings,
+                            &file_name,
+                            Cpu::Nmos6502,
+                            true,
+                            false,
+                            false,
                             format!(
                                 "{}_listing.txt",
                                 Path::new(&file_name).with_extension("").to_str().unwrap()
@@ -401,7 +1323,8 @@ fn main() {
 
                     if arg_matches.contains_id("symbol") {
                         let symbol_result = generation::binary::create_symbol_table(
-                            &mut object[0],
+                            &object,
+                            generation::binary::SymbolFormat::S502,
                             format!(
                                 "{}_symbols.65a",
                                 Path::new(&file_name).with_extension("").to_str().unwrap()
@@ -2,7 +2,7 @@
 
 use std::fmt;
 
-use logos::{Lexer, Logos};
+use logos::{Filter, Lexer, Logos};
 
 /// A literal number or string.
 #[derive(Clone, Debug, PartialEq)]
@@ -10,6 +10,9 @@ pub enum Literal {
     Byte(u8),
     Word(u16),
     String(String),
+    /// A character in single quotes, kept with its quotes so the parser can
+    /// report an empty or unterminated one.
+    Character(String),
 }
 
 /// Parse a number literal using the radix prefix.
@@ -72,6 +75,90 @@ fn lex_number(lex: &mut Lexer<Token>) -> Option<Literal> {
         .ok()
 }
 
+/// The two's complement of a number after a `-`, in the width its magnitude takes.
+pub fn negate(number: Literal) -> Literal {
+    match number {
+        Literal::Byte(byte) => Literal::Byte(byte.wrapping_neg()),
+        Literal::Word(word) => Literal::Word(word.wrapping_neg()),
+        other => other,
+    }
+}
+
+/// `*` multiplies when it directly follows a value, like `2*3`, or is inside
+/// parentheses, like `(1 + 2 * 3)`. In a `dfb` or `dfw` list it's also kept when a
+/// lone number follows it, like `dfb $ff * 3, $00`, for the parser to read as a
+/// repeat count. Otherwise it starts a comment that runs to the end of the line,
+/// either on its own line or after a complete operand.
+fn lex_star(lex: &mut Lexer<Token>) -> Filter<()> {
+    let line = lex.source()[..lex.span().start]
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default();
+    let follows_value = line
+        .chars()
+        .next_back()
+        .is_some_and(|previous| previous.is_ascii_alphanumeric() || "_)]'\"$@".contains(previous));
+
+    if follows_value || open_parentheses(line) > 0 || is_repeat_count(line, lex.remainder()) {
+        Filter::Emit(())
+    } else {
+        let comment_length = lex.remainder().find('\n').unwrap_or(lex.remainder().len());
+        lex.bump(comment_length);
+        Filter::Skip
+    }
+}
+
+/// Whether a `*` between the start of its line and the rest of the line is followed
+/// by only a number before the next value of a `dfb` or `dfw` list.
+fn is_repeat_count(line: &str, rest: &str) -> bool {
+    let data_directive = line
+        .split(|character: char| character.is_whitespace() || character == ':')
+        .any(|word| word.eq_ignore_ascii_case("dfb") || word.eq_ignore_ascii_case("dfw"));
+    let count = rest[..rest.find([',', '\n']).unwrap_or(rest.len())].trim();
+
+    data_directive
+        && matches!(
+            Token::lexer(count).collect::<Vec<_>>().as_slice(),
+            [Token::Literal(Literal::Byte(_) | Literal::Word(_))]
+        )
+}
+
+/// The number of parentheses in a line that aren't closed yet, not counting ones
+/// in strings or characters.
+fn open_parentheses(line: &str) -> usize {
+    let mut open = 0usize;
+    let mut quote = None;
+    for character in line.chars() {
+        match (quote, character) {
+            (Some(end), _) if character == end => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(character),
+            (None, '(') => open += 1,
+            (None, ')') => open = open.saturating_sub(1),
+            _ => {}
+        }
+    }
+    open
+}
+
+/// Find the first character in a number literal that isn't a digit of its radix,
+/// which makes `lex_number` reject it. Returns its offset into the literal, the
+/// character, and the radix.
+pub fn invalid_digit(literal: &str) -> Option<(usize, char, u32)> {
+    let (radix, digits_start) = match literal.chars().next()? {
+        '%' => (2, 1),
+        '@' => (8, 1),
+        '$' => (16, 1),
+        digit if digit.is_ascii_digit() => (10, 0),
+        _ => return None,
+    };
+
+    literal[digits_start..]
+        .char_indices()
+        .find(|(_, character)| *character != '_' && !character.is_digit(radix))
+        .map(|(offset, character)| (digits_start + offset, character, radix))
+}
+
 /// Tokens of the assembly language.
 #[derive(Clone, Debug, Logos, PartialEq)]
 pub enum Token {
@@ -187,6 +274,18 @@ pub enum Token {
     Txs,
     #[token("tya", priority = 2, ignore(case))]
     Tya,
+    #[token("bra", priority = 2, ignore(case))]
+    Bra,
+    #[token("phx", priority = 2, ignore(case))]
+    Phx,
+    #[token("phy", priority = 2, ignore(case))]
+    Phy,
+    #[token("plx", priority = 2, ignore(case))]
+    Plx,
+    #[token("ply", priority = 2, ignore(case))]
+    Ply,
+    #[token("stz", priority = 2, ignore(case))]
+    Stz,
     #[token("dfb", priority = 2, ignore(case))]
     Dfb,
     #[token("dfw", priority = 2, ignore(case))]
@@ -195,12 +294,40 @@ pub enum Token {
     Equ,
     #[token("hlt", priority = 2, ignore(case))]
     Hlt,
+    #[token("inb", priority = 2, ignore(case))]
+    Inb,
     #[token("inl", priority = 2, ignore(case))]
     Inl,
     #[token("org", priority = 2, ignore(case))]
     Org,
     #[token("sct", priority = 2, ignore(case))]
     Sct,
+    #[token("sctdef", priority = 2, ignore(case))]
+    Sctdef,
+    #[token("ds", priority = 2, ignore(case))]
+    Ds,
+    #[token("opcode", priority = 2, ignore(case))]
+    Opcode,
+    #[token("align", priority = 2, ignore(case))]
+    Align,
+    #[token("rept", priority = 2, ignore(case))]
+    Rept,
+    #[token("endr", priority = 2, ignore(case))]
+    Endr,
+    #[token("dfs", priority = 2, ignore(case))]
+    Dfs,
+    #[token("if", priority = 2, ignore(case))]
+    If,
+    #[token("else", priority = 2, ignore(case))]
+    Else,
+    #[token("endif", priority = 2, ignore(case))]
+    Endif,
+    #[token("datestamp", priority = 2, ignore(case))]
+    Datestamp,
+    #[token("fillbyte", priority = 2, ignore(case))]
+    Fillbyte,
+    #[token("entry", priority = 2, ignore(case))]
+    Entry,
     #[token("a", priority = 2, ignore(case))]
     A,
     #[token("x", priority = 2, ignore(case))]
@@ -211,6 +338,34 @@ pub enum Token {
     Global,
     #[token(".")]
     Period,
+    #[token(":")]
+    Colon,
+    #[token("=")]
+    Equals,
+    #[token("+")]
+    Plus,
+    #[token("-")]
+    Minus,
+    #[token("*", lex_star)]
+    Star,
+    #[token("/")]
+    Slash,
+    #[token("&")]
+    Ampersand,
+    #[token("|")]
+    Pipe,
+    #[token("^")]
+    Caret,
+    #[token("~")]
+    Tilde,
+    #[token("<<")]
+    ShiftLeft,
+    #[token(">>")]
+    ShiftRight,
+    #[token("$")]
+    ProgramCounter,
+    #[token("\\@")]
+    RepeatCounter,
     #[token(",")]
     Comma,
     #[token("#")]
@@ -219,6 +374,10 @@ pub enum Token {
     LParen,
     #[token(")")]
     RParen,
+    #[token("[")]
+    LBracket,
+    #[token("]")]
+    RBracket,
     #[token("<")]
     LAngle,
     #[token(">")]
@@ -229,13 +388,13 @@ pub enum Token {
     #[regex(r#"[%@$][0-9a-zA-Z][0-9a-zA-Z_]*"#, lex_number)]
     #[regex(r#"[0-9][0-9_]*"#, lex_number)]
     #[regex(r#""(\\[nif"0\\]|[^"\\])*""#, |lex| Literal::String(lex.slice()[1..lex.slice().len()-1].to_string()))]
+    #[regex(r#"'(\\[^\n]|[^'\\\n])*'?"#, |lex| Literal::Character(lex.slice().to_string()))]
     Literal(Literal),
     #[regex("[a-zA-Z][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
     Ident(String),
     #[regex("\n")]
     Eol,
     #[error]
-    #[regex("[*].*", logos::skip)]
     #[regex(r"[ \t]+", logos::skip)]
     Error,
 }
@@ -300,28 +459,65 @@ impl fmt::Display for Token {
             Self::Txa => write!(f, "`txa`"),
             Self::Txs => write!(f, "`txs`"),
             Self::Tya => write!(f, "`tya`"),
+            Self::Bra => write!(f, "`bra`"),
+            Self::Phx => write!(f, "`phx`"),
+            Self::Phy => write!(f, "`phy`"),
+            Self::Plx => write!(f, "`plx`"),
+            Self::Ply => write!(f, "`ply`"),
+            Self::Stz => write!(f, "`stz`"),
             Self::Dfb => write!(f, "`dfb`"),
             Self::Dfw => write!(f, "`dfw`"),
             Self::Equ => write!(f, "`equ`"),
             Self::Hlt => write!(f, "`hlt`"),
+            Self::Inb => write!(f, "`inb`"),
             Self::Inl => write!(f, "`inl`"),
             Self::Org => write!(f, "`org`"),
             Self::Sct => write!(f, "`sct`"),
+            Self::Sctdef => write!(f, "`sctdef`"),
+            Self::Ds => write!(f, "`ds`"),
+            Self::Opcode => write!(f, "`opcode`"),
+            Self::Align => write!(f, "`align`"),
+            Self::Rept => write!(f, "`rept`"),
+            Self::Endr => write!(f, "`endr`"),
+            Self::Dfs => write!(f, "`dfs`"),
+            Self::If => write!(f, "`if`"),
+            Self::Else => write!(f, "`else`"),
+            Self::Endif => write!(f, "`endif`"),
+            Self::Datestamp => write!(f, "`datestamp`"),
+            Self::Fillbyte => write!(f, "`fillbyte`"),
+            Self::Entry => write!(f, "`entry`"),
             Self::A => write!(f, "`a`"),
             Self::X => write!(f, "`x`"),
             Self::Y => write!(f, "`y`"),
             Self::Global => write!(f, "`!`"),
             Self::Period => write!(f, "`.`"),
+            Self::Colon => write!(f, "`:`"),
+            Self::Equals => write!(f, "`=`"),
+            Self::Plus => write!(f, "`+`"),
+            Self::Minus => write!(f, "`-`"),
+            Self::Star => write!(f, "`*`"),
+            Self::Slash => write!(f, "`/`"),
+            Self::Ampersand => write!(f, "`&`"),
+            Self::Pipe => write!(f, "`|`"),
+            Self::Caret => write!(f, "`^`"),
+            Self::Tilde => write!(f, "`~`"),
+            Self::ShiftLeft => write!(f, "`<<`"),
+            Self::ShiftRight => write!(f, "`>>`"),
             Self::Comma => write!(f, "`,`"),
             Self::Immediate => write!(f, "`#`"),
+            Self::ProgramCounter => write!(f, "`$`"),
+            Self::RepeatCounter => write!(f, "`\\@`"),
             Self::LParen => write!(f, "`(`"),
             Self::RParen => write!(f, "`)`"),
+            Self::LBracket => write!(f, "`[`"),
+            Self::RBracket => write!(f, "`]`"),
             Self::LAngle => write!(f, "`<`"),
             Self::RAngle => write!(f, "`>`"),
             Self::Literal(op) => match op {
                 Literal::Byte(byte) => write!(f, "byte `{}`", byte),
                 Literal::Word(word) => write!(f, "word `{}`", word),
                 Literal::String(string) => write!(f, "`\"{string}\"`"),
+                Literal::Character(character) => write!(f, "`{character}`"),
             },
             Self::Ident(ident) => write!(f, "`{ident}`"),
             Self::Eol => write!(f, "`<end of line>`"),
@@ -330,6 +526,49 @@ impl fmt::Display for Token {
     }
 }
 
+/// Parse a number written the same way as in source code, like a command line
+/// argument. Returns None if the string is anything other than a single number.
+pub fn parse_number(string: &str) -> Option<u16> {
+    let mut lexer = Token::lexer(string);
+    let number = match lexer.next()? {
+        Token::Literal(Literal::Byte(byte)) => byte as u16,
+        Token::Literal(Literal::Word(word)) => word,
+        _ => return None,
+    };
+
+    if lexer.next().is_some() {
+        None
+    } else {
+        Some(number)
+    }
+}
+
+/// Parse a definition from the command line like `DEBUG=1` or `NAME="game"` into
+/// the name and its value. A number is a byte if it fits in one, whether or not it
+/// was written with leading zeros.
+pub fn parse_definition(definition: &str) -> Option<(String, Literal)> {
+    let (name, value) = definition.split_once('=')?;
+
+    let mut lexer = Token::lexer(name);
+    let name = match (lexer.next()?, lexer.next()) {
+        (Token::Ident(name), None) => name,
+        _ => return None,
+    };
+
+    let value = match Token::lexer(value).collect::<Vec<_>>().as_slice() {
+        [Token::Literal(Literal::Word(word))] if *word <= 0xff => Literal::Byte(*word as u8),
+        [Token::Literal(literal @ (Literal::Byte(_) | Literal::Word(_) | Literal::String(_)))] => {
+            literal.clone()
+        }
+        [Token::Minus, Token::Literal(number @ (Literal::Byte(_) | Literal::Word(_)))] => {
+            negate(number.clone())
+        }
+        _ => return None,
+    };
+
+    Some((name, value))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -528,6 +767,60 @@ mod tests {
         assert_eq!(lexer.next().unwrap(), Token::Error);
     }
 
+    /// `-` is its own token, and negating a decimal byte gives its two's complement.
+    #[test]
+    fn lex_negative_0() {
+        let source = "-1".to_string();
+        let mut lexer = Token::lexer(&source);
+
+        assert_eq!(lexer.next().unwrap(), Token::Minus);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(1)));
+        assert_eq!(negate(Literal::Byte(1)), Literal::Byte(0xff));
+    }
+
+    #[test]
+    fn lex_negative_1() {
+        let source = "-128".to_string();
+        let mut lexer = Token::lexer(&source);
+
+        assert_eq!(lexer.next().unwrap(), Token::Minus);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(128)));
+        assert_eq!(negate(Literal::Byte(128)), Literal::Byte(0x80));
+    }
+
+    /// A magnitude that fits in a byte wraps to a byte even past -128.
+    #[test]
+    fn lex_negative_2() {
+        let source = "-129".to_string();
+        let mut lexer = Token::lexer(&source);
+
+        assert_eq!(lexer.next().unwrap(), Token::Minus);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(129)));
+        assert_eq!(negate(Literal::Byte(129)), Literal::Byte(0x7f));
+    }
+
+    /// A magnitude that takes a word gives a negative word.
+    #[test]
+    fn lex_negative_3() {
+        let source = "-256".to_string();
+        let mut lexer = Token::lexer(&source);
+
+        assert_eq!(lexer.next().unwrap(), Token::Minus);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Word(256)));
+        assert_eq!(negate(Literal::Word(256)), Literal::Word(0xff00));
+    }
+
+    /// Other radixes can be negative too.
+    #[test]
+    fn lex_negative_4() {
+        let source = "-$1".to_string();
+        let mut lexer = Token::lexer(&source);
+
+        assert_eq!(lexer.next().unwrap(), Token::Minus);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(1)));
+        assert_eq!(negate(Literal::Byte(1)), Literal::Byte(0xff));
+    }
+
     #[test]
     fn lex_string() {
         let source = "\"test\"".to_string();
@@ -538,4 +831,140 @@ mod tests {
             Token::Literal(Literal::String("test".to_string()))
         );
     }
+
+    /// A comment on the last line is skipped even without a final newline.
+    #[test]
+    fn lex_comment_at_eof() {
+        let source = "start\n* The end".to_string();
+        let mut lexer = Token::lexer(&source);
+
+        assert_eq!(lexer.next().unwrap(), Token::Ident("start".to_string()));
+        assert_eq!(lexer.next().unwrap(), Token::Eol);
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn lex_character() {
+        let source = "'A' '\\\"' 'AB' 'A".to_string();
+        let mut lexer = Token::lexer(&source);
+
+        assert_eq!(
+            lexer.next().unwrap(),
+            Token::Literal(Literal::Character("'A'".to_string()))
+        );
+        assert_eq!(
+            lexer.next().unwrap(),
+            Token::Literal(Literal::Character("'\\\"'".to_string()))
+        );
+        assert_eq!(
+            lexer.next().unwrap(),
+            Token::Literal(Literal::Character("'AB'".to_string()))
+        );
+        assert_eq!(
+            lexer.next().unwrap(),
+            Token::Literal(Literal::Character("'A".to_string()))
+        );
+    }
+
+    /// `*` multiplies right after a value or inside parentheses, and starts a
+    /// comment anywhere else.
+    #[test]
+    fn lex_star() {
+        let source = "2*3 * 4\n  * comment\n(1 * 2) * 2\nbase-1 end-start".to_string();
+        let mut lexer = Token::lexer(&source);
+
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(2)));
+        assert_eq!(lexer.next().unwrap(), Token::Star);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(3)));
+        assert_eq!(lexer.next().unwrap(), Token::Eol);
+        assert_eq!(lexer.next().unwrap(), Token::Eol);
+        assert_eq!(lexer.next().unwrap(), Token::LParen);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(1)));
+        assert_eq!(lexer.next().unwrap(), Token::Star);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(2)));
+        assert_eq!(lexer.next().unwrap(), Token::RParen);
+        assert_eq!(lexer.next().unwrap(), Token::Eol);
+        assert_eq!(lexer.next().unwrap(), Token::Ident("base".to_string()));
+        assert_eq!(lexer.next().unwrap(), Token::Minus);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(1)));
+        assert_eq!(lexer.next().unwrap(), Token::Ident("end".to_string()));
+        assert_eq!(lexer.next().unwrap(), Token::Minus);
+        assert_eq!(lexer.next().unwrap(), Token::Ident("start".to_string()));
+        assert_eq!(lexer.next(), None);
+    }
+
+    /// In a data list, `*` before a lone number is kept as a repeat count.
+    #[test]
+    fn lex_repeat_count() {
+        let source = "dfb $ff * 3, 0 * zeros\nlda #5 * 3".to_string();
+        let mut lexer = Token::lexer(&source);
+
+        assert_eq!(lexer.next().unwrap(), Token::Dfb);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(0xff)));
+        assert_eq!(lexer.next().unwrap(), Token::Star);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(3)));
+        assert_eq!(lexer.next().unwrap(), Token::Comma);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(0)));
+        assert_eq!(lexer.next().unwrap(), Token::Eol);
+        assert_eq!(lexer.next().unwrap(), Token::Lda);
+        assert_eq!(lexer.next().unwrap(), Token::Immediate);
+        assert_eq!(lexer.next().unwrap(), Token::Literal(Literal::Byte(5)));
+        assert_eq!(lexer.next(), None);
+    }
+
+    /// A single number is parsed in any radix.
+    #[test]
+    fn parse_number_radix() {
+        assert_eq!(parse_number("$2000"), Some(0x2000));
+        assert_eq!(parse_number("%101"), Some(0b101));
+        assert_eq!(parse_number("300"), Some(300));
+    }
+
+    /// Anything other than a single number is rejected.
+    #[test]
+    fn parse_number_invalid() {
+        assert_eq!(parse_number("$2000 $10"), None);
+        assert_eq!(parse_number("start"), None);
+        assert_eq!(parse_number(""), None);
+    }
+
+    /// Hex digits may be either case, and the first one that isn't a digit is found.
+    #[test]
+    fn invalid_hex_digit() {
+        assert_eq!(invalid_digit("$FG"), Some((2, 'G', 16)));
+        assert_eq!(invalid_digit("$1G0"), Some((2, 'G', 16)));
+        assert_eq!(invalid_digit("%102"), Some((3, '2', 2)));
+        assert_eq!(invalid_digit("$aF"), None);
+        assert_eq!(invalid_digit("$1_000"), None);
+
+        let mut lexer = Token::lexer("$aF $00aF $FG");
+        assert_eq!(lexer.next(), Some(Token::Literal(Literal::Byte(0xaf))));
+        assert_eq!(lexer.next(), Some(Token::Literal(Literal::Word(0xaf))));
+        assert_eq!(lexer.next(), Some(Token::Error));
+    }
+
+    /// A definition's number is a byte or word by its value, not how it's written.
+    #[test]
+    fn parse_definition_values() {
+        assert_eq!(
+            parse_definition("DEBUG=$0001"),
+            Some(("DEBUG".to_string(), Literal::Byte(1)))
+        );
+        assert_eq!(
+            parse_definition("screen=$0400"),
+            Some(("screen".to_string(), Literal::Word(0x400)))
+        );
+        assert_eq!(
+            parse_definition("NAME=\"game\""),
+            Some(("NAME".to_string(), Literal::String("game".to_string())))
+        );
+        assert_eq!(
+            parse_definition("OFFSET=-1"),
+            Some(("OFFSET".to_string(), Literal::Byte(0xff)))
+        );
+        assert_eq!(parse_definition("DEBUG"), None);
+        assert_eq!(parse_definition("lda=1"), None);
+        assert_eq!(parse_definition("DEBUG=on"), None);
+        assert_eq!(parse_definition("DEBUG=1 2"), None);
+    }
 }
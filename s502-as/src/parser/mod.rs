@@ -15,8 +15,27 @@ use lexer::{Literal, Token};
 
 pub type SpannedLexer<'source> = Peekable<SpannedIter<'source, Token>>;
 
+/// How deeply includes may be nested unless another limit is given.
+pub const DEFAULT_MAX_INCLUDE_DEPTH: usize = 64;
+
+/// A value along with the modifier that may precede it.
+type ModifiedValue = (Option<Spanned<Modifier>>, Spanned<Value>);
+
+/// Read a source file, describing where the first invalid byte is if it isn't UTF-8.
+pub fn read_source(file_name: &str) -> Result<String, String> {
+    let bytes = fs::read(file_name).map_err(|error| error.to_string())?;
+    String::from_utf8(bytes).map_err(|error| {
+        format!(
+            "invalid UTF-8 at byte offset {}",
+            error.utf8_error().valid_up_to()
+        )
+    })
+}
+
 pub struct ParserContext<'source, 'context> {
     file_name: String,
+    /// The source being parsed, used to show unrecognized characters in errors.
+    source: &'source str,
     lexer: SpannedLexer<'source>,
     files: &'context mut SimpleFiles<String, String>,
     include_stack: &'context mut Vec<Include>,
@@ -24,18 +43,29 @@ pub struct ParserContext<'source, 'context> {
     program: Program,
     errors: Vec<AssemblerError>,
     current_parent_label: Option<String>,
+    /// The `rept`, `if`, and `else` blocks the current line is inside of, innermost last.
+    blocks: Vec<Mnemonic>,
+    /// Set when the line just parsed was the `endr`, `else`, or `endif` ending the
+    /// innermost block.
+    block_end: Option<Mnemonic>,
+    /// How deeply includes may be nested, since each one is parsed recursively.
+    max_include_depth: usize,
+    /// Whether a `*` outside parentheses gives a repeat count instead of
+    /// multiplying, which it does in the values of `dfb` and `dfw`.
+    repeat_counts: bool,
 }
 
 impl<'source, 'context> ParserContext<'source, 'context> {
     pub fn new(
         file_name: String,
-        source: &'source String,
+        source: &'source str,
         files: &'context mut SimpleFiles<String, String>,
         include_stack: &'context mut Vec<Include>,
         id_table: &'context mut HashMap<String, usize>,
     ) -> Self {
         Self {
             file_name,
+            source,
             lexer: Token::lexer(source).spanned().peekable(),
             files,
             include_stack,
@@ -43,9 +73,20 @@ impl<'source, 'context> ParserContext<'source, 'context> {
             program: Program::with_capacity(256),
             errors: Vec::with_capacity(8),
             current_parent_label: None,
+            blocks: Vec::new(),
+            block_end: None,
+            max_include_depth: DEFAULT_MAX_INCLUDE_DEPTH,
+            repeat_counts: false,
         }
     }
 
+    /// Limit how deeply includes may be nested, so a deep chain of them errors
+    /// instead of overflowing the stack.
+    pub fn with_max_include_depth(mut self, max_include_depth: usize) -> Self {
+        self.max_include_depth = max_include_depth;
+        self
+    }
+
     pub fn parse_program(mut self) -> Result<Program, Vec<AssemblerError>> {
         while self.lexer.peek().is_some() {
             // Tell the generation stage where this line starts.
@@ -62,7 +103,7 @@ impl<'source, 'context> ParserContext<'source, 'context> {
             }
         }
 
-        if self.errors.len() > 0 {
+        if !self.errors.is_empty() {
             Err(self.errors)
         } else {
             Ok(self.program)
@@ -74,25 +115,109 @@ impl<'source, 'context> ParserContext<'source, 'context> {
         // Initialize to 0 to satisfy the compiler even though
         // parse_line is only called when lexer.peek() is Some.
         let mut line_end = 0;
-        // Label appears first.
-        let label = self.parse_label()?;
-        // Add it to the program right away because if the instruction is an include, then
-        // parse_instruction will put the included file in the program before returning.
-        if let Some(label) = label {
+        // Labels appear first, and there may be several if each is followed by a colon.
+        while let Some(mut label) = self.parse_label()? {
             // So far this is where the line ends.
             line_end = label.span.end;
-            // Push the label on the stack so the generation stage knows where
-            // the label is in the code.
+
+            // A top level label may be given an address instead of being placed at the code,
+            // which takes up the rest of the line.
+            if let Some((_, equals_span)) = self
+                .lexer
+                .next_if(|(token, _)| matches!(token, Token::Equals))
+            {
+                let address = self.parse_label_address(&label, equals_span);
+                let address = match address {
+                    Err(error) => {
+                        self.skip_to_eol();
+                        return Err(error);
+                    }
+                    Ok(address) => address,
+                };
+                line_end = address.span.end;
+                if let Label::Top(top_label) = &mut label.val {
+                    top_label.address = Some(address);
+                }
+                self.program.push(Action::Label(label));
+
+                let eol_end = self.skip_to_eol();
+                line_end = if eol_end != 0 { eol_end } else { line_end };
+                self.program.push(Action::LineEnd(line_end));
+                return Ok(());
+            }
+
+            // Add it to the program right away because if the instruction is an include, then
+            // parse_instruction will put the included file in the program before returning.
             self.program.push(Action::Label(label));
+
+            match self
+                .lexer
+                .next_if(|(token, _)| matches!(token, Token::Colon))
+            {
+                Some((_, colon_span)) => line_end = colon_span.end,
+                None => break,
+            }
         }
 
         let instruction = self.parse_instruction()?;
 
+        // Without an instruction, the rest of the line must be empty or a comment,
+        // so characters the lexer doesn't recognize are a mistake.
+        if instruction.is_none() {
+            if let Some((Token::Error, error_span)) = self.lexer.peek().cloned() {
+                let error = self.unrecognized_characters(error_span, None);
+                self.skip_to_eol();
+                return Err(error);
+            }
+        }
+
+        let mut block = None;
         let included_program = if let Some(instruction) = instruction {
             // The line might actualy end here.
             line_end = instruction.span.end;
 
-            if let Mnemonic::Inl = instruction.val.mnemonic.val {
+            let mnemonic = instruction.val.mnemonic.val;
+            if let Mnemonic::Endr | Mnemonic::Else | Mnemonic::Endif = mnemonic {
+                let innermost = self.blocks.last();
+                let message = match mnemonic {
+                    Mnemonic::Endr if innermost != Some(&Mnemonic::Rept) => {
+                        Some("Found `endr` without a `rept` to end")
+                    }
+                    Mnemonic::Else if innermost == Some(&Mnemonic::Else) => {
+                        Some("Found a second `else` for the same `if`")
+                    }
+                    Mnemonic::Else if innermost != Some(&Mnemonic::If) => {
+                        Some("Found `else` without an `if`")
+                    }
+                    Mnemonic::Endif
+                        if !matches!(innermost, Some(Mnemonic::If | Mnemonic::Else)) =>
+                    {
+                        Some("Found `endif` without an `if` to end")
+                    }
+                    _ => None,
+                };
+                if let Some(message) = message {
+                    self.skip_to_eol();
+                    return Err(AssemblerError {
+                        message: String::from(message),
+                        labels: vec![(
+                            Location {
+                                span: instruction.span,
+                                file_name: self.file_name.clone(),
+                            },
+                            None,
+                        )],
+                        help: None,
+                    });
+                }
+                // There's nothing to assemble, it only ends the block.
+                self.block_end = Some(mnemonic);
+                None
+            } else if let Mnemonic::Rept | Mnemonic::If = mnemonic {
+                // The block is parsed after the end of this line.
+                block = Some(instruction);
+                None
+            } else if let Mnemonic::Inl = instruction.val.mnemonic.val {
                 Some(
                     if let Value::Include(included_program) =
                         instruction.val.operand.unwrap().val.value.val
@@ -114,6 +239,45 @@ impl<'source, 'context> ParserContext<'source, 'context> {
 
         // Or, if there was a comment, then it actually ends there.
         line_end = if eol_end != 0 { eol_end } else { line_end };
+
+        if let Some(mut instruction) = block {
+            let mut end_line = if let Mnemonic::Rept = instruction.val.mnemonic.val {
+                let (body, endr_line) = self.parse_repeat_body(&instruction, line_end)?;
+                let operand = self.block_operand(
+                    &mut instruction,
+                    "Expected the number of times to repeat after `rept`",
+                )?;
+                let count = std::mem::replace(
+                    &mut operand.val.value,
+                    Spanned::new((Value::Accumulator, 0..0)),
+                );
+                operand.val.value =
+                    Spanned::new((Value::Repeat((Box::new(count), body)), operand.span.clone()));
+                endr_line
+            } else {
+                let (then_body, else_body, endif_line) =
+                    self.parse_conditional_body(&instruction, line_end)?;
+                let operand =
+                    self.block_operand(&mut instruction, "Expected a condition after `if`")?;
+                let condition = std::mem::replace(
+                    &mut operand.val.value,
+                    Spanned::new((Value::Accumulator, 0..0)),
+                );
+                operand.val.value = Spanned::new((
+                    Value::Conditional((Box::new(condition), then_body, else_body)),
+                    operand.span.clone(),
+                ));
+                endif_line
+            };
+
+            self.program.push(Action::Instruction(instruction));
+            self.program.push(Action::LineEnd(line_end));
+            // The line with `endr` or `endif` comes after the block so its label isn't
+            // repeated or skipped.
+            self.program.append(&mut end_line);
+            return Ok(());
+        }
+
         self.program.push(Action::LineEnd(line_end));
 
         if let Some((included_name, mut included_program)) = included_program {
@@ -125,6 +289,123 @@ impl<'source, 'context> ParserContext<'source, 'context> {
         Ok(())
     }
 
+    /// The operand of `rept` or `if`, which the block is stored in.
+    fn block_operand<'instruction>(
+        &self,
+        instruction: &'instruction mut Spanned<Instruction>,
+        message: &str,
+    ) -> Result<&'instruction mut Spanned<Operand>, AssemblerError> {
+        let span = instruction.span.clone();
+        instruction.val.operand.as_mut().ok_or(AssemblerError {
+            message: String::from(message),
+            labels: vec![(
+                Location {
+                    span,
+                    file_name: self.file_name.clone(),
+                },
+                None,
+            )],
+            help: None,
+        })
+    }
+
+    /// Parse the lines of a `rept`, `if`, or `else` block into a separate program
+    /// up to the line that ends it. Returns the program and, unless the file ends
+    /// first, the directive that ended it with the actions for its line.
+    fn parse_block(
+        &mut self,
+        kind: Mnemonic,
+        start_line_end: usize,
+    ) -> (Program, Option<(Mnemonic, Program)>) {
+        let outer_program = std::mem::replace(&mut self.program, Program::with_capacity(32));
+        self.blocks.push(kind);
+
+        let mut end = None;
+        while self.lexer.peek().is_some() {
+            let line_start = match self.program.last() {
+                Some(Action::LineEnd(line_end)) => *line_end,
+                _ => start_line_end,
+            };
+            let line_index = self.program.len();
+            self.program.push(Action::LineStart(line_start));
+
+            if let Err(error) = self.parse_line() {
+                self.errors.push(error);
+            }
+
+            if let Some(mnemonic) = self.block_end.take() {
+                end = Some((mnemonic, self.program.split_off(line_index)));
+                break;
+            }
+        }
+
+        self.blocks.pop();
+        let body = std::mem::replace(&mut self.program, outer_program);
+        (body, end)
+    }
+
+    /// Parse the lines after `if` up to its `else` or `endif`, and the lines from
+    /// `else` up to `endif`, into separate programs. The line with `else` starts the
+    /// second program. Returns both programs and the actions for the line with `endif`.
+    fn parse_conditional_body(
+        &mut self,
+        if_instruction: &Spanned<Instruction>,
+        if_line_end: usize,
+    ) -> Result<(Program, Program, Program), AssemblerError> {
+        let unterminated = AssemblerError {
+            message: String::from("Expected `endif` to end the `if` block"),
+            labels: vec![(
+                Location {
+                    span: if_instruction.val.mnemonic.span.clone(),
+                    file_name: self.file_name.clone(),
+                },
+                Some(String::from("Block starts here")),
+            )],
+            help: None,
+        };
+
+        match self.parse_block(Mnemonic::If, if_line_end) {
+            (then_body, Some((Mnemonic::Else, mut else_body))) => {
+                let else_line_end = match else_body.last() {
+                    Some(Action::LineEnd(line_end)) => *line_end,
+                    _ => if_line_end,
+                };
+                match self.parse_block(Mnemonic::Else, else_line_end) {
+                    (mut rest, Some((_, endif_line))) => {
+                        else_body.append(&mut rest);
+                        Ok((then_body, else_body, endif_line))
+                    }
+                    (_, None) => Err(unterminated),
+                }
+            }
+            (then_body, Some((_, endif_line))) => Ok((then_body, Program::new(), endif_line)),
+            (_, None) => Err(unterminated),
+        }
+    }
+
+    /// Parse the lines after `rept` up to its `endr` into a separate program.
+    /// Returns the program and the actions for the line with `endr`.
+    fn parse_repeat_body(
+        &mut self,
+        rept: &Spanned<Instruction>,
+        rept_line_end: usize,
+    ) -> Result<(Program, Program), AssemblerError> {
+        match self.parse_block(Mnemonic::Rept, rept_line_end) {
+            (body, Some((_, endr_line))) => Ok((body, endr_line)),
+            (_, None) => Err(AssemblerError {
+                message: String::from("Expected `endr` to end the `rept` block"),
+                labels: vec![(
+                    Location {
+                        span: rept.val.mnemonic.span.clone(),
+                        file_name: self.file_name.clone(),
+                    },
+                    Some(String::from("Block starts here")),
+                )],
+                help: None,
+            }),
+        }
+    }
+
     /// Parses an optional label at the beginning of a line.
     /// Matches the syntax (GLOBAL | PERIOD)? ID.
     fn parse_label(&mut self) -> Result<Option<Spanned<Label>>, AssemblerError> {
@@ -178,6 +459,11 @@ impl<'source, 'context> ParserContext<'source, 'context> {
         // Expect Ident to follow the above attribute.
         let identifier = match token {
             Token::Ident(ident) => ident,
+            Token::Error => {
+                return Err(
+                    self.unrecognized_characters(main_span, Some("Expected a label".to_string()))
+                )
+            }
             _ => {
                 return Err(AssemblerError {
                     message: format!("Unexpected token {}", token),
@@ -294,12 +580,12 @@ impl<'source, 'context> ParserContext<'source, 'context> {
 
         Ok(Some(
             if let Some(sublabel_identifier) = sublabel_identifier {
-                if visibility_span.is_some() {
+                if let Some(visibility_span) = visibility_span {
                     return Err(AssemblerError {
                         message: String::from("Cannot specify a global visibility with a sublabel"),
                         labels: vec![(
                             Location {
-                                span: visibility_span.unwrap(),
+                                span: visibility_span,
                                 file_name: self.file_name.clone(),
                             },
                             None,
@@ -333,6 +619,7 @@ impl<'source, 'context> ParserContext<'source, 'context> {
                         } else {
                             Visibility::Object
                         },
+                        address: None,
                     }),
                     main_span,
                 ))
@@ -340,114 +627,300 @@ impl<'source, 'context> ParserContext<'source, 'context> {
         ))
     }
 
+    /// Parse the address after `=` in `label = address`, which must end the line.
+    fn parse_label_address(
+        &mut self,
+        label: &Spanned<Label>,
+        equals_span: Range<usize>,
+    ) -> Result<Spanned<u16>, AssemblerError> {
+        if let Label::Sub(_) = label.val {
+            return Err(AssemblerError {
+                message: String::from("Only top level labels can be given an address"),
+                labels: vec![(
+                    Location {
+                        span: label.span.start..equals_span.end,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            });
+        }
+
+        self.check_number_digits()?;
+        let address = match self.lexer.next() {
+            Some((Token::Literal(Literal::Byte(byte)), span)) => Spanned::new((byte as u16, span)),
+            Some((Token::Literal(Literal::Word(word)), span)) => Spanned::new((word, span)),
+            Some((token, span)) => {
+                return Err(AssemblerError {
+                    message: format!("Unexpected token {}", token),
+                    labels: vec![(
+                        Location {
+                            span,
+                            file_name: self.file_name.clone(),
+                        },
+                        Some(String::from("Expected an address")),
+                    )],
+                    help: None,
+                });
+            }
+            None => {
+                return Err(AssemblerError {
+                    message: String::from("Unexpected end of file, expected an address"),
+                    labels: vec![(
+                        Location {
+                            span: equals_span,
+                            file_name: self.file_name.clone(),
+                        },
+                        None,
+                    )],
+                    help: None,
+                });
+            }
+        };
+
+        if let Some((token, span)) = self
+            .lexer
+            .next_if(|(token, _)| !matches!(token, Token::Eol))
+        {
+            return Err(AssemblerError {
+                message: format!("Unexpected token {}", token),
+                labels: vec![(
+                    Location {
+                        span,
+                        file_name: self.file_name.clone(),
+                    },
+                    Some(String::from("Expected the end of the line")),
+                )],
+                help: Some(String::from(
+                    "A label given an address doesn't mark code, so it has to be on its own line",
+                )),
+            });
+        }
+
+        Ok(address)
+    }
+
     fn parse_instruction(&mut self) -> Result<Option<Spanned<Instruction>>, AssemblerError> {
         let parsed_mnemonic = self.parse_mnemonic();
         let mut parsed_operand = None;
         // If mnemonic is implied then don't try to parse what follows
         // an operand, return and let parse_line skip it as a comment.
+        // Anything that could start an operand is still parsed as one so the
+        // generator can report that the instruction takes none.
         // TODO probably get rid of all these if lets and just return none if mnemonic is none
         if let Some(ref mnemonic) = parsed_mnemonic {
             if mnemonic.0.is_implied() {
-                return Ok(Some(Spanned::new((
-                    Instruction {
-                        mnemonic: Spanned::new(mnemonic.clone()),
-                        operand: None,
-                    },
-                    mnemonic.1.clone(),
-                ))));
+                match self.lexer.peek().cloned() {
+                    Some((
+                        Token::Literal(_)
+                        | Token::Ident(_)
+                        | Token::A
+                        | Token::Immediate
+                        | Token::LParen
+                        | Token::LAngle
+                        | Token::RAngle
+                        | Token::Period
+                        | Token::Plus
+                        | Token::ProgramCounter
+                        | Token::RepeatCounter,
+                        _,
+                    )) => {}
+                    // These can't start an operand on their own, but they only
+                    // make sense as part of one.
+                    Some((
+                        token @ (Token::Comma
+                        | Token::Minus
+                        | Token::Tilde
+                        | Token::LBracket
+                        | Token::X
+                        | Token::Y),
+                        span,
+                    )) => {
+                        return Err(AssemblerError {
+                            message: format!("`{}` takes no operand", mnemonic.0),
+                            labels: vec![(
+                                Location {
+                                    span,
+                                    file_name: self.file_name.clone(),
+                                },
+                                Some(format!("Unexpected {}", token)),
+                            )],
+                            help: Some(String::from("Remove the operand")),
+                        })
+                    }
+                    _ => {
+                        return Ok(Some(Spanned::new((
+                            Instruction {
+                                mnemonic: Spanned::new(mnemonic.clone()),
+                                operand: None,
+                            },
+                            mnemonic.1.clone(),
+                        ))))
+                    }
+                }
+            }
+            parsed_operand = match mnemonic.0 {
+                Mnemonic::Opcode => Some(self.parse_opcode_operand(mnemonic.1.clone())?),
+                Mnemonic::Align | Mnemonic::Dfs => self.parse_fill_operand(mnemonic.0)?,
+                Mnemonic::Dfb | Mnemonic::Dfw => self.parse_data_operand()?,
+                _ => self.parse_operand()?,
+            };
+
+            // An operand is required if there's no implied form, so don't treat
+            // unrecognized characters as a comment.
+            if parsed_operand.is_none() && OPCODES[mnemonic.0][AddressMode::Implied].is_none() {
+                if let Some((Token::Error, error_span)) = self.lexer.peek().cloned() {
+                    return Err(self.unrecognized_characters(
+                        error_span,
+                        Some(format!("Expected an operand for `{}`", mnemonic.0)),
+                    ));
+                }
             }
-            parsed_operand = self.parse_operand()?;
         }
 
         // Handle the include directive here so the nested parser can give its Items to the generation stage.
         // Other directives will be handled in that stage.
-        if let Some((ref mnemonic, ref mnemonic_span)) = parsed_mnemonic {
-            if let Mnemonic::Inl = mnemonic {
-                if let Some(Spanned {
-                    val:
-                        Operand {
-                            mode: OperandMode::Address,
-                            modifier: None,
-                            value:
-                                Spanned {
-                                    val: Value::String(to_include_name),
-                                    span: _,
-                                },
-                        },
-                    span: to_include_span,
-                }) = parsed_operand
-                {
-                    // Expect the included file extension to be 65a.
-                    return match Path::new(&to_include_name).extension() {
-                        Some(extension) if extension == "65a" => {
-                            let (included_name, parse_result) = self.handle_include(
-                                to_include_name,
-                                mnemonic_span.start..to_include_span.end,
-                            );
-
-                            if let Err(mut included_errors) = parse_result {
-                                self.errors.append(&mut included_errors);
-                                return Ok(None);
-                            }
+        if let Some((Mnemonic::Inl, ref mnemonic_span)) = parsed_mnemonic {
+            if let Some(Spanned {
+                val:
+                    Operand {
+                        mode: OperandMode::Address,
+                        modifier: None,
+                        value:
+                            Spanned {
+                                val: Value::String(to_include_name),
+                                span: _,
+                            },
+                    },
+                span: to_include_span,
+            }) = parsed_operand
+            {
+                // Expect the included file extension to be 65a.
+                return match Path::new(&to_include_name).extension() {
+                    Some(extension) if extension == "65a" => {
+                        let (included_name, parse_result) = self.handle_include(
+                            to_include_name,
+                            mnemonic_span.start..to_include_span.end,
+                        );
+
+                        if let Err(mut included_errors) = parse_result {
+                            self.errors.append(&mut included_errors);
+                            return Ok(None);
+                        }
 
-                            // None of these spans are ever actually used because parse_line will
-                            // check for an Inl mnemonic and not include it in the Program. Do
-                            // this because the operand is an entire included file and Locaiton
-                            // cannot span multiple files.
-                            Ok(Some(Spanned::new((
-                                Instruction {
-                                    mnemonic: Spanned::new((Mnemonic::Inl, mnemonic_span.clone())),
-                                    operand: Some(Spanned::new((
-                                        Operand {
-                                            mode: OperandMode::Immediate,
-                                            modifier: None,
-                                            value: Spanned::new((
-                                                Value::Include((
-                                                    included_name.unwrap(),
-                                                    parse_result.unwrap(),
-                                                )),
-                                                to_include_span.clone(),
+                        // None of these spans are ever actually used because parse_line will
+                        // check for an Inl mnemonic and not include it in the Program. Do
+                        // this because the operand is an entire included file and Locaiton
+                        // cannot span multiple files.
+                        Ok(Some(Spanned::new((
+                            Instruction {
+                                mnemonic: Spanned::new((Mnemonic::Inl, mnemonic_span.clone())),
+                                operand: Some(Spanned::new((
+                                    Operand {
+                                        mode: OperandMode::Immediate,
+                                        modifier: None,
+                                        value: Spanned::new((
+                                            Value::Include((
+                                                included_name.unwrap(),
+                                                parse_result.unwrap(),
                                             )),
-                                        },
-                                        to_include_span.clone(),
-                                    ))),
-                                },
-                                mnemonic_span.start..to_include_span.end,
-                            ))))
-                        }
-                        // Wrong or no extension, error.
-                        _ => Err(AssemblerError {
-                            message: format!("Could not include \"{}\"", to_include_name),
-                            labels: vec![(
-                                Location {
-                                    span: to_include_span,
-                                    file_name: self.file_name.clone(),
-                                },
-                                Some("File extension is expected to be `65a`".to_string()),
-                            )],
-                            help: None,
-                        }),
-                    };
-                } else {
-                    let mut labels = vec![];
-                    if let Some(operand) = parsed_operand {
-                        labels.push((
+                                            to_include_span.clone(),
+                                        )),
+                                    },
+                                    to_include_span.clone(),
+                                ))),
+                            },
+                            mnemonic_span.start..to_include_span.end,
+                        ))))
+                    }
+                    // Wrong or no extension, error.
+                    _ => Err(AssemblerError {
+                        message: format!("Could not include \"{}\"", to_include_name),
+                        labels: vec![(
                             Location {
-                                span: operand.span,
+                                span: to_include_span,
                                 file_name: self.file_name.clone(),
                             },
-                            // The operand cannot be a reference to a macro that is a string literal
-                            // because inclusion is done during parsing and macros are not evaluated
-                            // until code generation.
-                            Some("Expected an unmodified string literal".to_string()),
-                        ));
-                    }
-                    return Err(AssemblerError {
-                        message: "Invalid operand to `inl`".to_string(),
-                        labels: labels,
+                            Some("File extension is expected to be `65a`".to_string()),
+                        )],
                         help: None,
-                    });
+                    }),
+                };
+            } else {
+                let mut labels = vec![];
+                if let Some(operand) = parsed_operand {
+                    labels.push((
+                        Location {
+                            span: operand.span,
+                            file_name: self.file_name.clone(),
+                        },
+                        // The operand cannot be a reference to a macro that is a string literal
+                        // because inclusion is done during parsing and macros are not evaluated
+                        // until code generation.
+                        Some("Expected an unmodified string literal".to_string()),
+                    ));
                 }
+                return Err(AssemblerError {
+                    message: "Invalid operand to `inl`".to_string(),
+                    labels,
+                    help: None,
+                });
+            }
+        }
+
+        // Read the data file now because the parser has access to the files,
+        // and errors in the data file point into it.
+        if let Some((Mnemonic::Inb, ref mnemonic_span)) = parsed_mnemonic {
+            if let Some(Spanned {
+                val:
+                    Operand {
+                        mode: OperandMode::Address,
+                        modifier: None,
+                        value:
+                            Spanned {
+                                val: Value::String(data_name),
+                                span: _,
+                            },
+                    },
+                span: data_span,
+            }) = parsed_operand
+            {
+                let bytes = match self.read_data_file(data_name, data_span.clone()) {
+                    Err(mut data_errors) => {
+                        self.errors.append(&mut data_errors);
+                        return Ok(None);
+                    }
+                    Ok(bytes) => bytes,
+                };
+
+                return Ok(Some(Spanned::new((
+                    Instruction {
+                        mnemonic: Spanned::new((Mnemonic::Inb, mnemonic_span.clone())),
+                        operand: Some(Spanned::new((
+                            Operand {
+                                mode: OperandMode::Address,
+                                modifier: None,
+                                value: Spanned::new((Value::Data(bytes), data_span.clone())),
+                            },
+                            data_span.clone(),
+                        ))),
+                    },
+                    mnemonic_span.start..data_span.end,
+                ))));
+            } else {
+                return Err(AssemblerError {
+                    message: "Invalid operand to `inb`".to_string(),
+                    labels: vec![(
+                        Location {
+                            span: parsed_operand
+                                .map_or(mnemonic_span.clone(), |operand| operand.span),
+                            file_name: self.file_name.clone(),
+                        },
+                        Some("Expected an unmodified string literal".to_string()),
+                    )],
+                    help: None,
+                });
             }
         }
 
@@ -483,39 +956,289 @@ impl<'source, 'context> ParserContext<'source, 'context> {
         Some((mnemonic, mnemonic_span))
     }
 
-    fn parse_operand(&mut self) -> Result<Option<Spanned<Operand>>, AssemblerError> {
-        let (first_token, first_span) = match self.lexer.next_if(|(token, _)| {
-            matches!(token, Token::Literal { .. })
-                || matches!(token, Token::Ident { .. })
-                || matches!(token, Token::A)
-                || matches!(token, Token::Immediate)
-                || matches!(token, Token::LParen)
-                || matches!(token, Token::LAngle)
-                || matches!(token, Token::RAngle)
-                || matches!(token, Token::Period)
-        }) {
-            Some(next) => next,
-            None => return Ok(None),
-        };
-
-        // Decision tree to find which address mode the operand may be.
-        Ok(Some(match first_token {
-            // Simple enough, A makes up an entire operand.
-            Token::A => Spanned::new((
-                Operand {
-                    mode: OperandMode::Accumulator,
-                    modifier: None,
-                    value: Spanned::new((Value::Accumulator, first_span.clone())),
+    /// Parse the instruction and address mode after the `opcode` directive, like
+    /// `lda immediate`. Whether the instruction has that mode is checked later.
+    fn parse_opcode_operand(
+        &mut self,
+        directive_span: Range<usize>,
+    ) -> Result<Spanned<Operand>, AssemblerError> {
+        let (mnemonic, mnemonic_span) = self.parse_mnemonic().ok_or(AssemblerError {
+            message: String::from("Expected an instruction after `opcode`"),
+            labels: vec![(
+                Location {
+                    span: directive_span.clone(),
+                    file_name: self.file_name.clone(),
                 },
-                first_span,
-            )),
-            Token::Immediate => {
-                // Immediate address mode, only a value follows.
-                let operand_start = first_span.start;
-                let (modifier, value) = self.parse_modified_value()?.ok_or(AssemblerError {
-                    message: "Expected value after `#`".to_string(),
-                    labels: vec![(
-                        Location {
+                None,
+            )],
+            help: None,
+        })?;
+
+        let mode_names = AddressMode::NAMES
+            .iter()
+            .map(|(name, _)| format!("`{}`", name))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let (mode, mode_span) = match self.lexer.next() {
+            Some((Token::Ident(name), mode_span)) => {
+                let mode = AddressMode::NAMES
+                    .iter()
+                    .find(|(mode_name, _)| name.eq_ignore_ascii_case(mode_name))
+                    .map(|(_, mode)| *mode)
+                    .ok_or(AssemblerError {
+                        message: format!("Unknown address mode `{}`", name),
+                        labels: vec![(
+                            Location {
+                                span: mode_span.clone(),
+                                file_name: self.file_name.clone(),
+                            },
+                            None,
+                        )],
+                        help: Some(format!("Address modes are {}", mode_names)),
+                    })?;
+                (mode, mode_span)
+            }
+            _ => {
+                return Err(AssemblerError {
+                    message: format!("Expected an address mode after `{}`", mnemonic),
+                    labels: vec![(
+                        Location {
+                            span: mnemonic_span,
+                            file_name: self.file_name.clone(),
+                        },
+                        None,
+                    )],
+                    help: Some(format!("Address modes are {}", mode_names)),
+                })
+            }
+        };
+
+        let operand_span = mnemonic_span.start..mode_span.end;
+        Ok(Spanned::new((
+            Operand {
+                mode: OperandMode::Address,
+                modifier: None,
+                value: Spanned::new((Value::Opcode((mnemonic, mode)), operand_span.clone())),
+            },
+            operand_span,
+        )))
+    }
+
+    /// Parse the boundary after the `align` directive or the number of bytes after
+    /// the `dfs` directive, and the fill byte and name for the padding that may
+    /// follow it after commas.
+    fn parse_fill_operand(
+        &mut self,
+        directive: Mnemonic,
+    ) -> Result<Option<Spanned<Operand>>, AssemblerError> {
+        let boundary = match self.parse_value()? {
+            Some(boundary) => boundary,
+            None => return Ok(None),
+        };
+
+        let fill = match self.lexer.next_if(|(token, _)| token == &Token::Comma) {
+            Some((_, comma_span)) => Some(self.parse_value()?.ok_or(AssemblerError {
+                message: String::from("Expected a fill byte after `,`"),
+                labels: vec![(
+                    Location {
+                        span: comma_span,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            })?),
+            None => None,
+        };
+
+        // The padding may be named after the fill byte.
+        let name = match fill
+            .is_some()
+            .then(|| self.lexer.next_if(|(token, _)| token == &Token::Comma))
+            .flatten()
+        {
+            Some((_, comma_span)) => {
+                match self
+                    .lexer
+                    .next_if(|(token, _)| matches!(token, Token::Ident(_)))
+                {
+                    Some((Token::Ident(name), name_span)) => Some(Spanned::new((name, name_span))),
+                    _ => {
+                        return Err(AssemblerError {
+                            message: String::from("Expected a name for the padding after `,`"),
+                            labels: vec![(
+                                Location {
+                                    span: comma_span,
+                                    file_name: self.file_name.clone(),
+                                },
+                                None,
+                            )],
+                            help: None,
+                        })
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let operand_span = boundary.span.start
+            ..name
+                .as_ref()
+                .map_or(fill.as_ref().unwrap_or(&boundary).span.end, |name| {
+                    name.span.end
+                });
+        let value = if directive == Mnemonic::Dfs {
+            Value::Fill((Box::new(boundary), fill.map(Box::new), name))
+        } else {
+            Value::Alignment((Box::new(boundary), fill.map(Box::new), name))
+        };
+        Ok(Some(Spanned::new((
+            Operand {
+                mode: OperandMode::Address,
+                modifier: None,
+                value: Spanned::new((value, operand_span.clone())),
+            },
+            operand_span,
+        ))))
+    }
+
+    /// Parse the values after `dfb` or `dfw`, separated by commas. A single value is
+    /// an ordinary operand and several are put in a list. A value followed by `*` and
+    /// a number is repeated that many times, like `dfb $ff * 3`.
+    fn parse_data_operand(&mut self) -> Result<Option<Spanned<Operand>>, AssemblerError> {
+        self.repeat_counts = true;
+        let operands = self.parse_data_values();
+        self.repeat_counts = false;
+
+        let mut operands = match operands? {
+            Some(operands) => operands,
+            None => return self.parse_operand(),
+        };
+        if operands.len() == 1 {
+            return Ok(operands.pop());
+        }
+
+        let operand_span = operands[0].span.start..operands.last().unwrap().span.end;
+        Ok(Some(Spanned::new((
+            Operand {
+                mode: OperandMode::Address,
+                modifier: None,
+                value: Spanned::new((Value::List(operands), operand_span.clone())),
+            },
+            operand_span,
+        ))))
+    }
+
+    /// Parse the values of a data list with their repeats. Anything that doesn't
+    /// start with a value is left to be parsed as an operand so the generator can
+    /// report it the same way as for one value.
+    fn parse_data_values(&mut self) -> Result<Option<Vec<Spanned<Operand>>>, AssemblerError> {
+        let mut operands = match self.parse_modified_value()? {
+            Some(modified_value) => vec![data_operand(modified_value)],
+            None => return Ok(None),
+        };
+        self.parse_repeat_count(&mut operands)?;
+
+        while let Some((_, comma_span)) = self.lexer.next_if(|(token, _)| token == &Token::Comma) {
+            let modified_value = self.parse_modified_value()?.ok_or(AssemblerError {
+                message: String::from("Expected a value after `,`"),
+                labels: vec![(
+                    Location {
+                        span: comma_span,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: Some(String::from(
+                    "`dfb` and `dfw` take a list of values separated by commas",
+                )),
+            })?;
+            operands.push(data_operand(modified_value));
+            self.parse_repeat_count(&mut operands)?;
+        }
+
+        Ok(Some(operands))
+    }
+
+    /// Repeat the last value of a data list by the count after a `*`, if there is one.
+    fn parse_repeat_count(
+        &mut self,
+        operands: &mut Vec<Spanned<Operand>>,
+    ) -> Result<(), AssemblerError> {
+        let star_span = match self.lexer.next_if(|(token, _)| token == &Token::Star) {
+            Some((_, star_span)) => star_span,
+            None => return Ok(()),
+        };
+
+        self.check_number_digits()?;
+        let count = match self.lexer.next() {
+            Some((Token::Literal(Literal::Byte(byte)), _)) if byte > 0 => byte as usize,
+            Some((Token::Literal(Literal::Word(word)), _)) if word > 0 => word as usize,
+            next => {
+                let span = next.map_or(star_span.clone(), |(_, span)| span);
+                return Err(AssemblerError {
+                    message: String::from("Expected a repeat count after `*`"),
+                    labels: vec![(
+                        Location {
+                            span: star_span.start..span.end,
+                            file_name: self.file_name.clone(),
+                        },
+                        None,
+                    )],
+                    help: Some(String::from(
+                        "The count is a number of at least 1. Use parentheses to multiply, \
+                        like `dfb (2*3)`",
+                    )),
+                });
+            }
+        };
+
+        let repeated = operands.last().unwrap().clone();
+        operands.extend(std::iter::repeat_n(repeated, count - 1));
+        Ok(())
+    }
+
+    fn parse_operand(&mut self) -> Result<Option<Spanned<Operand>>, AssemblerError> {
+        self.check_number_digits()?;
+        let (first_token, first_span) = match self.lexer.next_if(|(token, _)| {
+            matches!(token, Token::Literal { .. })
+                || matches!(token, Token::Ident { .. })
+                || matches!(token, Token::A)
+                || matches!(token, Token::Immediate)
+                || matches!(token, Token::LParen)
+                || matches!(token, Token::LAngle)
+                || matches!(token, Token::RAngle)
+                || matches!(token, Token::Period)
+                || matches!(token, Token::Plus)
+                || matches!(token, Token::Minus)
+                || matches!(token, Token::ProgramCounter)
+                || matches!(token, Token::RepeatCounter)
+        }) {
+            Some((sign @ (Token::Plus | Token::Minus), sign_span)) => {
+                self.expect_signed_number(sign, sign_span)?
+            }
+            Some(next) => next,
+            None => return Ok(None),
+        };
+
+        // Decision tree to find which address mode the operand may be.
+        Ok(Some(match first_token {
+            // Simple enough, A makes up an entire operand.
+            Token::A => Spanned::new((
+                Operand {
+                    mode: OperandMode::Accumulator,
+                    modifier: None,
+                    value: Spanned::new((Value::Accumulator, first_span.clone())),
+                },
+                first_span,
+            )),
+            Token::Immediate => {
+                // Immediate address mode, only a value follows.
+                let operand_start = first_span.start;
+                let (modifier, value) = self.parse_modified_value()?.ok_or(AssemblerError {
+                    message: "Expected value after `#`".to_string(),
+                    labels: vec![(
+                        Location {
                             span: first_span,
                             file_name: self.file_name.clone(),
                         },
@@ -831,7 +1554,7 @@ impl<'source, 'context> ParserContext<'source, 'context> {
                 };
 
                 // first_span.end = sublabel_identifier_span.end;
-                let operand_span = first_span.start..sublabel_identifier_span.end;
+                let sublabel_span = first_span.start..sublabel_identifier_span.end;
 
                 let value = match &self.current_parent_label {
                     Some(parent) => Value::Reference(format!("{}.{}", parent, sublabel_identifier)),
@@ -842,7 +1565,7 @@ impl<'source, 'context> ParserContext<'source, 'context> {
                             ),
                             labels: vec![(
                                 Location {
-                                    span: operand_span,
+                                    span: sublabel_span,
                                     file_name: self.file_name.clone(),
                                 },
                                 None,
@@ -851,12 +1574,14 @@ impl<'source, 'context> ParserContext<'source, 'context> {
                         })
                     }
                 };
+                let value = self.parse_expression(Spanned::new((value, sublabel_span)))?;
+                let operand_span = value.span.clone();
 
                 Spanned::new((
                     Operand {
                         mode: OperandMode::Address,
                         modifier: None,
-                        value: Spanned::new((value, operand_span.clone())),
+                        value,
                     },
                     operand_span,
                 ))
@@ -869,16 +1594,31 @@ impl<'source, 'context> ParserContext<'source, 'context> {
                 //   zeropage
                 //   zeropage, X-indexed
                 //   zeropage, Y-indexed
-                let value = Spanned::new((
-                    match first_token {
-                        Token::Literal(Literal::Byte(byte)) => Value::Byte(byte),
-                        Token::Literal(Literal::Word(word)) => Value::Word(word),
-                        Token::Literal(Literal::String(string)) => Value::String(string),
-                        Token::Ident(ident) => Value::Reference(ident),
-                        _ => unreachable!(),
-                    },
-                    first_span.clone(),
-                ));
+                let value = match first_token {
+                    Token::Literal(Literal::Byte(byte)) => {
+                        Spanned::new((Value::Byte(byte), first_span))
+                    }
+                    Token::Literal(Literal::Word(word)) => {
+                        Spanned::new((Value::Word(word), first_span))
+                    }
+                    Token::Literal(Literal::String(string)) => {
+                        Spanned::new((Value::String(string), first_span))
+                    }
+                    Token::Literal(Literal::Character(character)) => Spanned::new((
+                        Value::Byte(self.parse_character(&character, first_span.clone())?),
+                        first_span,
+                    )),
+                    Token::ProgramCounter => Spanned::new((Value::ProgramCounter, first_span)),
+                    Token::RepeatCounter => {
+                        Spanned::new((self.repeat_counter(first_span.clone())?, first_span))
+                    }
+                    Token::Ident(ident) => self.parse_identifier_value(ident, first_span)?,
+                    _ => unreachable!(),
+                };
+                let value = self.parse_width_suffix(value)?;
+                let value = self.parse_expression(value)?;
+                // A coercion or expression spans more than the first token.
+                let first_span = value.span.clone();
                 let (peeked_token, _) = match self.lexer.peek() {
                     None => {
                         // Plain absolute or zeropage operand.
@@ -944,9 +1684,7 @@ impl<'source, 'context> ParserContext<'source, 'context> {
     }
 
     /// Parse a value with an optional modifier. Returns Err if there was a modifier with no value.
-    fn parse_modified_value(
-        &mut self,
-    ) -> Result<Option<(Option<Spanned<Modifier>>, Spanned<Value>)>, AssemblerError> {
+    fn parse_modified_value(&mut self) -> Result<Option<ModifiedValue>, AssemblerError> {
         if let Some(modifier) = self.parse_modifier() {
             // If there's a modifier then expect a value to follow.
             let modifier_span = modifier.span.clone();
@@ -960,13 +1698,9 @@ impl<'source, 'context> ParserContext<'source, 'context> {
 
     /// Tries to parse a modifier.
     fn parse_modifier(&mut self) -> Option<Spanned<Modifier>> {
-        let (modifier_token, modifier_span) = match self
-            .lexer
-            .next_if(|(token, _)| matches!(token, Token::LAngle) || matches!(token, Token::RAngle))
-        {
-            Some(next) => next,
-            None => return None,
-        };
+        let (modifier_token, modifier_span) = self.lexer.next_if(|(token, _)| {
+            matches!(token, Token::LAngle) || matches!(token, Token::RAngle)
+        })?;
 
         Some(Spanned::new((
             if let Token::LAngle = modifier_token {
@@ -1001,8 +1735,288 @@ impl<'source, 'context> ParserContext<'source, 'context> {
         }
     }
 
-    /// Tries to parse a value.
+    /// Expect a number after a `+` sign, which doesn't change it, or a `-` sign,
+    /// which negates it. Returns the number's token spanning the sign as well.
+    fn expect_signed_number(
+        &mut self,
+        sign: Token,
+        sign_span: Range<usize>,
+    ) -> Result<(Token, Range<usize>), AssemblerError> {
+        self.check_number_digits()?;
+        match self.lexer.next_if(|(token, _)| {
+            matches!(
+                token,
+                Token::Literal(Literal::Byte(_)) | Token::Literal(Literal::Word(_))
+            )
+        }) {
+            Some((Token::Literal(number), number_span)) if sign == Token::Minus => Ok((
+                Token::Literal(lexer::negate(number)),
+                sign_span.start..number_span.end,
+            )),
+            Some((number, number_span)) => Ok((number, sign_span.start..number_span.end)),
+            None => Err(AssemblerError {
+                message: format!("Expected a number after {}", sign),
+                labels: vec![(
+                    Location {
+                        span: sign_span,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            }),
+        }
+    }
+
+    /// Tries to parse a value, which may be an expression of several.
     fn parse_value(&mut self) -> Result<Option<Spanned<Value>>, AssemblerError> {
+        match self.parse_factor()? {
+            Some(first) => self.parse_expression(first).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parse the rest of an expression after its first value, if an operator
+    /// follows it. Operators are applied in the order of `PRECEDENCE`, and operators
+    /// of the same precedence are applied from left to right.
+    fn parse_expression(
+        &mut self,
+        first: Spanned<Value>,
+    ) -> Result<Spanned<Value>, AssemblerError> {
+        self.parse_precedence(first, 0)
+    }
+
+    /// Parse the operators of one precedence level after the first value, where
+    /// each side may use operators of the levels after it.
+    fn parse_precedence(
+        &mut self,
+        first: Spanned<Value>,
+        level: usize,
+    ) -> Result<Spanned<Value>, AssemblerError> {
+        if level == PRECEDENCE.len() {
+            return Ok(first);
+        }
+
+        let mut left = self.parse_precedence(first, level + 1)?;
+        while let Some((operator, right)) = self.parse_operator(PRECEDENCE[level])? {
+            let right = self.parse_precedence(right, level + 1)?;
+            left = expression(left, operator, right);
+        }
+
+        Ok(left)
+    }
+
+    /// Parse one of the given operators and the value after it, if it comes next.
+    fn parse_operator(
+        &mut self,
+        operators: &[Operator],
+    ) -> Result<Option<(Operator, Spanned<Value>)>, AssemblerError> {
+        let operator = match self.lexer.peek() {
+            Some((Token::Plus, _)) => Operator::Add,
+            Some((Token::Minus, _)) => Operator::Subtract,
+            Some((Token::Star, _)) if !self.repeat_counts => Operator::Multiply,
+            Some((Token::Slash, _)) => Operator::Divide,
+            Some((Token::Ampersand, _)) => Operator::And,
+            Some((Token::Pipe, _)) => Operator::Or,
+            Some((Token::Caret, _)) => Operator::Xor,
+            Some((Token::ShiftLeft, _)) => Operator::ShiftLeft,
+            Some((Token::ShiftRight, _)) => Operator::ShiftRight,
+            _ => return Ok(None),
+        };
+        if !operators.contains(&operator) {
+            return Ok(None);
+        }
+
+        let (operator_token, operator_span) = self.lexer.next().unwrap();
+        let right = self.parse_factor()?.ok_or(AssemblerError {
+            message: format!("Expected a value after {}", operator_token),
+            labels: vec![(
+                Location {
+                    span: operator_span,
+                    file_name: self.file_name.clone(),
+                },
+                None,
+            )],
+            help: None,
+        })?;
+
+        Ok(Some((operator, right)))
+    }
+
+    /// Parse a single value, an expression in parentheses, or the complement of
+    /// either.
+    fn parse_factor(&mut self) -> Result<Option<Spanned<Value>>, AssemblerError> {
+        if let Some((_, tilde_span)) = self
+            .lexer
+            .next_if(|(token, _)| matches!(token, Token::Tilde))
+        {
+            let value = self.parse_factor()?.ok_or(AssemblerError {
+                message: String::from("Expected a value after `~`"),
+                labels: vec![(
+                    Location {
+                        span: tilde_span.clone(),
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            })?;
+            let span = tilde_span.start..value.span.end;
+            return Ok(Some(Spanned::new((Value::Not(Box::new(value)), span))));
+        }
+
+        let lparen_span = match self
+            .lexer
+            .next_if(|(token, _)| matches!(token, Token::LParen))
+        {
+            Some((_, lparen_span)) => lparen_span,
+            None => return self.parse_single_value(),
+        };
+
+        // Inside parentheses `*` always multiplies.
+        let repeat_counts = std::mem::replace(&mut self.repeat_counts, false);
+        let value = self.parse_parenthesized(lparen_span);
+        self.repeat_counts = repeat_counts;
+        value
+    }
+
+    /// Parse the expression after a `(` up to the `)` that closes it.
+    fn parse_parenthesized(
+        &mut self,
+        lparen_span: Range<usize>,
+    ) -> Result<Option<Spanned<Value>>, AssemblerError> {
+        let first = self.parse_factor()?.ok_or(AssemblerError {
+            message: String::from("Expected value after `(`"),
+            labels: vec![(
+                Location {
+                    span: lparen_span.clone(),
+                    file_name: self.file_name.clone(),
+                },
+                None,
+            )],
+            help: None,
+        })?;
+        let value = self.parse_expression(first)?;
+
+        match self
+            .lexer
+            .next_if(|(token, _)| matches!(token, Token::RParen))
+        {
+            Some((_, rparen_span)) => Ok(Some(Spanned::new((
+                value.val,
+                lparen_span.start..rparen_span.end,
+            )))),
+            None => Err(AssemblerError {
+                message: String::from("Expected `)` after expression"),
+                labels: vec![(
+                    Location {
+                        span: value.span,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: Some(String::from("The `(` before it starts the expression")),
+            }),
+        }
+    }
+
+    /// Tries to parse a value, including a width suffix.
+    fn parse_single_value(&mut self) -> Result<Option<Spanned<Value>>, AssemblerError> {
+        match self.parse_unsuffixed_value()? {
+            Some(value) => self.parse_width_suffix(value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parse a `.b` or `.w` directly after a value, which coerces it to a byte or word
+    /// the same way as `byte()` and `word()`.
+    fn parse_width_suffix(
+        &mut self,
+        value: Spanned<Value>,
+    ) -> Result<Spanned<Value>, AssemblerError> {
+        let value_end = value.span.end;
+        let period_span = match self
+            .lexer
+            .next_if(|(token, span)| matches!(token, Token::Period) && span.start == value_end)
+        {
+            Some((_, period_span)) => period_span,
+            None => return Ok(value),
+        };
+
+        let width = match self.lexer.peek() {
+            Some((Token::Ident(suffix), suffix_span)) if suffix_span.start == period_span.end => {
+                match suffix.to_lowercase().as_str() {
+                    "b" => Some(Width::Byte),
+                    "w" => Some(Width::Word),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match width {
+            Some(width) => {
+                let (_, suffix_span) = self.lexer.next().unwrap();
+                let span = value.span.start..suffix_span.end;
+                Ok(Spanned::new((
+                    Value::Coerced((width, Box::new(value))),
+                    span,
+                )))
+            }
+            None => Err(AssemblerError {
+                message: String::from("Expected `b` or `w` after `.`"),
+                labels: vec![(
+                    Location {
+                        span: period_span,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: Some(String::from(
+                    "A `.b` or `.w` suffix makes a value a byte or a word",
+                )),
+            }),
+        }
+    }
+
+    /// A number with a digit that isn't in its radix is lexed as an error token, so
+    /// point out the digit if the next token is one, instead of treating the number
+    /// as unrecognized characters.
+    fn check_number_digits(&mut self) -> Result<(), AssemblerError> {
+        let error_span = match self.lexer.peek() {
+            Some((Token::Error, error_span)) => error_span.clone(),
+            _ => return Ok(()),
+        };
+        let (offset, digit, radix) = match lexer::invalid_digit(&self.source[error_span.clone()]) {
+            Some(invalid_digit) => invalid_digit,
+            None => return Ok(()),
+        };
+        self.lexer.next();
+
+        let (radix_name, digits) = match radix {
+            2 => ("binary", "`0` and `1`"),
+            8 => ("octal", "`0` to `7`"),
+            10 => ("decimal", "`0` to `9`"),
+            _ => ("hexadecimal", "`0` to `9` and `a` to `f` in either case"),
+        };
+        let digit_start = error_span.start + offset;
+        Err(AssemblerError {
+            message: format!("`{}` is not a valid {} digit", digit, radix_name),
+            labels: vec![(
+                Location {
+                    span: digit_start..digit_start + digit.len_utf8(),
+                    file_name: self.file_name.clone(),
+                },
+                None,
+            )],
+            help: Some(format!("The {} digits are {}", radix_name, digits)),
+        })
+    }
+
+    /// Tries to parse a value without a width suffix.
+    fn parse_unsuffixed_value(&mut self) -> Result<Option<Spanned<Value>>, AssemblerError> {
+        self.check_number_digits()?;
+
         // let next_token = self.lexer.next_if(|(token, _)| {
         //     matches!(token, Token::Literal { .. })
         //         || matches!(token, Token::Ident { .. })
@@ -1013,17 +2027,32 @@ impl<'source, 'context> ParserContext<'source, 'context> {
             matches!(token, Token::Literal { .. })
                 || matches!(token, Token::Ident { .. })
                 || matches!(token, Token::Period)
+                || matches!(token, Token::Plus)
+                || matches!(token, Token::Minus)
+                || matches!(token, Token::ProgramCounter)
+                || matches!(token, Token::RepeatCounter)
         }) {
+            Some((sign @ (Token::Plus | Token::Minus), sign_span)) => {
+                self.expect_signed_number(sign, sign_span)?
+            }
             Some(next) => next,
             None => return Ok(None),
         };
 
+        if let Token::Ident(ident) = value_token {
+            return self.parse_identifier_value(ident, value_span).map(Some);
+        }
+
         Ok(Some(Spanned::new((
             match value_token {
                 Token::Literal(Literal::Byte(byte)) => Value::Byte(byte),
                 Token::Literal(Literal::Word(word)) => Value::Word(word),
                 Token::Literal(Literal::String(string)) => Value::String(string),
-                Token::Ident(ident) => Value::Reference(ident),
+                Token::ProgramCounter => Value::ProgramCounter,
+                Token::RepeatCounter => self.repeat_counter(value_span.clone())?,
+                Token::Literal(Literal::Character(character)) => {
+                    Value::Byte(self.parse_character(&character, value_span.clone())?)
+                }
                 Token::Period => {
                     // Expect an identifier to follow.
                     let (sublabel_identifier, sublabel_identifier_span) = match self
@@ -1082,6 +2111,318 @@ impl<'source, 'context> ParserContext<'source, 'context> {
         ))))
     }
 
+    /// Convert a character literal, still in its quotes, to the Apple screen code
+    /// that a string would use for it.
+    fn parse_character(
+        &self,
+        character: &str,
+        character_span: Range<usize>,
+    ) -> Result<u8, AssemblerError> {
+        let location = Location {
+            span: character_span,
+            file_name: self.file_name.clone(),
+        };
+
+        if character.len() < 2 || !character.ends_with('\'') {
+            return Err(AssemblerError {
+                message: String::from("Unterminated character literal"),
+                labels: vec![(location, None)],
+                help: Some(String::from("Close the character with `'`")),
+            });
+        }
+
+        let contents = &character[1..character.len() - 1];
+        if contents.is_empty() {
+            return Err(AssemblerError {
+                message: String::from("Empty character literal"),
+                labels: vec![(location, None)],
+                help: None,
+            });
+        }
+
+        match apple_string(contents, location.clone())?.as_slice() {
+            [byte] => Ok(byte.0),
+            _ => Err(AssemblerError {
+                message: format!(
+                    "Character literal `{}` is not a single character",
+                    character
+                ),
+                labels: vec![(location, None)],
+                help: Some(String::from(
+                    "Use a string in double quotes for more than one character",
+                )),
+            }),
+        }
+    }
+
+    /// Parse the value starting with an identifier. This is a reference unless
+    /// the identifier is `byte` or `word` followed by `(`, which coerces the
+    /// enclosed value to that width, or `sizeof` followed by `(`.
+    fn parse_identifier_value(
+        &mut self,
+        identifier: String,
+        identifier_span: Range<usize>,
+    ) -> Result<Spanned<Value>, AssemblerError> {
+        let width = match identifier.to_lowercase().as_str() {
+            "byte" => Width::Byte,
+            "word" => Width::Word,
+            "sizeof" => return self.parse_section_size(identifier, identifier_span),
+            _ => return self.parse_index(identifier, identifier_span),
+        };
+
+        let lparen_span = match self
+            .lexer
+            .next_if(|(token, _)| matches!(token, Token::LParen))
+        {
+            Some((_, lparen_span)) => lparen_span,
+            None => {
+                return Ok(Spanned::new((
+                    Value::Reference(identifier),
+                    identifier_span,
+                )))
+            }
+        };
+
+        let value = self.parse_value()?.ok_or(AssemblerError {
+            message: format!("Expected value after `{}(`", width),
+            labels: vec![(
+                Location {
+                    span: identifier_span.start..lparen_span.end,
+                    file_name: self.file_name.clone(),
+                },
+                None,
+            )],
+            help: None,
+        })?;
+
+        match self.lexer.next() {
+            Some((Token::RParen, rparen_span)) => Ok(Spanned::new((
+                Value::Coerced((width, Box::new(value))),
+                identifier_span.start..rparen_span.end,
+            ))),
+            _ => Err(AssemblerError {
+                message: String::from("Expected `)` after value"),
+                labels: vec![(
+                    Location {
+                        span: value.span,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            }),
+        }
+    }
+
+    /// Parse the `[index]` after a reference to a table, if there is one.
+    fn parse_index(
+        &mut self,
+        identifier: String,
+        identifier_span: Range<usize>,
+    ) -> Result<Spanned<Value>, AssemblerError> {
+        let lbracket_span = match self
+            .lexer
+            .next_if(|(token, _)| matches!(token, Token::LBracket))
+        {
+            Some((_, lbracket_span)) => lbracket_span,
+            None => {
+                return Ok(Spanned::new((
+                    Value::Reference(identifier),
+                    identifier_span,
+                )))
+            }
+        };
+
+        let index = match self.lexer.next() {
+            Some((Token::Literal(Literal::Byte(byte)), _)) => byte as u16,
+            Some((Token::Literal(Literal::Word(word)), _)) => word,
+            _ => {
+                return Err(AssemblerError {
+                    message: format!("Expected an index after `{}[`", identifier),
+                    labels: vec![(
+                        Location {
+                            span: identifier_span.start..lbracket_span.end,
+                            file_name: self.file_name.clone(),
+                        },
+                        None,
+                    )],
+                    help: Some(String::from("The index is a number, like `table[3]`")),
+                })
+            }
+        };
+
+        match self.lexer.next() {
+            Some((Token::RBracket, rbracket_span)) => Ok(Spanned::new((
+                Value::Indexed((identifier, index)),
+                identifier_span.start..rbracket_span.end,
+            ))),
+            _ => Err(AssemblerError {
+                message: String::from("Expected `]` after index"),
+                labels: vec![(
+                    Location {
+                        span: identifier_span.start..lbracket_span.end,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            }),
+        }
+    }
+
+    /// Parse the rest of `sizeof("section")` after the identifier. Without the `(`
+    /// it's a reference to a label named `sizeof`.
+    fn parse_section_size(
+        &mut self,
+        identifier: String,
+        identifier_span: Range<usize>,
+    ) -> Result<Spanned<Value>, AssemblerError> {
+        let lparen_span = match self
+            .lexer
+            .next_if(|(token, _)| matches!(token, Token::LParen))
+        {
+            Some((_, lparen_span)) => lparen_span,
+            None => {
+                return Ok(Spanned::new((
+                    Value::Reference(identifier),
+                    identifier_span,
+                )))
+            }
+        };
+
+        let (name, name_span) = match self.lexer.next() {
+            Some((Token::Literal(Literal::String(name)), name_span)) => (name, name_span),
+            _ => {
+                return Err(AssemblerError {
+                    message: String::from("Expected a section name after `sizeof(`"),
+                    labels: vec![(
+                        Location {
+                            span: identifier_span.start..lparen_span.end,
+                            file_name: self.file_name.clone(),
+                        },
+                        None,
+                    )],
+                    help: Some(String::from("Section names are strings, like `\"code\"`")),
+                })
+            }
+        };
+
+        match self.lexer.next() {
+            Some((Token::RParen, rparen_span)) => Ok(Spanned::new((
+                Value::SectionSize(name),
+                identifier_span.start..rparen_span.end,
+            ))),
+            _ => Err(AssemblerError {
+                message: String::from("Expected `)` after section name"),
+                labels: vec![(
+                    Location {
+                        span: name_span,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            }),
+        }
+    }
+
+    /// Create an error showing the characters that the lexer could not recognize,
+    /// starting at the error token that was found. Each unrecognized character is
+    /// its own token so this consumes the ones that follow directly after.
+    fn unrecognized_characters(
+        &mut self,
+        error_span: Range<usize>,
+        label: Option<String>,
+    ) -> AssemblerError {
+        let mut span = error_span;
+        // The first error token may have only been peeked.
+        self.lexer
+            .next_if(|(token, next_span)| matches!(token, Token::Error) && *next_span == span);
+        while let Some((_, next_span)) = self.lexer.next_if(|(token, next_span)| {
+            matches!(token, Token::Error) && next_span.start == span.end
+        }) {
+            span.end = next_span.end;
+        }
+
+        AssemblerError {
+            message: format!("Unrecognized characters `{}`", &self.source[span.clone()]),
+            labels: vec![(
+                Location {
+                    span,
+                    file_name: self.file_name.clone(),
+                },
+                label,
+            )],
+            help: None,
+        }
+    }
+
+    /// Read a data file for `inb` containing numbers separated by whitespace,
+    /// commas, or newlines. Returns the bytes, or the errors found in the file.
+    fn read_data_file(
+        &mut self,
+        data_name: String,
+        data_span: Range<usize>,
+    ) -> Result<Vec<u8>, Vec<AssemblerError>> {
+        let data_source = read_source(&data_name).map_err(|error| {
+            vec![AssemblerError {
+                message: format!("Could not read data file \"{}\": {}", data_name, error),
+                labels: vec![(
+                    Location {
+                        span: data_span,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            }]
+        })?;
+
+        let mut bytes = Vec::new();
+        let mut errors = Vec::new();
+        for (token, span) in Token::lexer(&data_source).spanned() {
+            let message = match token {
+                Token::Literal(Literal::Byte(byte)) => {
+                    bytes.push(byte);
+                    continue;
+                }
+                Token::Comma | Token::Eol => continue,
+                Token::Literal(Literal::Word(word)) => {
+                    format!("Value `${:04x}` in data file does not fit in a byte", word)
+                }
+                Token::Error => format!(
+                    "Unrecognized characters `{}` in data file",
+                    &data_source[span.clone()]
+                ),
+                token => format!("Unexpected token {} in data file", token),
+            };
+
+            errors.push(AssemblerError {
+                message,
+                labels: vec![(
+                    Location {
+                        span,
+                        file_name: data_name.clone(),
+                    },
+                    Some("Expected a byte".to_string()),
+                )],
+                help: None,
+            });
+        }
+
+        // Errors point into the data file, so it needs to be available for reporting.
+        if !errors.is_empty() && !self.id_table.contains_key(&data_name) {
+            let data_file_id = self.files.add(data_name.clone(), data_source);
+            self.id_table.insert(data_name, data_file_id);
+        }
+
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(bytes)
+        }
+    }
+
     /// Read and parse an included file, preventing circular inclusion.
     /// Returns the included file ID (if reading was successful) and
     /// the result of parsing the included file.
@@ -1092,37 +2433,46 @@ impl<'source, 'context> ParserContext<'source, 'context> {
     ) -> (Option<String>, Result<Program, Vec<AssemblerError>>) {
         // Check if the file has already been parsed. If it has then it would loop back
         // to this file and cause infinite recursion.
-        if let Some(include) = self
+        if let Some(cycle_start) = self
             .include_stack
             .iter()
-            .find(|include| include.included == to_include_name)
+            .position(|include| include.included == to_include_name)
         {
+            let include = &self.include_stack[cycle_start];
+            let mut labels = vec![
+                (
+                    Location {
+                        span: to_include_span,
+                        // SAFETY Last can be unwrapped because the stack has at least one member
+                        // before parse_program and subsequently handle_include are called.
+                        file_name: self.include_stack.last().unwrap().included.clone(),
+                    },
+                    Some(format!("Could not include \"{}\"", to_include_name)),
+                ),
+                (
+                    Location {
+                        span: include.loc.span.clone(),
+                        file_name: include.loc.file_name.clone(),
+                    },
+                    Some(if cycle_start == 0 {
+                        "Given in assembler invocatiion".to_string()
+                    } else {
+                        "Already included here".to_string()
+                    }),
+                ),
+            ];
+            // Show the rest of the cycle, from the file that was included again to this one.
+            labels.extend(
+                self.include_stack[cycle_start + 1..]
+                    .iter()
+                    .map(|include| (include.loc.clone(), Some("Included here".to_string()))),
+            );
+
             return (
                 None,
                 Err(vec![AssemblerError {
                     message: "Recursive include found".to_string(),
-                    labels: vec![
-                        (
-                            Location {
-                                span: to_include_span,
-                                // SAFETY Last can be unwrapped because the stack has at least one member
-                                // before parse_program and subsequently handle_include are called.
-                                file_name: self.include_stack.last().unwrap().included.clone(),
-                            },
-                            Some(format!("Could not include \"{}\"", to_include_name)),
-                        ),
-                        (
-                            Location {
-                                span: include.loc.span.clone(),
-                                file_name: include.loc.file_name.clone(),
-                            },
-                            Some(if include == self.include_stack.first().unwrap() {
-                                "Given in assembler invocatiion".to_string()
-                            } else {
-                                "Already included here".to_string()
-                            }),
-                        ),
-                    ],
+                    labels,
 
                     help: Some(formatdoc!(
                         "Labels can be referenced before they're defined,
@@ -1133,8 +2483,31 @@ impl<'source, 'context> ParserContext<'source, 'context> {
             );
         }
 
+        // The top level file is on the stack too, so its length is how deep this include is.
+        if self.include_stack.len() > self.max_include_depth {
+            return (
+                None,
+                Err(vec![AssemblerError {
+                    message: format!(
+                        "Includes are nested more than {} deep",
+                        self.max_include_depth
+                    ),
+                    labels: vec![(
+                        Location {
+                            span: to_include_span,
+                            file_name: self.include_stack.last().unwrap().included.clone(),
+                        },
+                        Some(format!("Could not include \"{}\"", to_include_name)),
+                    )],
+                    help: Some(String::from(
+                        "The limit can be raised with --max-include-depth",
+                    )),
+                }]),
+            );
+        }
+
         // No recursion, read the source.
-        let included_source = match fs::read_to_string(&to_include_name) {
+        let included_source = match read_source(&to_include_name) {
             Err(error) => {
                 return (
                     None,
@@ -1169,7 +2542,8 @@ impl<'source, 'context> ParserContext<'source, 'context> {
             self.files,
             self.include_stack,
             self.id_table,
-        );
+        )
+        .with_max_include_depth(self.max_include_depth);
 
         let parse_result = included_context.parse_program();
         let included_file_id = if self.id_table.contains_key(&self.file_name) {
@@ -1188,6 +2562,24 @@ impl<'source, 'context> ParserContext<'source, 'context> {
         (Some(to_include_name), parse_result)
     }
 
+    /// The counter of the `rept` block being parsed, which only exists inside one.
+    fn repeat_counter(&self, span: Range<usize>) -> Result<Value, AssemblerError> {
+        if !self.blocks.contains(&Mnemonic::Rept) {
+            return Err(AssemblerError {
+                message: String::from("`\\@` can only be used inside a `rept` block"),
+                labels: vec![(
+                    Location {
+                        span,
+                        file_name: self.file_name.clone(),
+                    },
+                    None,
+                )],
+                help: None,
+            });
+        }
+        Ok(Value::RepeatCounter)
+    }
+
     /// Skip past the end of the line after it is done being parsed.
     /// This makes a dedicated line comment character unnecessary
     /// just like the good ol' days. Returns the index of the end of
@@ -1216,6 +2608,41 @@ impl<'source, 'context> ParserContext<'source, 'context> {
     }
 }
 
+/// The operators of expressions from the ones applied last to the ones applied
+/// first, the same as in C.
+const PRECEDENCE: [&[Operator]; 6] = [
+    &[Operator::Or],
+    &[Operator::Xor],
+    &[Operator::And],
+    &[Operator::ShiftLeft, Operator::ShiftRight],
+    &[Operator::Add, Operator::Subtract],
+    &[Operator::Multiply, Operator::Divide],
+];
+
+/// Combine two values with an operator, spanning both of them.
+fn expression(left: Spanned<Value>, operator: Operator, right: Spanned<Value>) -> Spanned<Value> {
+    let span = left.span.start..right.span.end;
+    Spanned::new((
+        Value::Expr((Box::new(left), operator, Box::new(right))),
+        span,
+    ))
+}
+
+/// Make an operand of one of the values after `dfb` or `dfw`.
+fn data_operand((modifier, value): ModifiedValue) -> Spanned<Operand> {
+    let span = modifier
+        .as_ref()
+        .map_or(value.span.start, |modifier| modifier.span.start)..value.span.end;
+    Spanned::new((
+        Operand {
+            mode: OperandMode::Address,
+            modifier,
+            value,
+        },
+        span,
+    ))
+}
+
 impl TryFrom<&Token> for Mnemonic {
     type Error = ();
 
@@ -1278,13 +2705,33 @@ impl TryFrom<&Token> for Mnemonic {
             Token::Txa => Ok(Mnemonic::Txa),
             Token::Txs => Ok(Mnemonic::Txs),
             Token::Tya => Ok(Mnemonic::Tya),
+            Token::Bra => Ok(Mnemonic::Bra),
+            Token::Phx => Ok(Mnemonic::Phx),
+            Token::Phy => Ok(Mnemonic::Phy),
+            Token::Plx => Ok(Mnemonic::Plx),
+            Token::Ply => Ok(Mnemonic::Ply),
+            Token::Stz => Ok(Mnemonic::Stz),
             Token::Dfb => Ok(Mnemonic::Dfb),
             Token::Dfw => Ok(Mnemonic::Dfw),
             Token::Equ => Ok(Mnemonic::Equ),
+            Token::Inb => Ok(Mnemonic::Inb),
             Token::Inl => Ok(Mnemonic::Inl),
             Token::Hlt => Ok(Mnemonic::Hlt),
             Token::Org => Ok(Mnemonic::Org),
             Token::Sct => Ok(Mnemonic::Sct),
+            Token::Sctdef => Ok(Mnemonic::Sctdef),
+            Token::Ds => Ok(Mnemonic::Ds),
+            Token::Opcode => Ok(Mnemonic::Opcode),
+            Token::Align => Ok(Mnemonic::Align),
+            Token::Rept => Ok(Mnemonic::Rept),
+            Token::Endr => Ok(Mnemonic::Endr),
+            Token::Dfs => Ok(Mnemonic::Dfs),
+            Token::If => Ok(Mnemonic::If),
+            Token::Else => Ok(Mnemonic::Else),
+            Token::Endif => Ok(Mnemonic::Endif),
+            Token::Datestamp => Ok(Mnemonic::Datestamp),
+            Token::Fillbyte => Ok(Mnemonic::Fillbyte),
+            Token::Entry => Ok(Mnemonic::Entry),
             _ => Err(()),
         }
     }
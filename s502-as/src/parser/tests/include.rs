@@ -46,7 +46,8 @@ fn safe_include() {
                                     Action::Label(Spanned::new((
                                         Label::Top(TopLabel {
                                             name: String::from("mylabel"),
-                                            visibility: Visibility::Object
+                                            visibility: Visibility::Object,
+                                            address: None,
                                         }),
                                         0..7
                                     ))),
@@ -190,3 +191,64 @@ fn file_dne_include() {
     let error = errors.get(0).unwrap();
     assert!(error.message.contains("No such file or directory"));
 }
+
+#[test]
+fn recursive_include_chain() {
+    let source = "inl \"a.65a\"".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    // `a.65a` included `b.65a`, which included `c.65a`.
+    let mut include_stack = vec![
+        Include {
+            included: "a.65a".to_string(),
+            loc: Location {
+                span: 0..1,
+                file_name: "<test harness>".to_string(),
+            },
+        },
+        Include {
+            included: "b.65a".to_string(),
+            loc: Location {
+                span: 4..11,
+                file_name: "a.65a".to_string(),
+            },
+        },
+        Include {
+            included: "c.65a".to_string(),
+            loc: Location {
+                span: 4..11,
+                file_name: "b.65a".to_string(),
+            },
+        },
+    ];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        "c.65a".to_string(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    let (_, included_result) = parser_context.handle_include(String::from("a.65a"), 4..11);
+
+    let errors = included_result.unwrap_err();
+    let error = errors.first().unwrap();
+    assert_eq!(error.message, String::from("Recursive include found"));
+    // Each file in the cycle is pointed to.
+    let labels = error
+        .labels
+        .iter()
+        .map(|(location, message)| (location.file_name.as_str(), message.as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        vec![
+            ("c.65a", Some("Could not include \"a.65a\"")),
+            ("<test harness>", Some("Given in assembler invocatiion")),
+            ("a.65a", Some("Included here")),
+            ("b.65a", Some("Included here")),
+        ]
+    );
+    assert!(error.help.is_some());
+}
@@ -33,6 +33,7 @@ fn plain() {
         Label::Top(TopLabel {
             name: "mylabel".to_string(),
             visibility: Visibility::Object,
+            address: None,
         })
     );
 }
@@ -67,6 +68,7 @@ fn global() {
         Label::Top(TopLabel {
             name: "yourlabel".to_string(),
             visibility: Visibility::Global,
+            address: None,
         })
     );
 }
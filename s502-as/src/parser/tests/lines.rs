@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use codespan_reporting::files::SimpleFiles;
 
-use crate::{ast::*, parser::ParserContext};
+use crate::{ast::*, error::AssemblerError, parser::ParserContext};
 
 #[test]
 fn empty_line() {
@@ -62,6 +62,7 @@ fn label_line() {
                 Label::Top(TopLabel {
                     name: "mylabel".to_string(),
                     visibility: Visibility::Object,
+                    address: None,
                 }),
                 0..7
             ))),
@@ -115,3 +116,107 @@ fn instruction_line() {
         ]
     );
 }
+
+#[test]
+fn unrecognized_line() {
+    let source = "  ?? nop".to_string();
+    let source_name = "unrecognized line test".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut include_stack = vec![Include {
+        included: source_name.clone(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<test harness>".to_string(),
+        },
+    }];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        source_name.clone(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    let line = parser_context.parse_line();
+    assert_eq!(
+        line,
+        Err(AssemblerError {
+            message: "Unrecognized characters `??`".to_string(),
+            labels: vec![(
+                Location {
+                    span: 2..4,
+                    file_name: source_name.clone(),
+                },
+                None,
+            )],
+            help: None,
+        })
+    );
+}
+
+#[test]
+fn repeat_lines() {
+    let source = "rept 2\nnop\nendr\n".to_string();
+    let source_name = "repeat lines test".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut include_stack = vec![Include {
+        included: source_name.clone(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<test harness>".to_string(),
+        },
+    }];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        source_name.clone(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    // The block is parsed along with the line that starts it.
+    let line = parser_context.parse_line();
+    assert!(line.is_ok());
+    assert_eq!(
+        parser_context.program,
+        vec![
+            Action::Instruction(Spanned::new((
+                Instruction {
+                    mnemonic: Spanned::new((Mnemonic::Rept, 0..4)),
+                    operand: Some(Spanned::new((
+                        Operand {
+                            mode: OperandMode::Address,
+                            modifier: None,
+                            value: Spanned::new((
+                                Value::Repeat((
+                                    Box::new(Spanned::new((Value::Byte(2), 5..6))),
+                                    vec![
+                                        Action::LineStart(7),
+                                        Action::Instruction(Spanned::new((
+                                            Instruction {
+                                                mnemonic: Spanned::new((Mnemonic::Nop, 7..10)),
+                                                operand: None,
+                                            },
+                                            7..10
+                                        ))),
+                                        Action::LineEnd(11),
+                                    ]
+                                )),
+                                5..6
+                            ))
+                        },
+                        5..6
+                    )))
+                },
+                0..6
+            ))),
+            Action::LineEnd(7),
+            Action::LineStart(11),
+            Action::LineEnd(16),
+        ]
+    );
+}
@@ -1,3 +1,5 @@
+// These tests predate clippy's get_first lint.
+#[allow(clippy::get_first)]
 mod include;
 mod labels;
 mod lines;
@@ -292,6 +292,57 @@ fn immediate() {
     );
 }
 
+#[test]
+fn immediate_expression() {
+    let source = "#(1+2*3)".to_string();
+    let source_name = "immediate expression operand test".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut include_stack = vec![Include {
+        included: source_name.clone(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<test harness>".to_string(),
+        },
+    }];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        source_name.clone(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    // Multiplication is applied first.
+    let operand = parser_context.parse_operand();
+    assert_eq!(
+        operand,
+        Ok(Some(Spanned::new((
+            Operand {
+                mode: OperandMode::Immediate,
+                modifier: None,
+                value: Spanned::new((
+                    Value::Expr((
+                        Box::new(Spanned::new((Value::Byte(1), 2..3))),
+                        Operator::Add,
+                        Box::new(Spanned::new((
+                            Value::Expr((
+                                Box::new(Spanned::new((Value::Byte(2), 4..5))),
+                                Operator::Multiply,
+                                Box::new(Spanned::new((Value::Byte(3), 6..7))),
+                            )),
+                            4..7
+                        ))),
+                    )),
+                    1..8
+                ))
+            },
+            0..8
+        ))))
+    );
+}
+
 #[test]
 fn low_indirect() {
     let source = "(>$1000)".to_string();
@@ -436,6 +487,101 @@ fn x_indexed() {
     );
 }
 
+#[test]
+fn table_index() {
+    let source = "table[3],x".to_string();
+    let source_name = "table index operand test".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut include_stack = vec![Include {
+        included: source_name.clone(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<test harness>".to_string(),
+        },
+    }];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        source_name.clone(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    let operand = parser_context.parse_operand();
+    assert_eq!(
+        operand,
+        Ok(Some(Spanned::new((
+            Operand {
+                mode: OperandMode::XIndexed,
+                modifier: None,
+                value: Spanned::new((Value::Indexed((String::from("table"), 3)), 0..8))
+            },
+            0..10
+        ))))
+    );
+}
+
+#[test]
+fn data_list() {
+    let source = "$01, <label".to_string();
+    let source_name = "data list operand test".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut include_stack = vec![Include {
+        included: source_name.clone(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<test harness>".to_string(),
+        },
+    }];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        source_name.clone(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    let operand = parser_context.parse_data_operand();
+    assert_eq!(
+        operand,
+        Ok(Some(Spanned::new((
+            Operand {
+                mode: OperandMode::Address,
+                modifier: None,
+                value: Spanned::new((
+                    Value::List(vec![
+                        Spanned::new((
+                            Operand {
+                                mode: OperandMode::Address,
+                                modifier: None,
+                                value: Spanned::new((Value::Byte(1), 0..3))
+                            },
+                            0..3
+                        )),
+                        Spanned::new((
+                            Operand {
+                                mode: OperandMode::Address,
+                                modifier: Some(Spanned::new((Modifier::HighByte, 5..6))),
+                                value: Spanned::new((
+                                    Value::Reference(String::from("label")),
+                                    6..11
+                                ))
+                            },
+                            5..11
+                        )),
+                    ]),
+                    0..11
+                ))
+            },
+            0..11
+        ))))
+    );
+}
+
 #[test]
 fn y_indexed() {
     let source = "$2526,y".to_string();
@@ -741,3 +887,163 @@ fn address_eol() {
         ))))
     );
 }
+
+#[test]
+fn coerced() {
+    let source = "byte(FOO),x".to_string();
+    let source_name = "coerced operand test".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut include_stack = vec![Include {
+        included: source_name.clone(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<test harness>".to_string(),
+        },
+    }];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        source_name.clone(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    let operand = parser_context.parse_operand();
+    assert_eq!(
+        operand,
+        Ok(Some(Spanned::new((
+            Operand {
+                mode: OperandMode::XIndexed,
+                modifier: None,
+                value: Spanned::new((
+                    Value::Coerced((
+                        Width::Byte,
+                        Box::new(Spanned::new((Value::Reference("FOO".to_string()), 5..8)))
+                    )),
+                    0..9
+                ))
+            },
+            0..11
+        ))))
+    );
+}
+
+#[test]
+fn character() {
+    let source = "#'A'".to_string();
+    let source_name = "character operand test".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut include_stack = vec![Include {
+        included: source_name.clone(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<test harness>".to_string(),
+        },
+    }];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        source_name.clone(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    let operand = parser_context.parse_operand();
+    assert_eq!(
+        operand,
+        Ok(Some(Spanned::new((
+            Operand {
+                mode: OperandMode::Immediate,
+                modifier: None,
+                value: Spanned::new((Value::Byte(0xc1), 1..4))
+            },
+            0..4
+        ))))
+    );
+}
+
+#[test]
+fn character_too_long() {
+    let source = "#'AB'".to_string();
+    let source_name = "long character operand test".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut include_stack = vec![Include {
+        included: source_name.clone(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<test harness>".to_string(),
+        },
+    }];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        source_name.clone(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    let operand = parser_context.parse_operand();
+    assert_eq!(
+        operand,
+        Err(AssemblerError {
+            message: String::from("Character literal `'AB'` is not a single character"),
+            labels: vec![(
+                Location {
+                    span: 1..5,
+                    file_name: String::from("long character operand test"),
+                },
+                None
+            )],
+            help: Some(String::from(
+                "Use a string in double quotes for more than one character"
+            ))
+        })
+    );
+}
+
+#[test]
+fn invalid_hex_digit() {
+    let source = "#$1G0".to_string();
+    let source_name = "invalid hex digit operand test".to_string();
+    let mut files = SimpleFiles::<String, String>::new();
+    let mut include_stack = vec![Include {
+        included: source_name.clone(),
+        loc: Location {
+            span: 0..1,
+            file_name: "<test harness>".to_string(),
+        },
+    }];
+    let mut id_table = HashMap::<String, usize>::new();
+
+    let mut parser_context = ParserContext::new(
+        source_name.clone(),
+        &source,
+        &mut files,
+        &mut include_stack,
+        &mut id_table,
+    );
+
+    let operand = parser_context.parse_operand();
+    assert_eq!(
+        operand,
+        Err(AssemblerError {
+            message: String::from("`G` is not a valid hexadecimal digit"),
+            labels: vec![(
+                Location {
+                    span: 3..4,
+                    file_name: String::from("invalid hex digit operand test"),
+                },
+                None
+            )],
+            help: Some(String::from(
+                "The hexadecimal digits are `0` to `9` and `a` to `f` in either case"
+            ))
+        })
+    );
+}
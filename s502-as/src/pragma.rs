@@ -0,0 +1,232 @@
+//! Pragmas at the start of a source that set options for assembling it, so a
+//! file can describe how it's built without command line flags.
+//!
+//! Each pragma is a comment line of the form
+//!
+//! ```text
+//! *pragma setting value
+//! ```
+//!
+//! where the setting is `cpu`, `org`, or `format` and the value is written the
+//! same way as for the option of the same name. Only the lines before the first
+//! line that isn't a pragma or blank are read, and an option given on the
+//! command line takes precedence over a pragma.
+
+use std::ops::Range;
+
+use crate::{
+    ast::{Cpu, Location},
+    error::AssemblerError,
+    generation::binary::OutputFormat,
+    parser::lexer::parse_number,
+};
+
+/// The options set by the pragmas of a source.
+#[derive(Debug, Default, PartialEq)]
+pub struct Pragmas {
+    /// The processor to assemble for.
+    pub cpu: Option<Cpu>,
+    /// The address that code starts at in binary mode.
+    pub origin: Option<u16>,
+    /// The format of the output binary.
+    pub format: Option<OutputFormat>,
+}
+
+/// Parse the pragmas at the start of a source.
+pub fn parse_pragmas(file_name: &str, source: &str) -> Result<Pragmas, Vec<AssemblerError>> {
+    let mut pragmas = Pragmas::default();
+    // Where each setting was given, to point out one that's given twice.
+    let mut given: Vec<(&str, Location)> = Vec::new();
+    let mut errors = Vec::new();
+    let mut line_start = 0;
+
+    for line in source.split_inclusive('\n') {
+        let words = words(line, line_start);
+        line_start += line.len();
+
+        let (setting, value, extra) = match words.as_slice() {
+            [] => continue,
+            [(pragma, _), rest @ ..] if pragma.eq_ignore_ascii_case("*pragma") => {
+                (rest.first(), rest.get(1), rest.get(2))
+            }
+            // The pragmas have ended.
+            _ => break,
+        };
+        let location = |span: &Range<usize>| Location {
+            span: span.clone(),
+            file_name: file_name.to_string(),
+        };
+        let line_location = location(&(words[0].1.start..words.last().unwrap().1.end));
+
+        let (setting, setting_span) = match setting {
+            Some((setting, span)) => (setting.to_ascii_lowercase(), span),
+            None => {
+                errors.push(AssemblerError {
+                    message: String::from("Expected a setting after `*pragma`"),
+                    labels: vec![(line_location, None)],
+                    help: Some(String::from("The settings are `cpu`, `org`, and `format`")),
+                });
+                continue;
+            }
+        };
+        let setting = match setting.as_str() {
+            "cpu" => "cpu",
+            "org" => "org",
+            "format" => "format",
+            _ => {
+                errors.push(AssemblerError {
+                    message: format!("Unknown pragma `{}`", setting),
+                    labels: vec![(location(setting_span), None)],
+                    help: Some(String::from("The settings are `cpu`, `org`, and `format`")),
+                });
+                continue;
+            }
+        };
+
+        let (value, value_span) = match value {
+            Some(value) => value,
+            None => {
+                errors.push(AssemblerError {
+                    message: format!("Expected a value for the `{}` pragma", setting),
+                    labels: vec![(line_location, None)],
+                    help: None,
+                });
+                continue;
+            }
+        };
+        if let Some((extra, extra_span)) = extra {
+            errors.push(AssemblerError {
+                message: format!("Unexpected `{}` after the value of the pragma", extra),
+                labels: vec![(location(extra_span), None)],
+                help: Some(String::from("Each pragma sets one value")),
+            });
+            continue;
+        }
+
+        if let Some((_, first_location)) = given.iter().find(|(name, _)| *name == setting) {
+            errors.push(AssemblerError {
+                message: format!("The `{}` pragma is given more than once", setting),
+                labels: vec![
+                    (line_location, None),
+                    (
+                        first_location.clone(),
+                        Some(String::from("First given here")),
+                    ),
+                ],
+                help: None,
+            });
+            continue;
+        }
+
+        let invalid_value = |what: &str, help: &str| AssemblerError {
+            message: format!("Invalid {} `{}` in pragma", what, value),
+            labels: vec![(location(value_span), None)],
+            help: Some(help.to_string()),
+        };
+        match setting {
+            "cpu" => match Cpu::from_name(value) {
+                Some(cpu) => pragmas.cpu = Some(cpu),
+                None => {
+                    errors.push(invalid_value("CPU", "The CPU is `6502` or `65c02`"));
+                    continue;
+                }
+            },
+            "org" => match parse_number(value) {
+                Some(origin) => pragmas.origin = Some(origin),
+                None => {
+                    errors.push(invalid_value(
+                        "address",
+                        "The address is written as a number, like `$2000`",
+                    ));
+                    continue;
+                }
+            },
+            _ => match OutputFormat::from_name(value) {
+                Some(format) => pragmas.format = Some(format),
+                None => {
+                    errors.push(invalid_value(
+                        "format",
+                        "The formats are `bin`, `rle`, `ihex`, `srec`, `basic-poke`, and `flat64k`",
+                    ));
+                    continue;
+                }
+            },
+        }
+        given.push((setting, line_location));
+    }
+
+    if !errors.is_empty() {
+        Err(errors)
+    } else {
+        Ok(pragmas)
+    }
+}
+
+/// The words of a line separated by whitespace, with their spans in the source.
+fn words(line: &str, line_start: usize) -> Vec<(&str, Range<usize>)> {
+    let mut words = Vec::new();
+    let mut word_start = None;
+
+    for (index, character) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (word_start, character.is_whitespace()) {
+            (None, false) => word_start = Some(index),
+            (Some(start), true) => {
+                words.push((&line[start..index], line_start + start..line_start + index));
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pragmas() {
+        let pragmas = parse_pragmas(
+            "game.65a",
+            "*pragma cpu 65c02\n\n*PRAGMA org $2000\n*pragma format ihex\n    bra end\n*pragma org $3000\n",
+        )
+        .unwrap();
+
+        // The pragma after the code is a comment.
+        assert_eq!(
+            pragmas,
+            Pragmas {
+                cpu: Some(Cpu::Cmos65c02),
+                origin: Some(0x2000),
+                format: Some(OutputFormat::IntelHex),
+            }
+        );
+    }
+
+    /// Each malformed pragma is reported at the word that is wrong.
+    #[test]
+    fn test_parse_pragmas_errors() {
+        let errors = parse_pragmas(
+            "game.65a",
+            "*pragma speed 2\n*pragma cpu\n*pragma cpu z80\n*pragma org $2000 $10\n*pragma org $2000\n*pragma org $3000\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(errors.len(), 5);
+        assert_eq!(errors[0].message, "Unknown pragma `speed`");
+        assert_eq!(errors[0].labels[0].0.span, 8..13);
+        assert_eq!(errors[1].message, "Expected a value for the `cpu` pragma");
+        assert_eq!(errors[2].message, "Invalid CPU `z80` in pragma");
+        assert_eq!(errors[2].labels[0].0.span, 40..43);
+        assert_eq!(
+            errors[3].message,
+            "Unexpected `$10` after the value of the pragma"
+        );
+        assert_eq!(
+            errors[4].message,
+            "The `org` pragma is given more than once"
+        );
+        assert_eq!(errors[4].labels[1].0.span, 66..83);
+    }
+}
@@ -0,0 +1,132 @@
+//! The layout script that places sections in memory.
+//!
+//! Each line of a script places one section and has the form
+//!
+//! ```text
+//! "name" address [fill]
+//! ```
+//!
+//! where `address` is the address the section starts at and `fill` is an optional
+//! byte used to pad the gap between the end of the section and whatever follows
+//! it in the output. Numbers are written the same way as in assembly source and
+//! `*` starts a comment.
+
+use crate::{
+    error::AssemblerError,
+    lines::{parse_lines, LineFormat},
+    parser::lexer::{Literal, Token},
+};
+
+/// Where a section is placed in memory.
+#[derive(Debug, PartialEq)]
+pub struct SectionPlacement {
+    /// The name of the section given to the `sct` directive.
+    pub name: String,
+    /// The address that the section starts at.
+    pub address: usize,
+    /// The byte that pads the space after the section.
+    pub fill: u8,
+}
+
+const FORMAT: LineFormat = LineFormat {
+    name: "layout script",
+    help: "Each line of a layout script is expected to be `\"name\" address [fill]`",
+    duplicate: |name| format!("Section `{}` is placed more than once", name),
+    first: "First placed here",
+};
+
+/// Parse a layout script, returning the placements in the order they are written.
+pub fn parse_script(
+    file_name: &str,
+    source: &str,
+) -> Result<Vec<SectionPlacement>, Vec<AssemblerError>> {
+    parse_lines(file_name, source, &FORMAT, |line| {
+        let name = match line.next() {
+            Some((Token::Literal(Literal::String(name)), _)) => name,
+            Some((token, span)) => return Err(line.unexpected(token, span, "a section name")),
+            None => unreachable!("Empty lines are skipped"),
+        };
+
+        let address = match line.next() {
+            Some((Token::Literal(Literal::Byte(byte)), _)) => byte as usize,
+            Some((Token::Literal(Literal::Word(word)), _)) => word as usize,
+            Some((token, span)) => return Err(line.unexpected(token, span, "an address")),
+            None => {
+                return Err(AssemblerError {
+                    message: format!("Missing address for section `{}`", name),
+                    labels: vec![(line.location.clone(), None)],
+                    help: None,
+                })
+            }
+        };
+
+        let fill = match line.next() {
+            Some((Token::Literal(Literal::Byte(byte)), _)) => byte,
+            Some((token, span)) => return Err(line.unexpected(token, span, "a fill byte")),
+            None => 0,
+        };
+
+        Ok((
+            name.clone(),
+            SectionPlacement {
+                name,
+                address,
+                fill,
+            },
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sections are placed in order with a default fill of 0.
+    #[test]
+    fn test_parse_script() {
+        let placements = parse_script(
+            "layout.cfg",
+            "* The program.\n\"code\" $8000\n\n\"data\" $9000 $ff\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            placements,
+            vec![
+                SectionPlacement {
+                    name: "code".to_string(),
+                    address: 0x8000,
+                    fill: 0,
+                },
+                SectionPlacement {
+                    name: "data".to_string(),
+                    address: 0x9000,
+                    fill: 0xff,
+                },
+            ]
+        );
+    }
+
+    /// Each malformed line is reported at the token that is wrong.
+    #[test]
+    fn test_parse_script_errors() {
+        let errors = parse_script(
+            "layout.cfg",
+            "code $8000\n\"data\"\n\"data\" $9000 $ff $1\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            errors[0].message,
+            "Expected a section name in layout script"
+        );
+        assert_eq!(errors[0].labels[0].0.span, 0..4);
+        assert_eq!(errors[1].message, "Missing address for section `data`");
+        assert_eq!(
+            errors[2].message,
+            "Expected the end of the line in layout script"
+        );
+        assert_eq!(errors[2].labels[0].0.span, 35..37);
+    }
+}
@@ -4,6 +4,10 @@ fn cleanup(test_name: &'static str) {
     let _ = fs::remove_file(format!("test_input/{}.65a", test_name));
     let _ = fs::remove_file(format!("test_input/{}_listing.txt", test_name));
     let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+    let _ = fs::remove_file(format!("test_input/{}.65o", test_name));
+    let _ = fs::remove_file(format!("test_input/{}.hex", test_name));
+    let _ = fs::remove_file(format!("test_input/{}.s19", test_name));
+    let _ = fs::remove_file(format!("test_input/{}.bas", test_name));
 }
 
 #[test]
@@ -17,7 +21,7 @@ fn byte_macro() {
             mac1 equ $1
             mac2 equ $2
             mac3 equ <$3040
-            mac4 equ >mac3
+            mac4 equ mac3
 
             adc mac1
             adc #mac2
@@ -240,3 +244,2511 @@ fn include() {
     cleanup("include_0");
     cleanup("include_1");
 }
+
+#[test]
+fn include_invalid_utf8() {
+    let test_name = "include_invalid_utf8";
+
+    assert!(fs::write(
+        format!("test_input/{}_0.65a", test_name),
+        indoc::formatdoc! {
+            "
+            inl \"test_input/{}_1.65a\"
+            ", test_name
+        },
+    )
+    .is_ok());
+
+    // A latin-1 encoded `é` in a comment.
+    assert!(fs::write(
+        format!("test_input/{}_1.65a", test_name),
+        b"    nop * caf\xe9\n",
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}_0.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+        "Could not include \"test_input/{}_1.65a\": invalid UTF-8 at byte offset 13",
+        test_name
+    )));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    cleanup("include_invalid_utf8_0");
+    cleanup("include_invalid_utf8_1");
+}
+
+#[test]
+fn positive_sign() {
+    let test_name = "positive_sign";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                dfb +5
+                dfw +$1234
+                lda #+10
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0x05, 0x34, 0x12, 0xa9, 0x0a]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn script_sections() {
+    let test_name = "script_sections";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                    sct \"code\"
+                start
+                    jmp data
+
+                    sct \"data\"
+                data
+                    dfw start
+            "
+        },
+    )
+    .is_ok());
+
+    assert!(fs::write(
+        format!("test_input/{}.cfg", test_name),
+        indoc::formatdoc! {
+            "
+            * Pad the code with nops up to the data.
+            \"code\" $1000 $ea
+            \"data\" $1008
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg("--script")
+        .arg(format!("test_input/{}.cfg", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0x4c, 0x08, 0x10, 0xea, 0xea, 0xea, 0xea, 0xea, 0x00, 0x10]
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}.cfg", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn overlapping_sections() {
+    let test_name = "overlapping_sections";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sct \"code\"
+                dfw $0102
+                dfw $0304
+
+                sct \"data\"
+                dfb $05
+            "
+        },
+    )
+    .is_ok());
+
+    assert!(fs::write(
+        format!("test_input/{}.cfg", test_name),
+        indoc::formatdoc! {
+            "
+            \"code\" $1000
+            \"data\" $1002
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--script")
+        .arg(format!("test_input/{}.cfg", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Sections `code` and `data` overlap at $1002-$1002"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let _ = fs::remove_file(format!("test_input/{}.cfg", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn macro_section_name() {
+    let test_name = "macro_section_name";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            SEG equ \"code\"
+                sct SEG
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    assert!(fs::write(format!("test_input/{}.cfg", test_name), "\"code\" $1000\n",).is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--script")
+        .arg(format!("test_input/{}.cfg", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xea]
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}.cfg", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn macro_section_name_not_string() {
+    let test_name = "macro_section_name_not_string";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            SEG equ $10
+                sct SEG
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("The `sct` directive expects a string operand"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    cleanup(test_name);
+}
+
+#[test]
+fn strict_sections() {
+    let test_name = "strict_sections";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sctdef \"code\"
+                sctdef \"data\"
+                sct \"code\"
+                nop
+                sct \"dtaa\"
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("--strict-sections")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Section `dtaa` has not been declared"));
+    assert!(stderr.contains("Did you mean `data`?"));
+    assert!(!stderr.contains("`code`"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // Without strict sections the misspelled name is a new section.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+
+    cleanup(test_name);
+}
+
+#[test]
+fn empty_section() {
+    let test_name = "empty_section";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sct \"code\"
+                nop
+                sct \"cdoe\"
+                sct \"code\"
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Section `cdoe` is declared but never used"));
+    assert!(!stderr.contains("Section `code`"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn output_name() {
+    let test_name = "output_name";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            nop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-o")
+        .arg(format!("test_input/{}_out", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}_out.bin", test_name)).unwrap(),
+        vec![0xea]
+    );
+    assert!(fs::metadata(format!("test_input/{}_out.65a", test_name)).is_err());
+
+    cleanup(test_name);
+    cleanup("output_name_out");
+}
+
+#[test]
+fn origin_argument() {
+    let test_name = "origin_argument";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            start
+                nop
+                jmp start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg("--org")
+        .arg("$2000")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xea, 0x4c, 0x00, 0x20]
+    );
+    assert!(
+        fs::read_to_string(format!("test_input/{}_listing.txt", test_name))
+            .unwrap()
+            .contains("2001 4c 00 20     jmp start")
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn rle_format() {
+    let test_name = "rle_format";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0800
+                dfb $01
+                dfb $02
+                dfb $00
+                dfb $00
+                dfb $00
+                dfb $00
+                dfb $03
+                dfw $0404
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--format")
+        .arg("rle")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let encoded = fs::read(format!("test_input/{}.bin", test_name)).unwrap();
+    assert_eq!(&encoded[..2], &[0x00, 0x08]);
+
+    // Decode it the way the 6502 routine would.
+    let mut decoded = vec![];
+    let mut index = 2;
+    loop {
+        let control = encoded[index] as usize;
+        index += 1;
+        match control {
+            0 => break,
+            1..=0x7f => {
+                decoded.extend_from_slice(&encoded[index..index + control]);
+                index += control;
+            }
+            _ => {
+                decoded.resize(decoded.len() + control - 0x7e, encoded[index]);
+                index += 1;
+            }
+        }
+    }
+    assert_eq!(index, encoded.len());
+    assert_eq!(
+        decoded,
+        vec![0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x03, 0x04, 0x04]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn multiple_formats() {
+    let test_name = "multiple_formats";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0800
+                dfw $0100
+                dfw $0302
+                dfw $0504
+                dfw $0706
+                dfw $0908
+                dfw $0b0a
+                dfw $0d0c
+                dfw $0f0e
+                dfw $1110
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--format")
+        .arg("bin")
+        .arg("--format")
+        .arg("ihex")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let binary = fs::read(format!("test_input/{}.bin", test_name)).unwrap();
+    assert_eq!(binary, (0x00..=0x11).collect::<Vec<u8>>());
+
+    let hex = fs::read_to_string(format!("test_input/{}.hex", test_name)).unwrap();
+    assert_eq!(
+        hex,
+        indoc::indoc! {"
+            :10080000000102030405060708090A0B0C0D0E0F70
+            :020810001011C5
+            :00000001FF
+        "}
+    );
+
+    // The data records hold the same bytes as the binary.
+    let mut decoded = vec![];
+    for record in hex.lines() {
+        let bytes = (1..record.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&record[index..index + 2], 16).unwrap())
+            .collect::<Vec<u8>>();
+        assert_eq!(
+            bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)),
+            0
+        );
+        if bytes[3] == 0 {
+            decoded.extend_from_slice(&bytes[4..bytes.len() - 1]);
+        }
+    }
+    assert_eq!(decoded, binary);
+
+    cleanup(test_name);
+}
+
+#[test]
+fn srec_format() {
+    let test_name = "srec_format";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0800
+                lda #$01
+                sta $0400
+                rts
+                org $0900
+                dfb $ea
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--format")
+        .arg("srec")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // The gap between the two origins is left out.
+    let srec = fs::read_to_string(format!("test_input/{}.s19", test_name)).unwrap();
+    assert_eq!(
+        srec,
+        indoc::indoc! {"
+            S00E0000737265635F666F726D61745C
+            S1090800A9018D00046053
+            S1040900EA08
+            S9030800F4
+        "}
+    );
+
+    // Each record's count covers the rest of it, and its checksum makes the sum $ff.
+    for record in srec.lines() {
+        let bytes = (2..record.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&record[index..index + 2], 16).unwrap())
+            .collect::<Vec<u8>>();
+        assert_eq!(bytes[0] as usize, bytes.len() - 1);
+        assert_eq!(
+            bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)),
+            0xff
+        );
+    }
+
+    cleanup(test_name);
+}
+
+#[test]
+fn basic_poke_format() {
+    let test_name = "basic_poke_format";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0300
+                lda #$01
+                sta $0400
+                rts
+                org $0320
+                dfs 20, $ea
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--format")
+        .arg("basic-poke")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let program = fs::read_to_string(format!("test_input/{}.bas", test_name)).unwrap();
+    assert_eq!(
+        program,
+        indoc::indoc! {"
+            10 FOR A=768 TO 773:READ B:POKE A,B:NEXT
+            20 FOR A=800 TO 819:READ B:POKE A,B:NEXT
+            30 END
+            40 DATA 169,1,141,0,4,96
+            50 DATA 234,234,234,234,234,234,234,234,234,234,234,234,234,234,234,234
+            60 DATA 234,234,234,234
+        "}
+    );
+
+    // Running the loops over the data puts the bytes back where they were assembled.
+    let mut ranges = Vec::new();
+    let mut data = Vec::new();
+    for line in program.lines() {
+        let (_, statement) = line.split_once(' ').unwrap();
+        if let Some(values) = statement.strip_prefix("DATA ") {
+            data.extend(values.split(',').map(|value| value.parse::<u8>().unwrap()));
+        } else if let Some(range) = statement.strip_prefix("FOR A=") {
+            let (start, rest) = range.split_once(" TO ").unwrap();
+            let (end, _) = rest.split_once(':').unwrap();
+            ranges.push(start.parse::<usize>().unwrap()..=end.parse::<usize>().unwrap());
+        }
+    }
+    let mut memory = vec![0u8; 0x10000];
+    let mut data = data.into_iter();
+    for range in ranges {
+        for address in range {
+            memory[address] = data.next().unwrap();
+        }
+    }
+    assert!(data.next().is_none());
+
+    assert_eq!(&memory[0x300..0x306], &[0xa9, 0x01, 0x8d, 0x00, 0x04, 0x60]);
+    assert_eq!(&memory[0x306..0x320], &[0; 0x1a]);
+    assert_eq!(&memory[0x320..0x334], &[0xea; 20]);
+
+    cleanup(test_name);
+}
+
+#[test]
+fn flat64k_format() {
+    let test_name = "flat64k_format";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $c000
+                lda #$01
+                org $fffc
+                dfw $c000
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--format")
+        .arg("flat64k")
+        .arg("--fill")
+        .arg("$ff")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // The code is at its own addresses instead of the start of the file.
+    let image = fs::read(format!("test_input/{}.img", test_name)).unwrap();
+    assert_eq!(image.len(), 0x10000);
+    assert_eq!(image[0xc000..0xc002], [0xa9, 0x01]);
+    assert_eq!(image[0xfffc..], [0x00, 0xc0, 0xff, 0xff]);
+    assert!(image[..0xc000].iter().all(|byte| *byte == 0xff));
+    assert!(image[0xc002..0xfffc].iter().all(|byte| *byte == 0xff));
+
+    let _ = fs::remove_file(format!("test_input/{}.img", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn expect_hash() {
+    let test_name = "expect_hash";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                lda #$01
+                rts
+            "
+        },
+    )
+    .is_ok());
+
+    for hash in ["fnv1a64:5581551a656a2ccd", "5581551a656a2ccd"] {
+        let output = test_bin::get_test_bin("s502-as")
+            .arg("-b")
+            .arg("--expect-hash")
+            .arg(hash)
+            .arg(format!("test_input/{}.65a", test_name))
+            .output()
+            .expect("failed to start s502-as");
+
+        assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+        assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+    }
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--expect-hash")
+        .arg("0123456789abcdef")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("The hash of the binary doesn't match the expected hash"));
+    assert!(stderr.contains("expected fnv1a64:0123456789abcdef"));
+    assert!(stderr.contains("got fnv1a64:5581551a656a2ccd"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn datestamp() {
+    let test_name = "datestamp";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                datestamp
+                datestamp \"bcd\"
+                rts
+            "
+        },
+    )
+    .is_ok());
+
+    for deterministic in [false, true] {
+        let mut command = test_bin::get_test_bin("s502-as");
+        command.arg("-b");
+        if deterministic {
+            command.arg("--deterministic");
+        }
+        let output = command
+            .arg(format!("test_input/{}.65a", test_name))
+            .output()
+            .expect("failed to start s502-as");
+
+        assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+        assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+        // Ten ASCII bytes of `yyyy-mm-dd`, then four BCD bytes.
+        let binary = fs::read(format!("test_input/{}.bin", test_name)).unwrap();
+        assert_eq!(binary.len(), 15);
+        assert_eq!(binary[14], 0x60);
+        if deterministic {
+            assert!(binary[..14].iter().all(|byte| *byte == 0));
+        } else {
+            assert_eq!(binary[4], b'-');
+            assert_eq!(binary[7], b'-');
+            assert!(binary[..4].iter().all(|byte| byte.is_ascii_digit()));
+            // The century is the same in both formats.
+            assert_eq!(binary[10], ((binary[0] - b'0') << 4) | (binary[1] - b'0'));
+        }
+    }
+
+    cleanup(test_name);
+}
+
+#[test]
+fn overwrite() {
+    let test_name = "overwrite";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0800
+                lda #$01
+                sta $0400
+                rts
+                org $0802
+                nop
+                nop
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    // One warning for the whole block, pointing at where it starts and what it overwrites.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr
+            .matches("Code overwrites bytes already assembled at $0802 [-Woverwrite]")
+            .count(),
+        1
+    );
+    assert!(stderr.contains(&format!("test_input/{}.65a:6:1", test_name)));
+    assert!(stderr.contains("First written here"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // The later code still wins.
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa9, 0x01, 0xea, 0xea, 0xea, 0x60]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn rom_range() {
+    let test_name = "rom_range";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            rom equ $e800
+                sta $F000
+                inc rom,x
+                lda $f000
+                sta $0400
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--rom-range")
+        .arg("$E000-$FFFF")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    // Reads and writes outside of ROM are fine.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("[-Wrom-write]").count(), 2);
+    assert!(stderr.contains("`sta` writes to ROM at `$f000`"));
+    assert!(stderr.contains("`inc` writes to ROM at `$e800`"));
+    assert!(stderr.contains("$e000-$ffff was declared ROM with --rom-range"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // Nothing is ROM without it.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+
+    cleanup(test_name);
+}
+
+#[test]
+fn warning_categories() {
+    let test_name = "warning_categories";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sct \"code\"
+                jmp ($10ff)
+                sct \"unused\"
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-Wno-jmp-indirect-bug")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("jmp ($10ff)"));
+    assert!(stderr.contains("Section `unused` is declared but never used [-Wempty-section]"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-Werror=jmp-indirect-bug")
+        .arg("-Wno-empty-section")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error"));
+    assert!(stderr.contains("[-Werror=jmp-indirect-bug]"));
+    assert!(!stderr.contains("unused"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    cleanup(test_name);
+}
+
+#[test]
+fn pedantic() {
+    let test_name = "pedantic";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            unused equ $10
+                sct \"code\"
+                tax
+                txa
+                jmp ($10ff)
+                sct \"empty\"
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("--pedantic")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[-Wunused-macro]"));
+    assert!(stderr.contains("[-Wredundant]"));
+    assert!(stderr.contains("[-Wjmp-indirect-bug]"));
+    assert!(stderr.contains("[-Wempty-section]"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // `-W` after it still turns categories off or makes them errors.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("--pedantic")
+        .arg("-Wno-redundant")
+        .arg("-Werror")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[-Werror=unused-macro]"));
+    assert!(!stderr.contains("redundant"));
+    assert!(stderr.contains("[-Werror=jmp-indirect-bug]"));
+    assert!(stderr.contains("[-Werror=empty-section]"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn indentation() {
+    let test_name = "indentation";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        "start\n    nop\n\tnop\n\n",
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("--indent")
+        .arg("spaces")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("Line is indented with tabs").count(), 1);
+    assert!(stderr.contains(&format!("test_input/{}.65a:3:1", test_name)));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // It's off by default.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+
+    cleanup(test_name);
+}
+
+#[test]
+fn debug_bundle() {
+    let test_name = "debug_bundle";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $1000
+            * Loop forever.
+            !start
+                nop
+                jmp start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--debug-bundle")
+        .arg(format!("test_input/{}.json", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let bundle = fs::read_to_string(format!("test_input/{}.json", test_name)).unwrap();
+    assert!(bundle.contains(r#"{"name": "start", "address": 4096, "global": true}"#));
+    assert!(bundle.contains(&format!(
+        r#"{{"address": 4097, "size": 3, "file": "test_input/{}.65a", "line": 5}}"#,
+        test_name
+    )));
+    assert!(bundle.contains(r#"{"name": "default", "base": 4096, "size": 4}"#));
+
+    let _ = fs::remove_file(format!("test_input/{}.json", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn listing_header() {
+    let test_name = "listing_header";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    for deterministic in [false, true] {
+        let mut command = test_bin::get_test_bin("s502-as");
+        command.arg("-b").arg("-l");
+        if deterministic {
+            command.arg("--deterministic");
+        }
+        let output = command
+            .arg(format!("test_input/{}.65a", test_name))
+            .output()
+            .expect("failed to start s502-as");
+
+        assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+        assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+        let listing = fs::read_to_string(format!("test_input/{}_listing.txt", test_name)).unwrap();
+        assert!(listing.starts_with(&format!(
+            "              * Source: test_input/{}.65a\n",
+            test_name
+        )));
+        assert!(listing.contains("              * CPU: 6502\n"));
+        assert_eq!(listing.contains("* Date: "), !deterministic);
+    }
+
+    cleanup(test_name);
+}
+
+#[test]
+fn listing_code_only() {
+    let test_name = "listing_code_only";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            * The screen.
+            screen equ $0400
+
+                sta screen
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg("--listing-code-only")
+        .arg("--deterministic")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let listing = fs::read_to_string(format!("test_input/{}_listing.txt", test_name)).unwrap();
+    assert!(listing.contains("0000 8d 00 04     sta screen"));
+    assert!(!listing.contains("equ"));
+    assert!(!listing.contains("The screen"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn manifest() {
+    let test_name = "manifest";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $1000
+            start
+                nop
+                jmp start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--manifest")
+        .arg(format!("test_input/{}.json", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let binary = fs::read(format!("test_input/{}.bin", test_name)).unwrap();
+    let manifest = fs::read_to_string(format!("test_input/{}.json", test_name)).unwrap();
+    assert_eq!(binary.len(), 4);
+    assert!(manifest
+        .contains("{\"output\": \"test_input/manifest.bin\", \"format\": \"bin\", \"size\": 4, "));
+    assert!(manifest.contains("{\"name\": \"default\", \"base\": 4096, \"size\": 4}"));
+
+    // Each file that's written gets an entry with its own format and size.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--format")
+        .arg("bin")
+        .arg("--format")
+        .arg("rle")
+        .arg("--manifest")
+        .arg(format!("test_input/{}.json", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+
+    let rle = fs::read(format!("test_input/{}.rle", test_name)).unwrap();
+    let manifest = fs::read_to_string(format!("test_input/{}.json", test_name)).unwrap();
+    assert!(manifest
+        .contains("{\"output\": \"test_input/manifest.bin\", \"format\": \"bin\", \"size\": 4, "));
+    assert!(manifest.contains(&format!(
+        "{{\"output\": \"test_input/manifest.rle\", \"format\": \"rle\", \"size\": {}, ",
+        rle.len()
+    )));
+    assert_ne!(rle.len(), binary.len());
+
+    let _ = fs::remove_file(format!("test_input/{}.json", test_name));
+    let _ = fs::remove_file(format!("test_input/{}.rle", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn coverage() {
+    let test_name = "coverage";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $1000
+            start
+                nop
+                jmp start
+                dfw start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--coverage")
+        .arg(format!("test_input/{}.txt", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read_to_string(format!("test_input/{}.txt", test_name)).unwrap(),
+        "default $1000-$1005\n"
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}.txt", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn data_file() {
+    let test_name = "data_file";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            inb \"test_input/{}.txt\"
+            nop
+            ", test_name
+        },
+    )
+    .is_ok());
+
+    assert!(fs::write(
+        format!("test_input/{}.txt", test_name),
+        indoc::formatdoc! {
+            "
+            1, %10
+            $ff
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0x01, 0x02, 0xff, 0xea]
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}.txt", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn data_file_error() {
+    let test_name = "data_file_error";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            inb \"test_input/{}.txt\"
+            ", test_name
+        },
+    )
+    .is_ok());
+
+    assert!(fs::write(format!("test_input/{}.txt", test_name), "1 $100 3\n").is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Value `$0100` in data file does not fit in a byte"));
+    assert!(stderr.contains(&format!("test_input/{}.txt:1:3", test_name)));
+
+    let _ = fs::remove_file(format!("test_input/{}.txt", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn unused_macro() {
+    let test_name = "unused_macro";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            used equ $10
+            unused equ $20
+                lda used
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-Wunused-macro")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Macro `unused` is defined but never used [-Wunused-macro]"));
+    assert!(!stderr.contains("Macro `used`"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn section_size() {
+    let test_name = "section_size";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                    sct \"code\"
+                start
+                    ldx #0
+                    jmp start
+
+                    sct \"data\"
+                    dfw sizeof(\"code\")
+            "
+        },
+    )
+    .is_ok());
+
+    assert!(fs::write(
+        format!("test_input/{}.cfg", test_name),
+        indoc::formatdoc! {
+            "
+            \"code\" $1000
+            \"data\" $1005
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--script")
+        .arg(format!("test_input/{}.cfg", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa2, 0x00, 0x4c, 0x00, 0x10, 0x05, 0x00]
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}.cfg", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn listing_canon_numbers() {
+    let test_name = "listing_canon_numbers";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                lda #10
+                sta %1010
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg("--listing-canon-numbers")
+        .arg("--deterministic")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let listing = fs::read_to_string(format!("test_input/{}_listing.txt", test_name)).unwrap();
+    assert!(listing.contains("0000 a9 0a    lda #$0a"));
+    assert!(listing.contains("0002 85 0a    sta $0a"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn object_file() {
+    let test_name = "object_file";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sct \"code\"
+            start
+                jmp start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-o")
+        .arg(format!("test_input/{}", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let object = fs::read(format!("test_input/{}.65o", test_name)).unwrap();
+    assert!(object.starts_with(b"s5o\x09"));
+    // There's no entry point.
+    assert!(object.ends_with(&[0]));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn entry() {
+    let test_name = "entry";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                entry start
+                sct \"code\"
+            start
+                jmp start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-o")
+        .arg(format!("test_input/{}", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // The object ends with the entry point's label.
+    let object = fs::read(format!("test_input/{}.65o", test_name)).unwrap();
+    assert!(object.ends_with(b"\x01\x05\x00start"));
+
+    // Only one entry point may be given.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                entry start
+            start
+                nop
+            other
+                entry other
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-o")
+        .arg(format!("test_input/{}", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("The entry point is given more than once"));
+    assert!(stderr.contains("First given here"));
+
+    // It has to be a label in the program.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                entry missing
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-o")
+        .arg(format!("test_input/{}", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("The entry point `missing` isn't a label in this program"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn reserve_bytes() {
+    let test_name = "reserve_bytes";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $1000
+                ds 16
+            after
+                dfw after
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let mut expected = vec![0; 16];
+    expected.extend_from_slice(&[0x10, 0x10]);
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        expected
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn past_memory_end() {
+    let test_name = "past_memory_end";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $fffe
+                dfw $1234
+                dfb 1
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    // The word fills the last two bytes, so only the byte after it doesn't fit.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Assembling at $10000 goes past the end of memory"));
+    assert!(stderr.contains("Only 0 bytes fit before the end of memory"));
+    assert_eq!(stderr.matches("goes past the end of memory").count(), 1);
+    assert!(!output.status.success());
+    assert!(fs::metadata(format!("test_input/{}.bin", test_name)).is_err());
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $ffff
+                lda $1234
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Assembling at $ffff goes past the end of memory"));
+    assert!(stderr.contains("Only 1 byte fits before the end of memory"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn align() {
+    let test_name = "align";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                dfb 1
+                align 256
+            table
+                dfw table
+                align 4, $ea
+                dfb 2
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    let mut expected = vec![1];
+    expected.resize(0x100, 0);
+    expected.extend_from_slice(&[0x00, 0x01, 0xea, 0xea, 0x02]);
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        expected
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    // Any boundary is allowed unless it has to be a power of two.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                align 100
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--strict-align")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Can't align to a boundary of 100 bytes")
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn dfs() {
+    let test_name = "dfs";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                dfs 4, $ea
+                dfs 2
+                dfb 1
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xea, 0xea, 0xea, 0xea, 0x00, 0x00, 0x01]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    // Filling can't go past the end of memory.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $fff0
+                dfs $20, $ff
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Filling 32 bytes at $fff0 goes past the end of memory"));
+    assert!(stderr.contains("Only 16 bytes can be filled before the end of memory"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn fillbyte() {
+    let test_name = "fillbyte";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                dfb 1
+                fillbyte $ea
+                align 4
+                dfs 2
+                dfs 1, $ff
+                fillbyte $00
+                align 4
+                dfb 2
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0x01, 0xea, 0xea, 0xea, 0xea, 0xea, 0xff, 0x00, 0x02]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    // The fill has to be a byte.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                fillbyte $1234
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("The `fillbyte` directive expects a byte")
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn named_padding() {
+    let test_name = "named_padding";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0300
+            start
+                dfb 1
+                align 256, padding
+                dfs 2, $ea, gap
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-s")
+        .arg("--symbol-format")
+        .arg("mesen")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // The names mark where the padding starts, before any bytes were inserted.
+    assert_eq!(
+        fs::read_to_string(format!("test_input/{}.mlb", test_name)).unwrap(),
+        "P:0000:start\nP:0001:padding\nP:0100:gap"
+    );
+    let _ = fs::remove_file(format!("test_input/{}.mlb", test_name));
+
+    // A name can't stand in for the fill byte when one is given.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                align 256, $ea, $01
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Expected a name for the padding after `,`"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn conditional() {
+    let test_name = "conditional";
+
+    // The byte between the blocks depends on the flag.
+    for (debug, expected) in [(1, 0x01), (0, 0x02)] {
+        assert!(fs::write(
+            format!("test_input/{}.65a", test_name),
+            indoc::formatdoc! {
+                "
+                DEBUG equ {debug}
+                    if DEBUG
+                    dfb $01
+                    else
+                    dfb $02
+                    endif
+                    if DEBUG
+                    if 0
+                    dfb $ff
+                    endif
+                    dfb $03
+                    endif
+                ",
+                debug = debug,
+            },
+        )
+        .is_ok());
+
+        let output = test_bin::get_test_bin("s502-as")
+            .arg("-b")
+            .arg(format!("test_input/{}.65a", test_name))
+            .output()
+            .expect("failed to start s502-as");
+
+        assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+        assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+        let mut bytes = vec![expected];
+        if debug == 1 {
+            bytes.push(0x03);
+        }
+        assert_eq!(
+            fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+            bytes
+        );
+    }
+
+    // A block that isn't ended points at its `if`.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                nop
+                if 1
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Expected `endif` to end the `if` block"));
+    assert!(stderr.contains(&format!("{}.65a:2:1", test_name)));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn data_lists() {
+    let test_name = "data_lists";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0300
+            start
+                dfb $01, $02, \"hi\", >after, <after
+            after
+                dfw start, $1234, after
+                dfb 1
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // The list's 6 bytes come before `after`, and a single value is unchanged.
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0x01, 0x02, 0xc8, 0xc9, 0x06, 0x03, 0x00, 0x03, 0x34, 0x12, 0x06, 0x03, 0x01]
+    );
+
+    // Each line lists every byte it defined.
+    let listing = fs::read_to_string(format!("test_input/{}_listing.txt", test_name)).unwrap();
+    assert!(listing
+        .contains("0300 01 02 c8     dfb $01, $02, \"hi\", >after, <after\n0303 c9 06 03 \n"));
+    assert!(listing.contains("0306 00 03 34     dfw start, $1234, after\n0309 12 06 03 \n"));
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                dfb 1,
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Expected a value after `,`"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn data_repeats() {
+    let test_name = "data_repeats";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                dfb $ff * 3, $00
+                dfw $1234 * 2
+                dfb (2*3) * 2, 1 * one more
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // `*` in parentheses still multiplies, and one that isn't followed by a lone
+    // number starts a comment.
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xff, 0xff, 0xff, 0x00, 0x34, 0x12, 0x34, 0x12, 0x06, 0x06, 0x01]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                dfb $ff*0
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Expected a repeat count after `*`"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn output_directory() {
+    let test_name = "output_directory";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                nop
+            "
+        },
+    )
+    .is_ok());
+    let _ = fs::create_dir(format!("test_input/{}_dir", test_name));
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-o")
+        .arg(format!("test_input/{}_dir", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+        "Output path `test_input/{}_dir` is a directory",
+        test_name
+    )));
+    // Nothing was written next to or inside the directory.
+    assert!(fs::read(format!("test_input/{}_dir.bin", test_name)).is_err());
+    assert!(fs::read(format!("test_input/{}_dir/.bin", test_name)).is_err());
+
+    let _ = fs::remove_dir(format!("test_input/{}_dir", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn listing_notes() {
+    let test_name = "listing_notes";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                jmp ($02ff)
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg("--listing-notes")
+        .arg("--deterministic")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    // The warning is still reported.
+    assert!(String::from_utf8_lossy(&output.stderr).contains("jmp-indirect-bug"));
+
+    let listing = fs::read_to_string(format!("test_input/{}_listing.txt", test_name)).unwrap();
+    assert!(listing.contains(indoc::indoc! {
+        "
+        0000 6c ff 02 jmp ($02ff)
+                      * Note: `jmp ($02ff)` reads the high byte of its target from `$0200` [-Wjmp-indirect-bug]
+        0003 ea       nop"
+    }));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn repeat() {
+    let test_name = "repeat";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                rept 3
+                dfb $ff
+                endr
+            * Each copy of the inner block counts from 0.
+                rept 2
+                rept 2
+                dfb \\@
+                endr
+                endr
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xff, 0xff, 0xff, 0x00, 0x01, 0x00, 0x01]
+    );
+
+    // Errors in the block point at its lines.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                rept 2
+                lda ($10)
+                endr
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Zeropage indirect address mode is invalid for instruction `lda`"));
+    assert!(stderr.contains(&format!("{}.65a:2:5", test_name)));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn many_sections() {
+    let source: String = (0..50)
+        .map(|index| format!("    sct \"section{}\"\n    dfb {}\n", index, index))
+        .collect();
+
+    let (object, _) = s502_as::assemble_source("many_sections.65a", &source, false).unwrap();
+
+    // Each section only holds the bytes put in it instead of all of memory.
+    assert_eq!(object.len(), 50);
+    for (index, section) in object.iter().enumerate() {
+        assert_eq!(section.name, format!("section{}", index));
+        assert_eq!(section.data, vec![index as u8]);
+    }
+}
+
+#[test]
+fn several_sources() {
+    let test_name = "several_sources";
+
+    for (index, source) in ["lda #$01", "ldx #$02", "ldy #$03"].iter().enumerate() {
+        assert!(fs::write(
+            format!("test_input/{}_{}.65a", test_name, index),
+            format!("{}\n", source),
+        )
+        .is_ok());
+    }
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .args((0..3).map(|index| format!("test_input/{}_{}.65a", test_name, index)))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    for (index, opcode) in [0xa9, 0xa2, 0xa0].iter().enumerate() {
+        assert_eq!(
+            fs::read(format!("test_input/{}_{}.bin", test_name, index)).unwrap(),
+            vec![*opcode, index as u8 + 1]
+        );
+    }
+
+    // One bad source fails the run without stopping the others.
+    assert!(fs::write(format!("test_input/{}_1.65a", test_name), "ldx #\n").is_ok());
+    let _ = fs::remove_file(format!("test_input/{}_0.bin", test_name));
+    let _ = fs::remove_file(format!("test_input/{}_2.bin", test_name));
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .args((0..3).map(|index| format!("test_input/{}_{}.65a", test_name, index)))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("{}_1.65a", test_name)));
+    assert!(fs::read(format!("test_input/{}_0.bin", test_name)).is_ok());
+    assert!(fs::read(format!("test_input/{}_2.bin", test_name)).is_ok());
+
+    // A source's warnings and errors are written together, not between another's.
+    for index in 0..3 {
+        assert!(fs::write(
+            format!("test_input/{}_{}.65a", test_name, index),
+            "    jmp ($10ff)\n    lda\n",
+        )
+        .is_ok());
+    }
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--message-format")
+        .arg("json")
+        .args((0..3).map(|index| format!("test_input/{}_{}.65a", test_name, index)))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines = stderr.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 6);
+    for pair in lines.chunks(2) {
+        assert!(pair[0].contains("\"severity\":\"warning\""));
+        assert!(pair[1].contains("\"severity\":\"error\""));
+        let file = &pair[0][pair[0].find("\"file\"").unwrap()..];
+        assert!(pair[1].contains(&file[..file.find(',').unwrap()]));
+    }
+
+    // The same source twice would write the same files at once.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}_0.65a", test_name))
+        .arg(format!("test_input/../test_input/{}_0.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is given more than once"));
+
+    cleanup("several_sources_0");
+    cleanup("several_sources_1");
+    cleanup("several_sources_2");
+}
+
+#[test]
+fn exit_status() {
+    let test_name = "exit_status";
+
+    for (index, source) in ["lda #", "lda #$01", "ldx #"].iter().enumerate() {
+        assert!(fs::write(
+            format!("test_input/{}_{}.65a", test_name, index),
+            format!("{}\n", source),
+        )
+        .is_ok());
+    }
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .args((0..3).map(|index| format!("test_input/{}_{}.65a", test_name, index)))
+        .output()
+        .expect("failed to start s502-as");
+
+    // Sources that fail to assemble exit with 1 and are counted at the end.
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).ends_with("2 files failed, 1 succeeded\n"));
+
+    // An invalid option exits with 2 before anything is assembled.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--org")
+        .arg("nowhere")
+        .arg(format!("test_input/{}_1.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("succeeded"));
+
+    cleanup("exit_status_0");
+    cleanup("exit_status_1");
+    cleanup("exit_status_2");
+}
+
+#[test]
+fn define() {
+    let test_name = "define";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                if DEBUG
+                lda #VERSION
+                endif
+                ldx SCREEN
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--define")
+        .arg("DEBUG=1")
+        .arg("--define")
+        .arg("VERSION=3")
+        .arg("--define")
+        .arg("SCREEN=$0400")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // A small value is a byte and a large one is a word.
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa9, 0x03, 0xae, 0x00, 0x04]
+    );
+
+    // Defining it again in the source points to the source line.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                lda #VERSION
+            VERSION equ 4
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--define")
+        .arg("VERSION=3")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("The macro `VERSION` has already been defined"));
+    assert!(stderr.contains(&format!("{}.65a:2:9", test_name)));
+    assert!(stderr.contains("`VERSION` was defined with --define"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn include_depth() {
+    let test_name = "include_depth";
+
+    // Each file includes the next, one more than the default limit deep.
+    for depth in 0..=65 {
+        assert!(fs::write(
+            format!("test_input/{}_{}.65a", test_name, depth),
+            format!("inl \"test_input/{}_{}.65a\"\n", test_name, depth + 1),
+        )
+        .is_ok());
+    }
+    assert!(fs::write(format!("test_input/{}_66.65a", test_name), "nop\n").is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}_0.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("Includes are nested more than 64 deep"));
+    assert!(stderr.contains(&format!("{}_64.65a:1:1", test_name)));
+
+    // With a higher limit, the chain is assembled.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--max-include-depth")
+        .arg("66")
+        .arg(format!("test_input/{}_0.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert_eq!(
+        fs::read(format!("test_input/{}_0.bin", test_name)).unwrap(),
+        vec![0xea]
+    );
+
+    for depth in 0..=66 {
+        let _ = fs::remove_file(format!("test_input/{}_{}.65a", test_name, depth));
+    }
+    let _ = fs::remove_file(format!("test_input/{}_0.bin", test_name));
+}
+
+#[test]
+fn message_format_json() {
+    let test_name = "message_format_json";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                jmp ($10ff)
+                lda
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--message-format")
+        .arg("json")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // Each diagnostic is one line, with newlines in the help escaped.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines = stderr.lines().collect::<Vec<&str>>();
+    assert_eq!(
+        lines,
+        vec![
+            format!(
+                "{{\"severity\":\"warning\",\"message\":\"`jmp ($10ff)` reads the high byte of its target from `$1000` [-Wjmp-indirect-bug]\",\"help\":\"The 6502 doesn't cross a page when reading an indirect address, so move the pointer so it doesn't end in $ff\",\"labels\":[{{\"file\":\"test_input/{}.65a\",\"start\":4,\"end\":11,\"label\":null}}]}}",
+                test_name
+            ),
+            format!(
+                "{{\"severity\":\"error\",\"message\":\"Implied address mode is invalid for instruction `lda`\",\"help\":\"Valid address modes are:\\n    Absolute\\n    Absolute, X-Indexed\\n    Absolute, Y-Indexed\\n    Immediate\\n    X-indexed, indirect\\n    Indirect, Y-indexed\\n    Zeropage\\n    Zeropage, X-indexed\",\"labels\":[{{\"file\":\"test_input/{}.65a\",\"start\":12,\"end\":15,\"label\":null}}]}}",
+                test_name
+            ),
+        ]
+    );
+
+    // Errors that aren't in a source are JSON too.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--message-format")
+        .arg("json")
+        .arg("--define")
+        .arg("DEBUG")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "{\"severity\":\"error\",\"message\":\"Invalid definition `DEBUG` given to --define, expected one like `DEBUG=1`\",\"help\":null,\"labels\":[]}\n"
+    );
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--message-format")
+        .arg("json")
+        .arg(format!("test_input/{}_missing.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with(&format!(
+        "{{\"severity\":\"error\",\"message\":\"Could not read test_input/{}_missing.65a: ",
+        test_name
+    )));
+    assert_eq!(stderr.lines().count(), 1);
+
+    cleanup(test_name);
+}
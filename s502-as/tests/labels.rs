@@ -1,4 +1,4 @@
-use std::fs;
+use std::{fs, path::Path};
 
 fn cleanup(test_name: &'static str) {
     let _ = fs::remove_file(format!("test_input/{}.65a", test_name));
@@ -51,6 +51,44 @@ fn parent() {
     cleanup(test_name);
 }
 
+#[test]
+fn trailing_comments() {
+    let test_name = "trailing_comments";
+
+    // A `*` after a complete operand starts a comment, even when a number follows.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0800 * load addr
+            start * where it begins
+                lda #1 * load one
+                sta $10 * store it
+                lda #5 * 2
+                dfb $ea * a nop
+            loop: jmp loop * forever
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa9, 0x01, 0x85, 0x10, 0xa9, 0x05, 0xea, 0x4c, 0x07, 0x08]
+    );
+
+    cleanup(test_name);
+}
+
 #[test]
 fn child() {
     let test_name = "child";
@@ -90,3 +128,1119 @@ fn child() {
 
     cleanup(test_name);
 }
+
+#[test]
+fn mesen_symbols() {
+    let test_name = "mesen_symbols";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $1000
+            !start
+                nop
+            .loop
+                jmp .loop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-s")
+        .arg("--symbol-format")
+        .arg("mesen")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read_to_string(format!("test_input/{}.mlb", test_name)).unwrap(),
+        "P:0000:start\nP:0001:start_loop"
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}.mlb", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn symbols_only() {
+    let test_name = "symbols_only";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $2000
+            !start
+                nop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--symbols-only")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read_to_string(format!("test_input/{}_symbols.65a", test_name)).unwrap(),
+        "start equ $2000"
+    );
+    assert!(!Path::new(&format!("test_input/{}.bin", test_name)).exists());
+
+    let _ = fs::remove_file(format!("test_input/{}_symbols.65a", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn object_symbols() {
+    let test_name = "object_symbols";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sct \"code\"
+                nop
+            local
+                nop
+            !start
+                rts
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-s")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // Only the global label is listed, with its offset into its section.
+    assert_eq!(
+        fs::read_to_string(format!("test_input/{}.65s", test_name)).unwrap(),
+        "start code $0002\n"
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}.65o", test_name));
+    let _ = fs::remove_file(format!("test_input/{}.65s", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn object_listing() {
+    let test_name = "object_listing";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sct \"code\"
+            start
+                lda #1
+                sct \"data\"
+            table
+                dfb 1, 2
+                sct \"code\"
+                rts
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-l")
+        .arg("--deterministic")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // Offsets are into each section, which is named whenever the code moves to it.
+    assert_eq!(
+        fs::read_to_string(format!("test_input/{}_listing.txt", test_name)).unwrap(),
+        indoc::formatdoc! {
+            "
+                          * Source: test_input/{}.65a
+                          * Assembler: s502-as {}
+                          * CPU: 6502
+
+                          * Section: code
+            0000              sct \"code\"
+            0000          start
+            0000 a9 01        lda #1
+                          * Section: data
+            0000              sct \"data\"
+            0000          table
+            0000 01 02        dfb 1, 2
+                          * Section: code
+            0002              sct \"code\"
+            0002 60           rts
+                          * Assembled 5 bytes",
+            test_name,
+            env!("CARGO_PKG_VERSION")
+        }
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}.65o", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn label_case() {
+    let test_name = "label_case";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            Start
+                dex
+                bne start
+            start
+                rts
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    // By default they're different labels, which is likely a mistake.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success());
+    assert!(stderr.contains("Labels `start` and `Start` only differ in case [-Wlabel-case]"));
+    assert!(stderr.contains(&format!("{}.65a:4:1", test_name)));
+    assert!(stderr.contains("`Start` is defined here"));
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xca, 0xd0, 0x00, 0x60]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn case_insensitive_labels() {
+    let test_name = "case_insensitive_labels";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            Count equ 3
+            !Start
+                ldx #COUNT
+            .Loop
+                dex
+                bne .loop
+                jmp START
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-s")
+        .arg("--case-sensitive-labels=false")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x4c, 0x00, 0x00]
+    );
+    // The names are written the same way whatever their case in the source.
+    let symbols = fs::read_to_string(format!("test_input/{}_symbols.65a", test_name)).unwrap();
+    assert!(symbols.contains("start"));
+    assert!(!symbols.contains("Start"));
+
+    let _ = fs::remove_file(format!("test_input/{}_symbols.65a", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn duplicate_label() {
+    let test_name = "duplicate_label";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            start
+                nop
+            start
+                jmp start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("The label `start` has already been defined"));
+    assert!(stderr.contains(&format!("{}.65a:3:1", test_name)));
+    assert!(stderr.contains("Already defined here"));
+
+    // A sublabel is only a duplicate under the same parent.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            first
+            .loop
+                dex
+                bne .loop
+            second
+            .loop
+                dey
+                bne .loop
+            .loop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("The label `second.loop` has already been defined"));
+    assert!(stderr.contains(&format!("{}.65a:9:1", test_name)));
+    assert!(!stderr.contains("first.loop"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn undefined_label() {
+    let test_name = "undefined_label";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            start
+                ldx cuont
+                jmp strt
+                lda later
+            count equ 3
+            later equ $10
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("Label `strt` being referenced does not exist"));
+    assert!(stderr.contains(&format!("{}.65a:3:9", test_name)));
+    assert!(stderr.contains("Did you mean `start`?"));
+    // A macro is suggested the same way, and one used above its definition is pointed out.
+    assert!(stderr.contains("Did you mean `count`?"));
+    assert!(stderr.contains("Define the macro `later` before it's used"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn same_sublabel_under_parents() {
+    let test_name = "same_sublabel_under_parents";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            first
+            .loop
+                dex
+                bne .loop
+            second
+            .loop
+                dey
+                bne .loop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xca, 0xd0, 0xfd, 0x88, 0xd0, 0xfd]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn multiple_labels() {
+    let test_name = "multiple_labels";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $1000
+                nop
+            alias1: alias2: nop
+                jmp alias1
+                jmp alias2
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xea, 0xea, 0x4c, 0x01, 0x10, 0x4c, 0x01, 0x10]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn label_address() {
+    let test_name = "label_address";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            !KBD = $c000
+            !start
+                lda KBD
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-s")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xad, 0x00, 0xc0]
+    );
+    assert_eq!(
+        fs::read_to_string(format!("test_input/{}_symbols.65a", test_name)).unwrap(),
+        "KBD equ $c000\nstart equ $0000"
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}_symbols.65a", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn position_independent() {
+    let test_name = "position_independent";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            start
+                bne start
+            table
+                dfw start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--pic")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Address table entry `start` is absolute in position independent code"));
+    assert!(stderr.contains("dispatch with a chain of comparisons and branches instead"));
+    assert_eq!(stderr.matches("error").count(), 1);
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    cleanup(test_name);
+}
+
+#[test]
+fn cross_reference() {
+    let test_name = "cross_reference";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            start
+                nop
+                jmp start
+                jmp start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--xref")
+        .arg(format!("test_input/{}.xref", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read_to_string(format!("test_input/{}.xref", test_name)).unwrap(),
+        indoc::formatdoc! {
+            "
+            start
+                defined  test_input/{0}.65a:1
+                used     test_input/{0}.65a:3
+                used     test_input/{0}.65a:4
+            ", test_name
+        }
+    );
+
+    let _ = fs::remove_file(format!("test_input/{}.xref", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn sublabel_after_include() {
+    let test_name = "sublabel_after_include";
+
+    assert!(fs::write(
+        format!("test_input/{}_0.65a", test_name),
+        indoc::formatdoc! {
+            "
+            main
+                inl \"test_input/{}_1.65a\"
+            .loop
+                jmp .loop
+            ", test_name
+        },
+    )
+    .is_ok());
+
+    // The included file's parent label doesn't carry over to the including file.
+    assert!(fs::write(
+        format!("test_input/{}_1.65a", test_name),
+        indoc::formatdoc! {
+            "
+            helper
+                rts
+            .loop
+                jmp .loop
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}_0.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}_0.bin", test_name)).unwrap(),
+        vec![0x60, 0x4c, 0x01, 0x00, 0x4c, 0x04, 0x00]
+    );
+
+    cleanup("sublabel_after_include_0");
+    cleanup("sublabel_after_include_1");
+}
+
+#[test]
+fn table_index() {
+    let test_name = "table_index";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            pointers equ $f0
+                lda table[3],x
+                lda pointers[2]
+                jmp table[$01]
+            table
+                dfb $10
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // The index is added to the label's address once it's known.
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xbd, 0x0b, 0x00, 0xa5, 0xf2, 0x4c, 0x09, 0x00, 0x10]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn equates() {
+    let test_name = "equates";
+
+    assert!(fs::write(
+        format!("test_input/{}.equ", test_name),
+        indoc::indoc! {
+            "
+            * Apple II soft switches.
+            KBD $c000
+            KBDSTRB $c010
+            "
+        },
+    )
+    .is_ok());
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0800
+                lda KBD
+                sta KBDSTRB
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-s")
+        .arg("--equates")
+        .arg(format!("test_input/{}.equ", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xad, 0x00, 0xc0, 0x8d, 0x10, 0xc0]
+    );
+
+    // They're labels, so they're in the symbol table unlike macros.
+    let symbols = fs::read_to_string(format!("test_input/{}_symbols.65a", test_name)).unwrap();
+    assert!(symbols.contains("KBD equ $c000"));
+    assert!(symbols.contains("KBDSTRB equ $c010"));
+
+    // Labels in an object are offsets into their section, which a fixed address isn't.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("--equates")
+        .arg(format!("test_input/{}.equ", test_name))
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Equates can only be given in binary mode"));
+    assert!(fs::metadata(format!("test_input/{}.65o", test_name)).is_err());
+
+    let _ = fs::remove_file(format!("test_input/{}.equ", test_name));
+    let _ = fs::remove_file(format!("test_input/{}_symbols.65a", test_name));
+    cleanup(test_name);
+}
+
+#[test]
+fn branch_out_of_range() {
+    let test_name = "branch_out_of_range";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            start
+                beq far
+                ds 200
+            far
+                rts
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Branch target `far` is out of range, at `200` bytes"));
+    assert!(stderr.contains(&format!("test_input/{}.65a:2:9", test_name)));
+    assert!(stderr.contains("Branch on the opposite condition over a `jmp far` instead"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+    assert!(!Path::new(&format!("test_input/{}.bin", test_name)).exists());
+
+    cleanup(test_name);
+}
+
+#[test]
+fn table_limits() {
+    let test_name = "table_limits";
+
+    // Generated code can define far more labels than anyone would write by hand.
+    let source = (0..100)
+        .map(|index| format!("label{}\n    nop\n", index))
+        .collect::<String>();
+    assert!(fs::write(format!("test_input/{}.65a", test_name), source).is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--max-labels")
+        .arg("50")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Can't define more than 50 labels in section `default`"));
+    assert!(stderr.contains(&format!("test_input/{}.65a:101:1", test_name)));
+    assert!(fs::metadata(format!("test_input/{}.bin", test_name)).is_err());
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            one equ 1
+            two equ 2
+            three equ 3
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--max-macros")
+        .arg("2")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Can't define more than 2 macros"));
+    assert!(stderr.contains(&format!("test_input/{}.65a:3:7", test_name)));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn expressions() {
+    let test_name = "expressions";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            base equ $10
+                org $0300
+            start
+                lda #(1+2*3)
+                lda base+1
+                lda start+2
+                dfb 10/3, 7-2-1
+                dfw end-start
+            end
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // A macro of a byte stays zeropage, and the distance is taken once `end` is known.
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa9, 0x07, 0xa5, 0x11, 0xad, 0x02, 0x03, 0x03, 0x04, 0x0b, 0x00]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    // A distance is a word whether the labels come before or after it.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0300
+                lda before_end-before
+                lda #before_end-before
+                dfw before_end-before
+            before
+                nop
+            before_end
+                lda before_end-before
+                lda #before_end-before
+                dfw before_end-before
+                dfb >(before_end-before)
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![
+            0xad, 0x01, 0x00, 0xa9, 0x01, 0x01, 0x00, 0xea, 0xad, 0x01, 0x00, 0xa9, 0x01, 0x01,
+            0x00, 0x01
+        ]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    // `*` multiplies right after a value or inside parentheses, and `-` negates.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                lda #2*3
+                lda #(1 + 2)*3
+                lda #(1 + 2 * 3)
+                lda #-1
+                dfb 7 - 2, -2
+              * a comment
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa9, 0x06, 0xa9, 0x09, 0xa9, 0x07, 0xa9, 0xff, 0x05, 0xfe]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    // In an object, the number added to a label is kept with the reference to it.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sct \"code\"
+            start
+                jmp start+2
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+
+    let object = fs::read(format!("test_input/{}.65o", test_name)).unwrap();
+    let reference = b"\x05\x00start\x01\x00\x00\x00\x02\x00";
+    assert!(object
+        .windows(reference.len())
+        .any(|window| window == reference));
+    let _ = fs::remove_file(format!("test_input/{}.65o", test_name));
+
+    // The linker places sections on their own, so a distance can't cross them.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sct \"code\"
+            start
+                nop
+            end
+                dfw end-start
+                sct \"data\"
+            table
+                dfw table_end-table
+                dfw end-table
+            table_end
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert_eq!(stderr.matches("aren't in the same section").count(), 1);
+    assert!(stderr.contains("`end` and `table` aren't in the same section"));
+    assert!(stderr.contains(&format!("test_input/{}.65a:9:9", test_name)));
+    let _ = fs::remove_file(format!("test_input/{}.65o", test_name));
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                lda #(4/(2-2))
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Division by zero"));
+    assert!(stderr.contains(&format!("test_input/{}.65a:1:9", test_name)));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn immediate_address() {
+    let test_name = "immediate_address";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $0400
+            screen
+                lda #screen
+                lda #>screen
+                lda screen
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    // Only the unmodified immediate reference is suspicious.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("[-Wimmediate-address]").count(), 1);
+    assert!(stderr.contains("The address of `screen` is loaded as an immediate byte"));
+    assert!(stderr.contains(
+        "Use `#>screen` or `#<screen` to load the low or high byte of the address, \
+        or `screen` without `#` to load from the address"
+    ));
+
+    // And it takes the low byte so the instruction keeps its size.
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa9, 0x00, 0xa9, 0x00, 0xad, 0x00, 0x04]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn bitwise_expressions() {
+    let test_name = "bitwise_expressions";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                lda #($ff & $0f)
+                lda #(1 << 4)
+                lda #(~0)
+                lda #(1 | 2 ^ 3 & 2)
+                dfw $0001 << 16, $8000 >> 15
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // `&` is applied before `^`, which is applied before `|`, and shifting every
+    // bit out of a word leaves zero.
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa9, 0x0f, 0xa9, 0x10, 0xa9, 0xff, 0xa9, 0x01, 0x00, 0x00, 0x01, 0x00]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    // An immediate value that doesn't fit in a byte keeps its low byte with a warning.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                lda #(1<<8)
+                lda #(~$00ff)
+                lda #>($1234+1)
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("The expression's value `256` does not fit in a byte [-Woverflow]"));
+    assert!(stderr.contains("The expression's value `-256` does not fit in a byte [-Woverflow]"));
+    assert!(stderr.contains("Only the low byte `$00` is used"));
+    assert_eq!(stderr.matches("[-Woverflow]").count(), 2);
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa9, 0x00, 0xa9, 0x00, 0xa9, 0x35]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn sublabel_before_parent() {
+    let test_name = "sublabel_before_parent";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            .loop
+                nop
+            main
+                rts
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    // The parent label written below the sublabel is pointed out.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No parent label to put sublabel under"));
+    assert!(stderr.contains("Parent label defined after the sublabel"));
+    assert!(stderr.contains("Did you mean to put `main` above `.loop`?"));
+    assert!(!stderr.contains("Did you mean to create a parent label"));
+
+    let _ = fs::remove_file(format!("test_input/{}.65o", test_name));
+    cleanup(test_name);
+}
@@ -14,13 +14,13 @@ fn high() {
         format!("test_input/{}.65a", test_name),
         indoc::formatdoc! {
             "
-            adc #<$1
-            adc <$2
-            adc <$3,x
+            adc #<$0100
+            adc <$0200
+            adc <$0300,x
             adc <$100
             adc <$200,x
-            adc (<$4,x)
-            adc (<$5),y
+            adc (<$0400,x)
+            adc (<$0500),y
             "
         },
     )
@@ -52,13 +52,13 @@ fn low() {
         format!("test_input/{}.65a", test_name),
         indoc::formatdoc! {
             "
-            adc #>$1
-            adc >$2
-            adc >$3,x
+            adc #>$0001
+            adc >$0002
+            adc >$0003,x
             adc >$120
             adc >$230,x
-            adc (>$4,x)
-            adc (>$5),y
+            adc (>$0004,x)
+            adc (>$0005),y
             "
         },
     )
@@ -81,3 +81,192 @@ fn low() {
 
     cleanup(test_name);
 }
+
+#[test]
+fn label() {
+    let test_name = "label";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            before equ $1234
+
+            lda #<before
+            lda >before,x
+            lda #<after
+            lda >after
+            lda <after,x
+            lda (>after),y
+            org $5678
+            after
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    // A label that isn't defined yet is still a byte, so the address is in the zeropage.
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap()[..12],
+        [0xa9, 0x12, 0xb5, 0x34, 0xa9, 0x56, 0xa5, 0x78, 0xb5, 0x56, 0xb1, 0x78]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn byte_value() {
+    let test_name = "byte_value";
+
+    for (source, column, help) in [
+        (
+            "lda #<$12",
+            7,
+            "Write it as a word, like `$0012`, to take one of its bytes",
+        ),
+        ("lda >$12,x", 6, "Write it as a word, like `$0012`"),
+        (
+            "zp equ $10\nlda <zp",
+            6,
+            "Leave out the modifier to use the byte",
+        ),
+    ] {
+        assert!(fs::write(
+            format!("test_input/{}.65a", test_name),
+            format!("{}\n", source)
+        )
+        .is_ok());
+
+        let output = test_bin::get_test_bin("s502-as")
+            .arg("-b")
+            .arg(format!("test_input/{}.65a", test_name))
+            .output()
+            .expect("failed to start s502-as");
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(stderr.contains("modifier requires a word"));
+        assert!(stderr.contains(&format!(
+            "{}.65a:{}:{}",
+            test_name,
+            source.lines().count(),
+            column
+        )));
+        assert!(stderr.contains(help));
+    }
+
+    cleanup(test_name);
+}
+
+#[test]
+fn coerce() {
+    let test_name = "coerce";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            zp equ $10
+            big equ $0020
+
+            lda word(zp)
+            lda byte(big)
+            lda byte($0030),x
+            lda Word($40),y
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xad, 0x10, 0x00, 0xa5, 0x20, 0xb5, 0x30, 0xb9, 0x40, 0x00]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn width_suffix() {
+    let test_name = "width_suffix";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            big equ $0020
+
+            lda $10.w
+            lda big.b
+            lda $0030.B,x
+            lda #<$1234.w
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("-l")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xad, 0x10, 0x00, 0xa5, 0x20, 0xb5, 0x30, 0xa9, 0x12]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn coerce_too_big() {
+    let test_name = "coerce_too_big";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            big equ $1234
+
+            lda byte(big)
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Value `$1234` does not fit in a byte")
+    );
+
+    cleanup(test_name);
+}
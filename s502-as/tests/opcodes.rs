@@ -932,8 +932,11 @@ fn jmp() {
         format!("test_input/{}.65a", test_name),
         indoc::formatdoc! {
             "
+            pointer equ $20
             jmp $100
             jmp ($200)
+            jmp ($10)
+            jmp (pointer)
             "
         },
     )
@@ -951,7 +954,41 @@ fn jmp() {
 
     assert_eq!(
         fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
-        vec![0x4c, 0x00, 0x01, 0x6c, 0x00, 0x02]
+        vec![0x4c, 0x00, 0x01, 0x6c, 0x00, 0x02, 0x6c, 0x10, 0x00, 0x6c, 0x20, 0x00]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn jmp_indirect_page_boundary() {
+    let test_name = "jmp_indirect_page_boundary";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            jmp ($10ff)
+            jmp ($1100)
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`jmp ($10ff)` reads the high byte of its target from `$1000`"));
+    assert!(!stderr.contains("$1100"));
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0x6c, 0xff, 0x10, 0x6c, 0x00, 0x11]
     );
 
     cleanup(test_name);
@@ -1915,3 +1952,447 @@ fn tya() {
 
     cleanup(test_name);
 }
+
+#[test]
+fn invalid_mode_suggestion() {
+    let test_name = "invalid_mode_suggestion";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            stx $1234,y
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Absolute, Y-Indexed address mode is invalid for instruction `stx`"));
+    assert!(stderr.contains("Did you mean `sta`?"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn implied_operand() {
+    let test_name = "implied_operand";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                inx 5
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`inx` takes no operand"));
+    assert!(stderr.contains(&format!("{}.65a:1:5", test_name)));
+    assert!(stderr.contains("Unexpected operand"));
+    assert!(stderr.contains("Did you mean `inc`?"));
+
+    // A label or a parenthesized address isn't taken as a comment either.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            loop
+                inx loop
+                dex ($10)
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`inx` takes no operand"));
+    assert!(stderr.contains(&format!("{}.65a:2:9", test_name)));
+    assert!(stderr.contains("`dex` takes no operand"));
+    assert!(stderr.contains(&format!("{}.65a:3:9", test_name)));
+
+    // Neither is a comma, which could only be part of an operand.
+    assert!(fs::write(format!("test_input/{}.65a", test_name), "    nop , 5\n").is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`nop` takes no operand"));
+    assert!(stderr.contains(&format!("{}.65a:1:9", test_name)));
+    assert!(stderr.contains("Unexpected `,`"));
+
+    // Without the operand it assembles, and a comment can still follow.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        "    inx * next entry\n"
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xe8]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn redundant_transfer() {
+    let test_name = "redundant_transfer";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                tax
+                txa
+                lda $10
+                sta $10
+            loop
+                sta $20
+                tay
+            next
+                tya
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--lint")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`txa` undoes the `tax` before it"));
+    assert!(stderr.contains("`sta` stores the value that was just loaded from the same address"));
+    assert!(!stderr.contains("`tya` undoes"));
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xaa, 0x8a, 0xa5, 0x10, 0x85, 0x10, 0x85, 0x20, 0xa8, 0x98]
+    );
+
+    cleanup(test_name);
+}
+
+#[test]
+fn expression() {
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("--expr")
+        .arg("nop")
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "EA\n");
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("--expr")
+        .arg("lda #$05")
+        .output()
+        .expect("failed to start s502-as");
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "A9 05\n");
+}
+
+#[test]
+fn accumulator_mode() {
+    let test_name = "accumulator_mode";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                asl a
+                asl
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--accumulator")
+        .arg("implicit")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0x0a, 0x0a]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--accumulator")
+        .arg("explicit")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    // Only the line without `a` is an error.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("address mode is invalid for instruction `asl`"));
+    assert!(stderr.contains(&format!("{}.65a:2:1", test_name)));
+    assert_eq!(stderr.matches("error").count(), 1);
+
+    cleanup(test_name);
+}
+
+#[test]
+fn program_counter() {
+    let test_name = "program_counter";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                org $1000
+            loop: bne loop
+                bne $
+            here: jmp here
+                jmp $
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xd0, 0xfe, 0xd0, 0xfe, 0x4c, 0x04, 0x10, 0x4c, 0x07, 0x10]
+    );
+
+    // In an object, it's the start of the section plus the offset into it.
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                sct \"code\"
+                nop
+                jmp $+1
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+
+    let object = fs::read(format!("test_input/{}.65o", test_name)).unwrap();
+    let reference = b"\x04\x00code\x02\x00\x00\x00\x02\x00\x00\x08";
+    assert!(object
+        .windows(reference.len())
+        .any(|window| window == reference));
+    let _ = fs::remove_file(format!("test_input/{}.65o", test_name));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn opcode_directive() {
+    let test_name = "opcode_directive";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                opcode lda immediate
+                opcode bne relative
+                opcode jmp Indirect
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0xa9, 0xd0, 0x6c]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                opcode sta immediate
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Immediate address mode is invalid for instruction `sta`"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn cpu_65c02() {
+    let test_name = "cpu_65c02";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+                bra done
+                phx
+                phy
+                plx
+                ply
+            done
+                stz $10
+                stz $10,x
+                stz $1234
+                stz $1234,x
+                lda ($10)
+                bit #$01
+                jmp ($10ff)
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--cpu")
+        .arg("65c02")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    // The 65C02 doesn't have the `jmp` indirect bug to warn about.
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![
+            0x80, 0x04, 0xda, 0x5a, 0xfa, 0x7a, 0x64, 0x10, 0x74, 0x10, 0x9c, 0x34, 0x12, 0x9e,
+            0x34, 0x12, 0xb2, 0x10, 0x89, 0x01, 0x6c, 0xff, 0x10
+        ]
+    );
+    let _ = fs::remove_file(format!("test_input/{}.bin", test_name));
+
+    // The default CPU is the NMOS 6502, which doesn't have them.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Instruction `bra` is not available for the 6502"));
+    assert!(stderr.contains(&format!("{}.65a:1:5", test_name)));
+    assert!(stderr.contains("Zeropage indirect address mode is invalid for instruction `lda`"));
+
+    cleanup(test_name);
+}
+
+#[test]
+fn cpu_pragma() {
+    let test_name = "cpu_pragma";
+
+    assert!(fs::write(
+        format!("test_input/{}.65a", test_name),
+        indoc::formatdoc! {
+            "
+            *pragma cpu 65c02
+            *pragma org $2000
+            start
+                bra start
+            "
+        },
+    )
+    .is_ok());
+
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+
+    assert_eq!(
+        fs::read(format!("test_input/{}.bin", test_name)).unwrap(),
+        vec![0x80, 0xfe]
+    );
+
+    // An option on the command line takes precedence over the pragma.
+    let output = test_bin::get_test_bin("s502-as")
+        .arg("-b")
+        .arg("--cpu")
+        .arg("6502")
+        .arg(format!("test_input/{}.65a", test_name))
+        .output()
+        .expect("failed to start s502-as");
+
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Instruction `bra` is not available for the 6502"));
+
+    cleanup(test_name);
+}